@@ -0,0 +1,21 @@
+-- Cart version rows and cart lines. Guest carts use their cart id as the user id.
+CREATE TABLE carts (
+    user_id BINARY(16) PRIMARY KEY,
+    version BIGINT UNSIGNED NOT NULL DEFAULT 0,
+    last_activity_at DATETIME(6) NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'active',
+    abandoned_at DATETIME(6) NULL,
+    currency CHAR(3) NULL,
+    INDEX carts_status_activity_idx (status, last_activity_at)
+);
+
+CREATE TABLE cart_items (
+    id BINARY(16) PRIMARY KEY,
+    user_id BINARY(16) NOT NULL,
+    product_id BINARY(16) NOT NULL,
+    options JSON NULL,
+    options_key VARCHAR(64) NOT NULL DEFAULT '',
+    quantity INT UNSIGNED NOT NULL,
+    updated_at DATETIME(6) NOT NULL,
+    UNIQUE KEY cart_items_line_key (user_id, product_id, options_key)
+);
@@ -0,0 +1,3 @@
+DROP TABLE inventory_reservations;
+DROP TABLE order_lines;
+DROP TABLE orders;
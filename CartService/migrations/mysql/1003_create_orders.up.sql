@@ -0,0 +1,44 @@
+CREATE TABLE orders (
+    id BINARY(16) PRIMARY KEY,
+    user_id BINARY(16) NOT NULL,
+    status VARCHAR(32) NOT NULL,
+    currency CHAR(3) NOT NULL,
+    shipping JSON NOT NULL,
+    discounts JSON NOT NULL,
+    subtotal DECIMAL(19, 4) NOT NULL,
+    discount_total DECIMAL(19, 4) NOT NULL,
+    tax_total DECIMAL(19, 4) NOT NULL,
+    tax_breakdown JSON NULL,
+    shipping_total DECIMAL(19, 4) NOT NULL,
+    grand_total DECIMAL(19, 4) NOT NULL,
+    created_at DATETIME(6) NOT NULL,
+    updated_at DATETIME(6) NOT NULL,
+    INDEX orders_user_idx (user_id, created_at)
+);
+
+CREATE TABLE order_lines (
+    id BINARY(16) PRIMARY KEY,
+    order_id BINARY(16) NOT NULL,
+    product_id BINARY(16) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    options JSON NULL,
+    quantity INT UNSIGNED NOT NULL,
+    unit_price DECIMAL(19, 4) NOT NULL,
+    line_total DECIMAL(19, 4) NOT NULL,
+    INDEX order_lines_product_idx (product_id),
+    CONSTRAINT order_lines_order_fk FOREIGN KEY (order_id) REFERENCES orders (id) ON DELETE CASCADE
+);
+
+CREATE TABLE inventory_reservations (
+    id BINARY(16) PRIMARY KEY,
+    order_id BINARY(16) NOT NULL,
+    user_id BINARY(16) NOT NULL,
+    product_id BINARY(16) NOT NULL,
+    quantity INT UNSIGNED NOT NULL,
+    status VARCHAR(16) NOT NULL,
+    created_at DATETIME(6) NOT NULL,
+    expires_at DATETIME(6) NOT NULL,
+    INDEX inventory_reservations_product_idx (product_id, status, expires_at),
+    INDEX inventory_reservations_expiry_idx (status, expires_at),
+    CONSTRAINT inventory_reservations_order_fk FOREIGN KEY (order_id) REFERENCES orders (id) ON DELETE CASCADE
+);
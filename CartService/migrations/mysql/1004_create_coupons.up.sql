@@ -0,0 +1,29 @@
+CREATE TABLE coupons (
+    code VARCHAR(64) PRIMARY KEY,
+    rules JSON NOT NULL,
+    min_spend DECIMAL(19, 4) NULL,
+    category VARCHAR(255) NULL,
+    max_uses INT UNSIGNED NULL,
+    max_uses_per_user INT UNSIGNED NULL,
+    valid_from DATETIME(6) NULL,
+    valid_until DATETIME(6) NULL
+);
+
+CREATE TABLE cart_coupons (
+    user_id BINARY(16) NOT NULL,
+    code VARCHAR(64) NOT NULL,
+    applied_at DATETIME(6) NOT NULL,
+    PRIMARY KEY (user_id, code),
+    CONSTRAINT cart_coupons_code_fk FOREIGN KEY (code) REFERENCES coupons (code) ON DELETE CASCADE
+);
+
+CREATE TABLE coupon_redemptions (
+    code VARCHAR(64) NOT NULL,
+    user_id BINARY(16) NOT NULL,
+    order_id BINARY(16) NOT NULL,
+    redeemed_at DATETIME(6) NOT NULL,
+    PRIMARY KEY (order_id, code),
+    INDEX coupon_redemptions_code_idx (code, user_id),
+    CONSTRAINT coupon_redemptions_code_fk FOREIGN KEY (code) REFERENCES coupons (code),
+    CONSTRAINT coupon_redemptions_order_fk FOREIGN KEY (order_id) REFERENCES orders (id) ON DELETE CASCADE
+);
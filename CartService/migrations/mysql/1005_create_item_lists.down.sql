@@ -0,0 +1,2 @@
+DROP TABLE item_list_entries;
+DROP TABLE item_lists;
@@ -0,0 +1,20 @@
+CREATE TABLE item_lists (
+    id BINARY(16) PRIMARY KEY,
+    user_id BINARY(16) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    created_at DATETIME(6) NOT NULL,
+    INDEX item_lists_user_idx (user_id, kind)
+);
+
+CREATE TABLE item_list_entries (
+    id BINARY(16) PRIMARY KEY,
+    list_id BINARY(16) NOT NULL,
+    product_id BINARY(16) NOT NULL,
+    options JSON NULL,
+    options_key VARCHAR(64) NOT NULL DEFAULT '',
+    quantity INT UNSIGNED NOT NULL,
+    added_at DATETIME(6) NOT NULL,
+    UNIQUE KEY item_list_entries_line_key (list_id, product_id, options_key),
+    CONSTRAINT item_list_entries_list_fk FOREIGN KEY (list_id) REFERENCES item_lists (id) ON DELETE CASCADE
+);
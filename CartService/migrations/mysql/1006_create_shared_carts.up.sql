@@ -0,0 +1,8 @@
+CREATE TABLE shared_carts (
+    id BINARY(16) PRIMARY KEY,
+    user_id BINARY(16) NOT NULL,
+    items JSON NOT NULL,
+    currency CHAR(3) NOT NULL,
+    created_at DATETIME(6) NOT NULL,
+    expires_at DATETIME(6) NULL
+);
@@ -0,0 +1,21 @@
+-- sequence gives the order events are published in
+CREATE TABLE cart_outbox (
+    sequence BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    id BINARY(16) NOT NULL,
+    occurred_at DATETIME(6) NOT NULL,
+    event JSON NOT NULL,
+    published_at DATETIME(6) NULL,
+    UNIQUE KEY cart_outbox_id_key (id),
+    INDEX cart_outbox_unpublished_idx (published_at, sequence)
+);
+
+CREATE TABLE idempotency_keys (
+    key_hash CHAR(64) PRIMARY KEY,
+    fingerprint CHAR(64) NOT NULL,
+    created_at DATETIME(6) NOT NULL,
+    expires_at DATETIME(6) NOT NULL,
+    response_status SMALLINT UNSIGNED NULL,
+    response_headers JSON NULL,
+    response_body LONGBLOB NULL,
+    INDEX idempotency_keys_expiry_idx (expires_at)
+);
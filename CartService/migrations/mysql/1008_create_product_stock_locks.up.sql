@@ -0,0 +1,4 @@
+-- One row per product, locked by checkouts so they reserve its stock one at a time
+CREATE TABLE product_stock_locks (
+    product_id BINARY(16) PRIMARY KEY
+);
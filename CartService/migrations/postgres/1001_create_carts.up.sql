@@ -0,0 +1,22 @@
+-- Cart version rows and cart lines. Guest carts use their cart id as the user id.
+CREATE TABLE carts (
+    user_id UUID PRIMARY KEY,
+    version BIGINT NOT NULL DEFAULT 0,
+    last_activity_at TIMESTAMPTZ NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'active',
+    abandoned_at TIMESTAMPTZ NULL,
+    currency CHAR(3) NULL
+);
+
+CREATE INDEX carts_status_activity_idx ON carts (status, last_activity_at);
+
+CREATE TABLE cart_items (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    product_id UUID NOT NULL,
+    options JSONB NULL,
+    options_key VARCHAR(64) NOT NULL DEFAULT '',
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    updated_at TIMESTAMPTZ NOT NULL,
+    CONSTRAINT cart_items_line_key UNIQUE (user_id, product_id, options_key)
+);
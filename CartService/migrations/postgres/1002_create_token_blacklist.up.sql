@@ -0,0 +1,10 @@
+-- On MySQL this table belongs to UserService, whose database CartService shares. UserService
+-- does not run on PostgreSQL, so here CartService creates it to check revoked tokens against.
+CREATE TABLE IF NOT EXISTS token_blacklist (
+    id UUID PRIMARY KEY,
+    token VARCHAR(512) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS token_blacklist_token_idx ON token_blacklist (token, expires_at);
@@ -0,0 +1,47 @@
+CREATE TABLE orders (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    status VARCHAR(32) NOT NULL,
+    currency CHAR(3) NOT NULL,
+    shipping JSONB NOT NULL,
+    discounts JSONB NOT NULL,
+    subtotal NUMERIC(19, 4) NOT NULL,
+    discount_total NUMERIC(19, 4) NOT NULL,
+    tax_total NUMERIC(19, 4) NOT NULL,
+    tax_breakdown JSONB NULL,
+    shipping_total NUMERIC(19, 4) NOT NULL,
+    grand_total NUMERIC(19, 4) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX orders_user_idx ON orders (user_id, created_at);
+
+CREATE TABLE order_lines (
+    id UUID PRIMARY KEY,
+    order_id UUID NOT NULL REFERENCES orders (id) ON DELETE CASCADE,
+    product_id UUID NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    options JSONB NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    unit_price NUMERIC(19, 4) NOT NULL,
+    line_total NUMERIC(19, 4) NOT NULL
+);
+
+CREATE INDEX order_lines_order_idx ON order_lines (order_id);
+CREATE INDEX order_lines_product_idx ON order_lines (product_id);
+
+CREATE TABLE inventory_reservations (
+    id UUID PRIMARY KEY,
+    order_id UUID NOT NULL REFERENCES orders (id) ON DELETE CASCADE,
+    user_id UUID NOT NULL,
+    product_id UUID NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    status VARCHAR(16) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX inventory_reservations_product_idx ON inventory_reservations (product_id, status, expires_at);
+CREATE INDEX inventory_reservations_order_idx ON inventory_reservations (order_id);
+CREATE INDEX inventory_reservations_expiry_idx ON inventory_reservations (status, expires_at);
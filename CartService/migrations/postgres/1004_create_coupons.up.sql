@@ -0,0 +1,27 @@
+CREATE TABLE coupons (
+    code VARCHAR(64) PRIMARY KEY,
+    rules JSONB NOT NULL,
+    min_spend NUMERIC(19, 4) NULL,
+    category VARCHAR(255) NULL,
+    max_uses INTEGER NULL,
+    max_uses_per_user INTEGER NULL,
+    valid_from TIMESTAMPTZ NULL,
+    valid_until TIMESTAMPTZ NULL
+);
+
+CREATE TABLE cart_coupons (
+    user_id UUID NOT NULL,
+    code VARCHAR(64) NOT NULL REFERENCES coupons (code) ON DELETE CASCADE,
+    applied_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (user_id, code)
+);
+
+CREATE TABLE coupon_redemptions (
+    code VARCHAR(64) NOT NULL REFERENCES coupons (code),
+    user_id UUID NOT NULL,
+    order_id UUID NOT NULL REFERENCES orders (id) ON DELETE CASCADE,
+    redeemed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (order_id, code)
+);
+
+CREATE INDEX coupon_redemptions_code_idx ON coupon_redemptions (code, user_id);
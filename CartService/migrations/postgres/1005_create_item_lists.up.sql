@@ -0,0 +1,20 @@
+CREATE TABLE item_lists (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX item_lists_user_idx ON item_lists (user_id, kind);
+
+CREATE TABLE item_list_entries (
+    id UUID PRIMARY KEY,
+    list_id UUID NOT NULL REFERENCES item_lists (id) ON DELETE CASCADE,
+    product_id UUID NOT NULL,
+    options JSONB NULL,
+    options_key VARCHAR(64) NOT NULL DEFAULT '',
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    added_at TIMESTAMPTZ NOT NULL,
+    CONSTRAINT item_list_entries_line_key UNIQUE (list_id, product_id, options_key)
+);
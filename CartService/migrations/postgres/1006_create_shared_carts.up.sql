@@ -0,0 +1,8 @@
+CREATE TABLE shared_carts (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL,
+    items JSONB NOT NULL,
+    currency CHAR(3) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NULL
+);
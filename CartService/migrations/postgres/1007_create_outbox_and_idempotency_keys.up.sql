@@ -0,0 +1,22 @@
+-- sequence gives the order events are published in
+CREATE TABLE cart_outbox (
+    sequence BIGSERIAL PRIMARY KEY,
+    id UUID NOT NULL UNIQUE,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    event JSONB NOT NULL,
+    published_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX cart_outbox_unpublished_idx ON cart_outbox (sequence) WHERE published_at IS NULL;
+
+CREATE TABLE idempotency_keys (
+    key_hash CHAR(64) PRIMARY KEY,
+    fingerprint CHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    response_status INTEGER NULL,
+    response_headers JSONB NULL,
+    response_body BYTEA NULL
+);
+
+CREATE INDEX idempotency_keys_expiry_idx ON idempotency_keys (expires_at);
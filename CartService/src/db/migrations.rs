@@ -0,0 +1,204 @@
+use sqlx::migrate::{AppliedMigration, Migrate, MigrateError, Migrator};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+use super::Database;
+
+/// Migrations for each SQL backend, embedded in the binary at build time.
+/// On MySQL, CartService shares UserService's database and with it the `_sqlx_migrations` table,
+/// so CartService versions start at 1001, clear of UserService's, and the migrations UserService
+/// applied are ignored rather than treated as missing.
+static POSTGRES_MIGRATOR: Migrator = Migrator {
+    ignore_missing: true,
+    ..sqlx::migrate!("./migrations/postgres")
+};
+static MYSQL_MIGRATOR: Migrator = Migrator {
+    ignore_missing: true,
+    ..sqlx::migrate!("./migrations/mysql")
+};
+
+/// Subcommands of `cart_service migrate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrateCommand {
+    Up,     // Apply every pending migration
+    Down,   // Revert the most recently applied migration
+    Status, // List migrations and whether they have been applied
+}
+
+impl FromStr for MigrateCommand {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "up" => Ok(MigrateCommand::Up),
+            "down" => Ok(MigrateCommand::Down),
+            "status" => Ok(MigrateCommand::Status),
+            other => Err(format!("Unknown migrate command: {} (expected up, down or status)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    pub checksum_matches: bool, // False when an applied migration was edited afterwards
+}
+
+impl fmt::Display for MigrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match (self.applied, self.checksum_matches) {
+            (false, _) => "pending",
+            (true, true) => "applied",
+            (true, false) => "modified",
+        };
+        write!(f, "{:>4} {:<8} {}", self.version, state, self.description)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("Migration failed: {0}")]
+    MigrateError(#[from] MigrateError),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error("Schema is behind, pending migrations: {0:?}")]
+    Pending(Vec<i64>),
+    #[error("Migration {0} was changed after it was applied")]
+    Modified(i64),
+    #[error("Migration {0} failed partway and left the schema dirty")]
+    Dirty(i64),
+}
+
+impl Database {
+    // Apply every pending migration
+    pub async fn migrate_up(&self) -> Result<(), SchemaError> {
+        match self {
+            Database::Postgres(pool) => POSTGRES_MIGRATOR.run(pool).await?,
+            Database::MySql(pool) => MYSQL_MIGRATOR.run(pool).await?,
+            Database::Memory(_) => {}
+        }
+
+        Ok(())
+    }
+
+    // Revert the most recently applied migration, returning its version
+    pub async fn migrate_down(&self) -> Result<Option<i64>, SchemaError> {
+        let mut applied: Vec<i64> = self
+            .migration_status()
+            .await?
+            .into_iter()
+            .filter(|status| status.applied)
+            .map(|status| status.version)
+            .collect();
+        applied.sort_unstable();
+
+        let Some(latest) = applied.pop() else {
+            return Ok(None);
+        };
+        // Undo reverts everything above the target, so aim for the previous applied version
+        let target = applied.last().copied().unwrap_or(0);
+
+        match self {
+            Database::Postgres(pool) => POSTGRES_MIGRATOR.undo(pool, target).await?,
+            Database::MySql(pool) => MYSQL_MIGRATOR.undo(pool, target).await?,
+            Database::Memory(_) => {}
+        }
+
+        Ok(Some(latest))
+    }
+
+    // List the embedded migrations and whether each one has been applied
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>, SchemaError> {
+        match self {
+            Database::Postgres(pool) => {
+                let mut conn = pool.acquire().await?;
+                let (applied, _) = applied_migrations(&mut *conn).await?;
+                Ok(status(&POSTGRES_MIGRATOR, &applied))
+            }
+            Database::MySql(pool) => {
+                let mut conn = pool.acquire().await?;
+                let (applied, _) = applied_migrations(&mut *conn).await?;
+                Ok(status(&MYSQL_MIGRATOR, &applied))
+            }
+            Database::Memory(_) => Ok(Vec::new()),
+        }
+    }
+
+    // Fail unless every embedded migration has been applied unchanged
+    pub async fn check_schema(&self) -> Result<(), SchemaError> {
+        let dirty = match self {
+            Database::Postgres(pool) => applied_migrations(&mut *pool.acquire().await?).await?.1,
+            Database::MySql(pool) => applied_migrations(&mut *pool.acquire().await?).await?.1,
+            Database::Memory(_) => None,
+        };
+        // The table may also hold UserService's migrations; only CartService's count here
+        let statuses = self.migration_status().await?;
+        if let Some(version) = dirty.filter(|&version| statuses.iter().any(|status| status.version == version)) {
+            return Err(SchemaError::Dirty(version));
+        }
+
+        if let Some(status) = statuses.iter().find(|status| status.applied && !status.checksum_matches) {
+            return Err(SchemaError::Modified(status.version));
+        }
+
+        let pending: Vec<i64> = statuses
+            .iter()
+            .filter(|status| !status.applied)
+            .map(|status| status.version)
+            .collect();
+        if !pending.is_empty() {
+            return Err(SchemaError::Pending(pending));
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the applied migrations and any dirty version, creating the bookkeeping table if needed
+async fn applied_migrations<C>(conn: &mut C) -> Result<(Vec<AppliedMigration>, Option<i64>), MigrateError>
+where
+    C: Migrate + ?Sized,
+{
+    conn.ensure_migrations_table().await?;
+    let dirty = conn.dirty_version().await?;
+    let applied = conn.list_applied_migrations().await?;
+
+    Ok((applied, dirty))
+}
+
+/// Match the embedded up migrations against the applied ones
+fn status(migrator: &Migrator, applied: &[AppliedMigration]) -> Vec<MigrationStatus> {
+    let applied: HashMap<i64, &AppliedMigration> =
+        applied.iter().map(|migration| (migration.version, migration)).collect();
+
+    migrator
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| {
+            let record = applied.get(&migration.version);
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: record.is_some(),
+                checksum_matches: record.is_none_or(|record| record.checksum == migration.checksum),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_stay_clear_of_user_service() {
+        for migrator in [&POSTGRES_MIGRATOR, &MYSQL_MIGRATOR] {
+            assert!(migrator.iter().all(|migration| (1001..2000).contains(&migration.version)));
+        }
+        assert!(MYSQL_MIGRATOR.iter().all(|migration| !migration.description.contains("token_blacklist")));
+    }
+}
@@ -1,9 +1,72 @@
-use sqlx::{Pool, Postgres};
-
-pub async fn establish_connection(db_url: &str) -> Pool<Postgres> {
-    Pool::<Postgres>::builder()
-        .max_connections(5)
-        .build(db_url)
-        .await
-        .expect("Failed to connect to the database")
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{MySqlPool, PgPool};
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::repository::{
+    CartRepository, DatabaseBackend, InMemoryCartRepository, MySqlCartRepository, PostgresCartRepository,
+};
+
+pub mod migrations;
+
+/// An open connection to the configured database backend
+pub enum Database {
+    Postgres(PgPool),
+    MySql(MySqlPool),
+    Memory(InMemoryCartRepository),
+}
+
+impl Database {
+    /// Connect with the pool size and timeouts from the configuration
+    pub async fn connect(config: &Config) -> Result<Self, sqlx::Error> {
+        let database = match config.database_backend {
+            DatabaseBackend::Postgres => Database::Postgres(
+                PgPoolOptions::new()
+                    .max_connections(config.db_max_connections)
+                    .min_connections(config.db_min_connections)
+                    .acquire_timeout(config.db_acquire_timeout())
+                    .connect(&config.db_url)
+                    .await?,
+            ),
+            DatabaseBackend::MySql => Database::MySql(
+                MySqlPoolOptions::new()
+                    .max_connections(config.db_max_connections)
+                    .min_connections(config.db_min_connections)
+                    .acquire_timeout(config.db_acquire_timeout())
+                    .connect(&config.db_url)
+                    .await?,
+            ),
+            DatabaseBackend::Memory => Database::Memory(InMemoryCartRepository::new()),
+        };
+
+        Ok(database)
+    }
+
+    /// Run a trivial query to check the database answers
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        match self {
+            Database::Postgres(pool) => sqlx::query("SELECT 1").execute(pool).await.map(|_| ()),
+            Database::MySql(pool) => sqlx::query("SELECT 1").execute(pool).await.map(|_| ()),
+            Database::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Wait for checked out connections to be returned, then close the pool
+    pub async fn close(&self) {
+        match self {
+            Database::Postgres(pool) => pool.close().await,
+            Database::MySql(pool) => pool.close().await,
+            Database::Memory(_) => {}
+        }
+    }
+
+    /// Wrap the connection in the matching repository
+    pub fn repository(&self) -> Arc<dyn CartRepository> {
+        match self {
+            Database::Postgres(pool) => Arc::new(PostgresCartRepository::new(pool.clone())),
+            Database::MySql(pool) => Arc::new(MySqlCartRepository::new(pool.clone())),
+            Database::Memory(repository) => Arc::new(repository.clone()),
+        }
+    }
 }
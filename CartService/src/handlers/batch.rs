@@ -0,0 +1,38 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use crate::services::{batch::BatchRequest, CartService, CartServiceError};
+use std::sync::Arc;
+use uuid::Uuid;
+use super::{etag, if_match, version_mismatch_response};
+
+pub async fn apply_cart_batch(
+    Path(user_id): Path<Uuid>,
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<BatchRequest>,
+) -> impl IntoResponse {
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service
+        .apply_batch(user_id, &payload.operations, expected_version)
+        .await
+    {
+        Ok(result) if result.applied => (StatusCode::OK, etag(result.version), Json(result)).into_response(),
+        Ok(result) => (StatusCode::UNPROCESSABLE_ENTITY, etag(result.version), Json(result)).into_response(),
+        Err(CartServiceError::VersionMismatch { current }) => version_mismatch_response(current),
+        Err(err @ CartServiceError::BatchTooLarge(_)) => {
+            (StatusCode::PAYLOAD_TOO_LARGE, err.to_string()).into_response()
+        }
+        Err(CartServiceError::CatalogError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Product catalog unavailable").into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to apply batch").into_response(),
+    }
+}
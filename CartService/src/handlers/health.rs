@@ -0,0 +1,231 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use crate::db::Database;
+
+/// Dependencies checked by the readiness probe
+pub struct HealthCheck {
+    pub database: Arc<Database>,
+    pub http_client: reqwest::Client,
+    pub endpoints: Vec<(&'static str, String)>, // Upstream services by name, checked for reachability
+    pub timeout: Duration, // Applies to each check separately
+    pub stopping: watch::Receiver<bool>, // Set on shutdown, before the server stops accepting connections
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CheckStatus {
+    Ok,
+    Failing,
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+struct CheckResult {
+    status: CheckStatus,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    status: &'static str,
+    checks: BTreeMap<&'static str, CheckResult>,
+}
+
+/// Liveness: the process is up and serving requests
+pub async fn healthz() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness: every dependency answers, the schema is current and shutdown has not begun.
+/// Responds with 503 and the failing checks otherwise.
+pub async fn readyz(State(health): State<Arc<HealthCheck>>) -> impl IntoResponse {
+    if *health.stopping.borrow() {
+        let body = Readiness {
+            status: "shutting_down",
+            checks: BTreeMap::new(),
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body));
+    }
+
+    let database = check(health.timeout, async { health.database.ping().await.map_err(|err| err.to_string()) });
+
+    let migrations = async {
+        if matches!(*health.database, Database::Memory(_)) {
+            return CheckResult {
+                status: CheckStatus::Skipped,
+                latency_ms: 0,
+                error: None,
+            };
+        }
+        check(health.timeout, async { health.database.check_schema().await.map_err(|err| err.to_string()) }).await
+    };
+
+    // Upstream checks run on their own tasks, alongside the database checks
+    let endpoints: Vec<_> = health
+        .endpoints
+        .iter()
+        .map(|(name, url)| {
+            let (name, url, client, timeout) = (*name, url.clone(), health.http_client.clone(), health.timeout);
+            tokio::spawn(async move {
+                let result = check(timeout, async move {
+                    // Any response means the service can be reached, whatever its status
+                    client.get(&url).send().await.map(|_| ()).map_err(|err| err.to_string())
+                })
+                .await;
+                (name, result)
+            })
+        })
+        .collect();
+
+    let (database, migrations) = tokio::join!(database, migrations);
+
+    let mut checks = BTreeMap::new();
+    checks.insert("database", database);
+    checks.insert("migrations", migrations);
+    for endpoint in endpoints {
+        if let Ok((name, result)) = endpoint.await {
+            checks.insert(name, result);
+        }
+    }
+
+    let ready = checks.values().all(|check| !matches!(check.status, CheckStatus::Failing));
+    if ready {
+        (StatusCode::OK, Json(Readiness { status: "ready", checks }))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(Readiness { status: "not_ready", checks }))
+    }
+}
+
+/// Run one check, failing it if it does not finish within `timeout`
+async fn check<F>(timeout: Duration, check: F) -> CheckResult
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Timed out after {}ms", timeout.as_millis())),
+    };
+
+    CheckResult {
+        status: if result.is_ok() { CheckStatus::Ok } else { CheckStatus::Failing },
+        latency_ms: started.elapsed().as_millis(),
+        error: result.err(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::{Router, Server};
+    use std::net::SocketAddr;
+
+    use crate::repository::InMemoryCartRepository;
+    use crate::services::testing::spawn_product_service;
+
+    fn health_check(endpoints: Vec<(&'static str, String)>, stopping: watch::Receiver<bool>) -> HealthCheck {
+        HealthCheck {
+            database: Arc::new(Database::Memory(InMemoryCartRepository::new())),
+            http_client: reqwest::Client::new(),
+            endpoints,
+            timeout: Duration::from_millis(500),
+            stopping,
+        }
+    }
+
+    // Serve the probes and return the base URL
+    async fn spawn_probes(health: HealthCheck) -> String {
+        let app = Router::new()
+            .route("/healthz", get(healthz))
+            .route("/readyz", get(readyz))
+            .with_state(Arc::new(health));
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(app.into_make_service());
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    async fn probe(url: &str, path: &str) -> (StatusCode, serde_json::Value) {
+        let response = reqwest::get(format!("{}{}", url, path)).await.unwrap();
+        (response.status(), response.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn readiness_fails_once_shutdown_begins() {
+        let (stopping_tx, stopping_rx) = watch::channel(false);
+        let url = spawn_probes(health_check(Vec::new(), stopping_rx)).await;
+        assert_eq!(probe(&url, "/readyz").await.0, StatusCode::OK);
+
+        stopping_tx.send(true).unwrap();
+
+        let (status, body) = probe(&url, "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "shutting_down");
+        // Liveness stays up while requests drain
+        assert_eq!(probe(&url, "/healthz").await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn readiness_reports_each_dependency() {
+        let (upstream, _) = spawn_product_service([]).await;
+        let url = spawn_probes(health_check(vec![("product_service", upstream)], watch::channel(false).1)).await;
+
+        let (status, body) = probe(&url, "/readyz").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["checks"]["database"]["status"], "ok");
+        // The in-memory backend has no schema to check
+        assert_eq!(body["checks"]["migrations"]["status"], "skipped");
+        assert_eq!(body["checks"]["product_service"]["status"], "ok");
+        assert!(body["checks"]["product_service"].get("error").is_none());
+    }
+
+    #[tokio::test]
+    async fn unreachable_dependencies_fail_readiness() {
+        // A port nothing listens on
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        // A server that accepts connections but never answers
+        let hung = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hung_url = format!("http://{}", hung.local_addr().unwrap());
+        let endpoints = vec![("product_service", closed_url), ("user_service", hung_url)];
+        let url = spawn_probes(health_check(endpoints, watch::channel(false).1)).await;
+
+        let (status, body) = probe(&url, "/readyz").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["checks"]["database"]["status"], "ok");
+        assert_eq!(body["checks"]["product_service"]["status"], "failing");
+        assert!(body["checks"]["product_service"]["error"].is_string());
+        assert_eq!(body["checks"]["user_service"]["status"], "failing");
+        assert_eq!(body["checks"]["user_service"]["error"], "Timed out after 500ms");
+        drop(hung);
+    }
+
+    #[tokio::test]
+    async fn liveness_does_not_depend_on_anything() {
+        let url = spawn_probes(health_check(
+            vec![("product_service", "http://127.0.0.1:1".to_string())],
+            watch::channel(false).1,
+        ))
+        .await;
+
+        let (status, body) = probe(&url, "/healthz").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+    }
+}
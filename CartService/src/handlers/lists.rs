@@ -0,0 +1,89 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+use crate::services::{
+    lists::{ItemListNameRequest, MoveToListRequest},
+    CartService, CartServiceError,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub async fn get_item_lists(
+    Path(user_id): Path<Uuid>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    match cart_service.get_item_lists(user_id).await {
+        Ok(lists) => Json(lists).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch lists").into_response(),
+    }
+}
+
+pub async fn create_wishlist(
+    Path(user_id): Path<Uuid>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<ItemListNameRequest>,
+) -> impl IntoResponse {
+    match cart_service.create_wishlist(user_id, &payload.name).await {
+        Ok(list_id) => (StatusCode::CREATED, Json(json!({ "id": list_id }))).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create list").into_response(),
+    }
+}
+
+pub async fn rename_wishlist(
+    Path((user_id, list_id)): Path<(Uuid, Uuid)>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<ItemListNameRequest>,
+) -> impl IntoResponse {
+    match cart_service.rename_wishlist(user_id, list_id, &payload.name).await {
+        Ok(_) => (StatusCode::OK, "List renamed").into_response(),
+        Err(CartServiceError::ListNotFound) => (StatusCode::NOT_FOUND, "List not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to rename list").into_response(),
+    }
+}
+
+pub async fn delete_wishlist(
+    Path((user_id, list_id)): Path<(Uuid, Uuid)>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    match cart_service.delete_wishlist(user_id, list_id).await {
+        Ok(_) => (StatusCode::OK, "List deleted").into_response(),
+        Err(CartServiceError::ListNotFound) => (StatusCode::NOT_FOUND, "List not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete list").into_response(),
+    }
+}
+
+pub async fn move_cart_item_to_list(
+    Path((user_id, item_id)): Path<(Uuid, Uuid)>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<MoveToListRequest>,
+) -> impl IntoResponse {
+    match cart_service
+        .move_cart_item_to_list(user_id, item_id, payload.list_id)
+        .await
+    {
+        Ok(list_id) => (StatusCode::OK, Json(json!({ "list_id": list_id }))).into_response(),
+        Err(CartServiceError::ItemNotFound) => (StatusCode::NOT_FOUND, "Item not found").into_response(),
+        Err(CartServiceError::ListNotFound) => (StatusCode::NOT_FOUND, "List not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to move item").into_response(),
+    }
+}
+
+pub async fn move_list_entry_to_cart(
+    Path((user_id, list_id, entry_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    match cart_service
+        .move_list_entry_to_cart(user_id, list_id, entry_id)
+        .await
+    {
+        Ok(_) => (StatusCode::OK, "Item moved to cart").into_response(),
+        Err(CartServiceError::ListEntryNotFound) => {
+            (StatusCode::NOT_FOUND, "List entry not found").into_response()
+        }
+        Err(err) => super::add_item_error_response(err),
+    }
+}
@@ -1,53 +1,519 @@
+pub mod batch;
+pub mod health;
+pub mod lists;
+pub mod orders;
+pub mod sharing;
+pub mod shipping;
+
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use crate::services::{CartService, CartServiceError, CartItem, AddCartItemRequest};
+use crate::services::{
+    CartService, CartServiceError, AddCartItemRequest, UpdateCartItemRequest,
+    MergeGuestCartRequest, PriceCartQuery, SetCartCurrencyRequest, promotions::ApplyCouponRequest,
+    currency::ExchangeRateError, limits::LimitViolation,
+};
+use crate::utils::cart_token::CART_TOKEN_HEADER;
+use serde_json::json;
+use std::sync::Arc;
 use uuid::Uuid;
 
+/// Formats a cart version as an ETag header
+fn etag(version: u64) -> [(header::HeaderName, String); 1] {
+    [(header::ETAG, format!("\"{}\"", version))]
+}
+
+/// Reads the cart version the client expects from If-Match; `*` or no header matches any version
+fn if_match(headers: &HeaderMap) -> Result<Option<u64>, Box<Response>> {
+    let value = match headers.get(header::IF_MATCH) {
+        Some(value) => value.to_str().unwrap_or_default().trim(),
+        None => return Ok(None),
+    };
+
+    if value == "*" {
+        return Ok(None);
+    }
+
+    value
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse::<u64>()
+        .map(Some)
+        .map_err(|_| Box::new((StatusCode::PRECONDITION_FAILED, "Invalid If-Match header").into_response()))
+}
+
+/// Response for a write made against a stale cart version
+fn version_mismatch_response(current: u64) -> Response {
+    (
+        StatusCode::PRECONDITION_FAILED,
+        etag(current),
+        "Cart was modified by another request",
+    )
+        .into_response()
+}
+
+/// Maps errors from pricing a cart in a given currency to a response
+fn currency_error_response(err: CartServiceError, fallback: &'static str) -> Response {
+    match err {
+        CartServiceError::VersionMismatch { current } => version_mismatch_response(current),
+        CartServiceError::ExchangeRateError(ExchangeRateError::UnsupportedPair { .. }) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, "Currency is not supported").into_response()
+        }
+        CartServiceError::ExchangeRateError(_) => {
+            (StatusCode::BAD_GATEWAY, "Exchange rates unavailable").into_response()
+        }
+        CartServiceError::CatalogError(_) => {
+            (StatusCode::BAD_GATEWAY, "Product catalog unavailable").into_response()
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, fallback).into_response(),
+    }
+}
+
 pub async fn get_cart(
     Path(user_id): Path<Uuid>,
-    State(cart_service): State<CartService>,
+    Query(query): Query<PriceCartQuery>,
+    State(cart_service): State<Arc<CartService>>,
 ) -> impl IntoResponse {
-    match cart_service.get_cart(user_id).await {
-        Ok(cart) => Json(cart).into_response(),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch cart").into_response(),
+    match cart_service.get_priced_cart(user_id, &query).await {
+        Ok(cart) => (etag(cart.version), Json(cart)).into_response(),
+        Err(err) => currency_error_response(err, "Failed to fetch cart"),
+    }
+}
+
+pub async fn set_cart_currency(
+    Path(user_id): Path<Uuid>,
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<SetCartCurrencyRequest>,
+) -> impl IntoResponse {
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service
+        .set_cart_currency(user_id, payload.currency, expected_version)
+        .await
+    {
+        Ok(version) => (StatusCode::OK, etag(version), "Cart currency updated").into_response(),
+        Err(err) => currency_error_response(err, "Failed to update cart currency"),
     }
 }
 
 pub async fn add_cart_item(
     Path(user_id): Path<Uuid>,
-    State(cart_service): State<CartService>,
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
     Json(payload): Json<AddCartItemRequest>,
 ) -> impl IntoResponse {
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
     match cart_service
-        .add_item_to_cart(user_id, payload.product_id, payload.quantity)
+        .add_item_to_cart(user_id, payload.product_id, payload.quantity, &payload.options, expected_version)
         .await
     {
-        Ok(_) => (StatusCode::CREATED, "Item added to cart").into_response(),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to add item").into_response(),
+        Ok(version) => (StatusCode::CREATED, etag(version), "Item added to cart").into_response(),
+        Err(err) => add_item_error_response(err),
+    }
+}
+
+/// Explains which cart limit or purchase rule a change broke
+pub(crate) fn limit_violation_response(violation: LimitViolation) -> Response {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Json(json!({ "error": violation.to_string(), "violation": violation })),
+    )
+        .into_response()
+}
+
+/// Maps errors from adding or resizing a cart line to a response
+pub(crate) fn add_item_error_response(err: CartServiceError) -> Response {
+    match err {
+        CartServiceError::LimitExceeded(violation) => limit_violation_response(violation),
+        CartServiceError::ItemNotFound => (StatusCode::NOT_FOUND, "Item not found").into_response(),
+        CartServiceError::VersionMismatch { current } => version_mismatch_response(current),
+        CartServiceError::ProductNotFound(_) => (StatusCode::NOT_FOUND, "Product not found").into_response(),
+        CartServiceError::ProductInactive(_) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, "Product is not available").into_response()
+        }
+        CartServiceError::InsufficientStock { .. } => {
+            (StatusCode::CONFLICT, "Not enough stock for product").into_response()
+        }
+        CartServiceError::StockReserved { .. } => {
+            (StatusCode::CONFLICT, "Stock is reserved by other shoppers").into_response()
+        }
+        CartServiceError::InvalidLineOptions(err) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response()
+        }
+        CartServiceError::CatalogError(_) => {
+            (StatusCode::BAD_GATEWAY, "Product catalog unavailable").into_response()
+        }
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to add item").into_response(),
+    }
+}
+
+pub async fn update_cart_item_quantity(
+    Path((user_id, item_id)): Path<(Uuid, Uuid)>,
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<UpdateCartItemRequest>,
+) -> impl IntoResponse {
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service
+        .set_item_quantity(user_id, item_id, payload.quantity, expected_version)
+        .await
+    {
+        Ok(version) if payload.quantity == 0 => {
+            (StatusCode::OK, etag(version), "Item removed from cart").into_response()
+        }
+        Ok(version) => (StatusCode::OK, etag(version), "Item quantity updated").into_response(),
+        Err(err) => add_item_error_response(err),
     }
 }
 
 pub async fn remove_cart_item(
     Path((user_id, item_id)): Path<(Uuid, Uuid)>,
-    State(cart_service): State<CartService>,
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
 ) -> impl IntoResponse {
-    match cart_service.remove_item_from_cart(user_id, item_id).await {
-        Ok(_) => (StatusCode::OK, "Item removed from cart").into_response(),
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service
+        .remove_item_from_cart(user_id, item_id, expected_version)
+        .await
+    {
+        Ok(version) => (StatusCode::OK, etag(version), "Item removed from cart").into_response(),
         Err(CartServiceError::ItemNotFound) => (StatusCode::NOT_FOUND, "Item not found").into_response(),
+        Err(CartServiceError::VersionMismatch { current }) => version_mismatch_response(current),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove item").into_response(),
     }
 }
 
 pub async fn clear_cart(
     Path(user_id): Path<Uuid>,
-    State(cart_service): State<CartService>,
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
 ) -> impl IntoResponse {
-    match cart_service.clear_cart(user_id).await {
-        Ok(_) => (StatusCode::OK, "Cart cleared").into_response(),
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service.clear_cart(user_id, expected_version).await {
+        Ok(version) => (StatusCode::OK, etag(version), "Cart cleared").into_response(),
+        Err(CartServiceError::VersionMismatch { current }) => version_mismatch_response(current),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to clear cart").into_response(),
     }
 }
+
+pub async fn apply_coupon(
+    Path(user_id): Path<Uuid>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<ApplyCouponRequest>,
+) -> impl IntoResponse {
+    match cart_service.apply_coupon(user_id, &payload.code).await {
+        Ok(_) => (StatusCode::CREATED, "Coupon applied").into_response(),
+        Err(CartServiceError::CouponNotFound) => (StatusCode::NOT_FOUND, "Coupon not found").into_response(),
+        Err(CartServiceError::CouponRejected(reason)) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, reason.to_string()).into_response()
+        }
+        Err(CartServiceError::CatalogError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Product catalog unavailable").into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to apply coupon").into_response(),
+    }
+}
+
+pub async fn remove_coupon(
+    Path((user_id, code)): Path<(Uuid, String)>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    match cart_service.remove_coupon(user_id, &code).await {
+        Ok(_) => (StatusCode::OK, "Coupon removed").into_response(),
+        Err(CartServiceError::CouponNotFound) => (StatusCode::NOT_FOUND, "Coupon not applied").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove coupon").into_response(),
+    }
+}
+
+pub async fn merge_guest_cart(
+    Path(user_id): Path<Uuid>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<MergeGuestCartRequest>,
+) -> impl IntoResponse {
+    match cart_service
+        .merge_guest_cart(user_id, &payload.cart_token, payload.strategy)
+        .await
+    {
+        Ok(_) => (StatusCode::OK, "Guest cart merged").into_response(),
+        Err(CartServiceError::InvalidCartToken) => (StatusCode::BAD_REQUEST, "Invalid cart token").into_response(),
+        Err(
+            err @ (CartServiceError::LimitExceeded(_)
+            | CartServiceError::ProductNotFound(_)
+            | CartServiceError::ProductInactive(_)
+            | CartServiceError::InsufficientStock { .. }
+            | CartServiceError::StockReserved { .. }
+            | CartServiceError::CatalogError(_)),
+        ) => add_item_error_response(err),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to merge guest cart").into_response(),
+    }
+}
+
+/// Reads the guest cart token sent by the client, if any
+fn guest_cart_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get(CART_TOKEN_HEADER).and_then(|value| value.to_str().ok())
+}
+
+/// Resolves the guest cart id for requests that require an existing guest cart
+fn existing_guest_cart(
+    cart_service: &CartService,
+    headers: &HeaderMap,
+) -> Result<Uuid, (StatusCode, &'static str)> {
+    match guest_cart_token(headers) {
+        Some(token) => cart_service
+            .resolve_guest_cart(Some(token))
+            .map(|(cart_id, _)| cart_id)
+            .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid cart token")),
+        None => Err((StatusCode::NOT_FOUND, "Guest cart not found")),
+    }
+}
+
+pub async fn get_guest_cart(
+    headers: HeaderMap,
+    Query(query): Query<PriceCartQuery>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    let cart_id = match existing_guest_cart(&cart_service, &headers) {
+        Ok(cart_id) => cart_id,
+        Err(err) => return err.into_response(),
+    };
+
+    match cart_service.get_priced_cart(cart_id, &query).await {
+        Ok(cart) => (etag(cart.version), Json(cart)).into_response(),
+        Err(err) => currency_error_response(err, "Failed to fetch cart"),
+    }
+}
+
+pub async fn add_guest_cart_item(
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<AddCartItemRequest>,
+) -> impl IntoResponse {
+    // The cart token is issued on the first add
+    let (cart_id, issued_token) = match cart_service.resolve_guest_cart(guest_cart_token(&headers)) {
+        Ok(resolved) => resolved,
+        Err(_) => return (StatusCode::UNAUTHORIZED, "Invalid cart token").into_response(),
+    };
+
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service
+        .add_item_to_cart(cart_id, payload.product_id, payload.quantity, &payload.options, expected_version)
+        .await
+    {
+        Ok(version) => match issued_token {
+            Some(token) => (
+                StatusCode::CREATED,
+                etag(version),
+                [(CART_TOKEN_HEADER, token)],
+                "Item added to cart",
+            )
+                .into_response(),
+            None => (StatusCode::CREATED, etag(version), "Item added to cart").into_response(),
+        },
+        Err(err) => add_item_error_response(err),
+    }
+}
+
+pub async fn update_guest_cart_item_quantity(
+    headers: HeaderMap,
+    Path(item_id): Path<Uuid>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<UpdateCartItemRequest>,
+) -> impl IntoResponse {
+    let cart_id = match existing_guest_cart(&cart_service, &headers) {
+        Ok(cart_id) => cart_id,
+        Err(err) => return err.into_response(),
+    };
+
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service
+        .set_item_quantity(cart_id, item_id, payload.quantity, expected_version)
+        .await
+    {
+        Ok(version) if payload.quantity == 0 => {
+            (StatusCode::OK, etag(version), "Item removed from cart").into_response()
+        }
+        Ok(version) => (StatusCode::OK, etag(version), "Item quantity updated").into_response(),
+        Err(err) => add_item_error_response(err),
+    }
+}
+
+pub async fn remove_guest_cart_item(
+    headers: HeaderMap,
+    Path(item_id): Path<Uuid>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    let cart_id = match existing_guest_cart(&cart_service, &headers) {
+        Ok(cart_id) => cart_id,
+        Err(err) => return err.into_response(),
+    };
+
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service
+        .remove_item_from_cart(cart_id, item_id, expected_version)
+        .await
+    {
+        Ok(version) => (StatusCode::OK, etag(version), "Item removed from cart").into_response(),
+        Err(CartServiceError::ItemNotFound) => (StatusCode::NOT_FOUND, "Item not found").into_response(),
+        Err(CartServiceError::VersionMismatch { current }) => version_mismatch_response(current),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove item").into_response(),
+    }
+}
+
+pub async fn clear_guest_cart(
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    let cart_id = match existing_guest_cart(&cart_service, &headers) {
+        Ok(cart_id) => cart_id,
+        Err(err) => return err.into_response(),
+    };
+
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service.clear_cart(cart_id, expected_version).await {
+        Ok(version) => (StatusCode::OK, etag(version), "Cart cleared").into_response(),
+        Err(CartServiceError::VersionMismatch { current }) => version_mismatch_response(current),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to clear cart").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    use crate::services::testing::{access_token, cart_service, product, spawn_app};
+
+    // An app selling one product, and a client signed in as `user_id`
+    async fn signed_in(user_id: Uuid) -> (String, reqwest::Client, Uuid) {
+        let product = product(Decimal::new(1000, 2), 10);
+        let url = spawn_app(cart_service([product.clone()])).await;
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", access_token(user_id)).parse().unwrap(),
+        );
+        let client = reqwest::Client::builder().default_headers(headers).build().unwrap();
+
+        (url, client, product.id)
+    }
+
+    async fn add(
+        client: &reqwest::Client,
+        url: &str,
+        user_id: Uuid,
+        product_id: Uuid,
+        if_match: Option<&str>,
+    ) -> reqwest::Response {
+        let mut request = client
+            .post(format!("{}/cart/{}/add", url, user_id))
+            .json(&json!({ "product_id": product_id, "quantity": 1 }));
+        if let Some(if_match) = if_match {
+            request = request.header(header::IF_MATCH, if_match);
+        }
+        request.send().await.unwrap()
+    }
+
+    async fn cart(client: &reqwest::Client, url: &str, user_id: Uuid) -> (String, serde_json::Value) {
+        let response = client.get(format!("{}/cart/{}", url, user_id)).send().await.unwrap();
+        let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        (etag, response.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn writes_return_the_new_cart_version_as_the_etag() {
+        let user_id = Uuid::new_v4();
+        let (url, client, product_id) = signed_in(user_id).await;
+
+        let first = add(&client, &url, user_id, product_id, None).await;
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert_eq!(first.headers()[header::ETAG], "\"1\"");
+
+        let second = add(&client, &url, user_id, product_id, Some("\"1\"")).await;
+        assert_eq!(second.status(), StatusCode::CREATED);
+        assert_eq!(second.headers()[header::ETAG], "\"2\"");
+
+        let (etag, cart) = cart(&client, &url, user_id).await;
+        assert_eq!(etag, "\"2\"");
+        assert_eq!(cart["version"], 2);
+    }
+
+    #[tokio::test]
+    async fn writes_against_a_stale_version_fail_with_412() {
+        let user_id = Uuid::new_v4();
+        let (url, client, product_id) = signed_in(user_id).await;
+        add(&client, &url, user_id, product_id, None).await;
+        add(&client, &url, user_id, product_id, None).await;
+        let (_, before) = cart(&client, &url, user_id).await;
+        let item_id = before["lines"][0]["id"].as_str().unwrap().to_string();
+
+        let stale_add = add(&client, &url, user_id, product_id, Some("\"1\"")).await;
+        let stale_remove = client
+            .delete(format!("{}/cart/{}/remove/{}", url, user_id, item_id))
+            .header(header::IF_MATCH, "\"1\"")
+            .send()
+            .await
+            .unwrap();
+
+        for response in [stale_add, stale_remove] {
+            assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+            assert_eq!(response.headers()[header::ETAG], "\"2\"");
+        }
+        let (etag, after) = cart(&client, &url, user_id).await;
+        assert_eq!(etag, "\"2\"");
+        assert_eq!(after["lines"], before["lines"]);
+    }
+
+    #[tokio::test]
+    async fn weak_and_wildcard_if_match_values_are_accepted() {
+        let user_id = Uuid::new_v4();
+        let (url, client, product_id) = signed_in(user_id).await;
+        add(&client, &url, user_id, product_id, None).await;
+
+        assert_eq!(add(&client, &url, user_id, product_id, Some("W/\"1\"")).await.status(), StatusCode::CREATED);
+        assert_eq!(add(&client, &url, user_id, product_id, Some("*")).await.status(), StatusCode::CREATED);
+        assert_eq!(
+            add(&client, &url, user_id, product_id, Some("not-a-version")).await.status(),
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(cart(&client, &url, user_id).await.0, "\"3\"");
+    }
+}
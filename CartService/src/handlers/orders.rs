@@ -0,0 +1,74 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use crate::services::{
+    orders::{CheckoutRequest, UpdateOrderStatusRequest},
+    CartService, CartServiceError,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub async fn checkout(
+    Path(user_id): Path<Uuid>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<CheckoutRequest>,
+) -> impl IntoResponse {
+    match cart_service.checkout(user_id, &payload).await {
+        Ok(order) => (StatusCode::CREATED, Json(order)).into_response(),
+        Err(CartServiceError::EmptyCart) => (StatusCode::UNPROCESSABLE_ENTITY, "Cart is empty").into_response(),
+        Err(CartServiceError::ProductNotFound(_) | CartServiceError::ProductInactive(_)) => {
+            (StatusCode::CONFLICT, "Cart contains products that are no longer available").into_response()
+        }
+        Err(CartServiceError::VersionMismatch { current }) => super::version_mismatch_response(current),
+        Err(CartServiceError::LimitExceeded(violation)) => super::limit_violation_response(violation),
+        Err(CartServiceError::CouponRejected(reason)) => (StatusCode::CONFLICT, reason.to_string()).into_response(),
+        Err(err @ CartServiceError::StockReserved { .. }) => {
+            (StatusCode::CONFLICT, err.to_string()).into_response()
+        }
+        Err(err @ CartServiceError::ShippingUnavailable(_)) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response()
+        }
+        Err(CartServiceError::CatalogError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Product catalog unavailable").into_response()
+        }
+        Err(CartServiceError::ExchangeRateError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Exchange rates unavailable").into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to check out").into_response(),
+    }
+}
+
+pub async fn get_order(
+    Path((user_id, order_id)): Path<(Uuid, Uuid)>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    match cart_service.get_order(user_id, order_id).await {
+        Ok(order) => Json(order).into_response(),
+        Err(CartServiceError::OrderNotFound) => (StatusCode::NOT_FOUND, "Order not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch order").into_response(),
+    }
+}
+
+pub async fn update_order_status(
+    Path((user_id, order_id)): Path<(Uuid, Uuid)>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<UpdateOrderStatusRequest>,
+) -> impl IntoResponse {
+    match cart_service
+        .transition_order(user_id, order_id, payload.status)
+        .await
+    {
+        Ok(_) => (StatusCode::OK, "Order status updated").into_response(),
+        Err(CartServiceError::OrderNotFound) => (StatusCode::NOT_FOUND, "Order not found").into_response(),
+        Err(err @ CartServiceError::IllegalTransition { .. }) => {
+            (StatusCode::CONFLICT, err.to_string()).into_response()
+        }
+        Err(CartServiceError::CatalogError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Failed to update product stock").into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update order").into_response(),
+    }
+}
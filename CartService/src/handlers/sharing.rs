@@ -0,0 +1,69 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use crate::services::{sharing::ShareCartRequest, CartService, CartServiceError};
+use std::sync::Arc;
+use uuid::Uuid;
+use super::{add_item_error_response, etag, if_match};
+
+pub async fn share_cart(
+    Path(user_id): Path<Uuid>,
+    State(cart_service): State<Arc<CartService>>,
+    Json(payload): Json<ShareCartRequest>,
+) -> impl IntoResponse {
+    match cart_service.share_cart(user_id, &payload).await {
+        Ok(link) => (StatusCode::CREATED, Json(link)).into_response(),
+        Err(CartServiceError::EmptyCart) => (StatusCode::UNPROCESSABLE_ENTITY, "Cart is empty").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to share cart").into_response(),
+    }
+}
+
+pub async fn get_shared_cart(
+    Path(token): Path<String>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    match cart_service.get_shared_cart(&token).await {
+        Ok(shared) => Json(shared).into_response(),
+        Err(CartServiceError::SharedCartNotFound) => {
+            (StatusCode::NOT_FOUND, "Shared cart not found").into_response()
+        }
+        Err(CartServiceError::SharedCartExpired) => {
+            (StatusCode::GONE, "Shared cart link has expired").into_response()
+        }
+        Err(CartServiceError::CatalogError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Product catalog unavailable").into_response()
+        }
+        Err(CartServiceError::ExchangeRateError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Exchange rates unavailable").into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch shared cart").into_response(),
+    }
+}
+
+pub async fn import_shared_cart(
+    Path((user_id, token)): Path<(Uuid, String)>,
+    headers: HeaderMap,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    let expected_version = match if_match(&headers) {
+        Ok(version) => version,
+        Err(response) => return *response,
+    };
+
+    match cart_service
+        .import_shared_cart(user_id, &token, expected_version)
+        .await
+    {
+        Ok(version) => (StatusCode::OK, etag(version), "Shared cart imported").into_response(),
+        Err(CartServiceError::SharedCartNotFound) => {
+            (StatusCode::NOT_FOUND, "Shared cart not found").into_response()
+        }
+        Err(CartServiceError::SharedCartExpired) => {
+            (StatusCode::GONE, "Shared cart link has expired").into_response()
+        }
+        Err(err) => add_item_error_response(err),
+    }
+}
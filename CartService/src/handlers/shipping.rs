@@ -0,0 +1,30 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use crate::services::{shipping::ShippingOptionsQuery, CartService, CartServiceError};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub async fn get_shipping_options(
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<ShippingOptionsQuery>,
+    State(cart_service): State<Arc<CartService>>,
+) -> impl IntoResponse {
+    match cart_service
+        .get_shipping_options(user_id, &query.address, query.currency)
+        .await
+    {
+        Ok(options) => Json(options).into_response(),
+        Err(CartServiceError::EmptyCart) => (StatusCode::UNPROCESSABLE_ENTITY, "Cart is empty").into_response(),
+        Err(CartServiceError::CatalogError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Product catalog unavailable").into_response()
+        }
+        Err(CartServiceError::ExchangeRateError(_)) => {
+            (StatusCode::BAD_GATEWAY, "Exchange rates unavailable").into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch shipping options").into_response(),
+    }
+}
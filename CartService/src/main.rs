@@ -1,72 +1,319 @@
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{Json, IntoResponse},
-    routing::get,
-    Router, Server,
-};
-use dotenvy::dotenv;
-use serde::Serialize;
-use sqlx::MySqlPool;
-use std::{env, net::SocketAddr};
-use uuid::Uuid;
+use axum::Server;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::timeout::TimeoutLayer;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
 
+mod config;
 mod db;
+mod handlers;
+mod middleware;
+mod repository;
+mod routes;
 mod services;
+mod utils;
 
-#[derive(Clone)]
-struct AppState {
-    db_pool: MySqlPool,
-}
+use config::{Args, Config, LogFormat};
+use db::migrations::MigrateCommand;
+use db::Database;
+use handlers::health::HealthCheck;
+use services::abandonment::{spawn_cart_sweeper, AbandonmentPolicy, LogNotifier};
+use services::catalog::HttpProductCatalog;
+use services::currency::{ExchangeRateProvider, HttpExchangeRates, StaticExchangeRates};
+use services::inventory::spawn_reservation_sweeper;
+use services::limits::CartLimits;
+use services::outbox::{spawn_outbox_relay, EventPublisher, NdjsonFilePublisher};
+use services::pricing::PricingEngine;
+use services::shipping::TableShippingCalculator;
+use services::tax::{spawn_tax_rules_reloader, TaxRulesStore};
+use services::CartService;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Load environment variables
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-
-    // Create a database connection pool
-    let db_pool = MySqlPool::connect(&database_url)
-        .await
-        .expect("Failed to connect to the database");
-
-    // Share application state
-    let app_state = AppState { db_pool };
-
-    // Build the router
-    let app = Router::new()
-        .route("/user/:id", get(get_user))
-        .with_state(app_state);
-
-    // Start the server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
-    println!("Server running on http://{}", addr);
-    Server::bind(&addr)
+    let args = Args::parse(env::args().skip(1))?;
+    let config = config::get_config(&args)?;
+
+    // `cart_service --print-config` shows the effective configuration and exits
+    if args.print_config {
+        print!("{}", config.redacted());
+        return Ok(());
+    }
+
+    init_tracing(&config)?;
+
+    let database = Arc::new(Database::connect(&config).await?);
+
+    // `cart_service migrate up|down|status` manages the schema and exits
+    if args.command.first().map(String::as_str) == Some("migrate") {
+        let command = args
+            .command
+            .get(1)
+            .ok_or("Usage: cart_service migrate <up|down|status>")?
+            .parse::<MigrateCommand>()?;
+
+        match command {
+            MigrateCommand::Up => {
+                database.migrate_up().await?;
+                println!("Schema is up to date");
+            }
+            MigrateCommand::Down => match database.migrate_down().await? {
+                Some(version) => println!("Reverted migration {}", version),
+                None => println!("No migrations to revert"),
+            },
+            MigrateCommand::Status => {
+                for status in database.migration_status().await? {
+                    println!("{}", status);
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    if let Some(command) = args.command.first() {
+        return Err(format!("Unknown command: {}", command).into());
+    }
+
+    // Refuse to serve against a schema that is behind the binary
+    if config.schema_check {
+        database.check_schema().await?;
+    }
+
+    let cart_service = Arc::new(build_cart_service(&config, &database)?);
+
+    // On SIGTERM or SIGINT readiness fails first, so load balancers stop sending traffic
+    // before the server stops accepting it. After the pre-stop delay the shutdown flag,
+    // which the server and background workers watch, starts the drain.
+    let (stopping_tx, stopping_rx) = watch::channel(false);
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let pre_stop_delay = config.pre_stop_delay();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutdown signal received, failing readiness for {}s", pre_stop_delay.as_secs());
+        let _ = stopping_tx.send(true);
+        tokio::time::sleep(pre_stop_delay).await;
+        info!("Draining in-flight requests");
+        let _ = shutdown_tx.send(true);
+    });
+
+    let mut workers = Vec::new();
+    if config.outbox_relay_enabled {
+        workers.push(spawn_outbox_relay(
+            cart_service.clone(),
+            Duration::from_secs(config.outbox_relay_interval_secs),
+            shutdown_rx.clone(),
+        ));
+    }
+    if config.reservation_sweeper_enabled {
+        workers.push(spawn_reservation_sweeper(
+            cart_service.clone(),
+            Duration::from_secs(config.reservation_sweep_interval_secs),
+            shutdown_rx.clone(),
+        ));
+    }
+    if config.cart_sweeper_enabled {
+        let policy = AbandonmentPolicy {
+            abandon_after: chrono::Duration::seconds(config.cart_abandon_after_secs as i64),
+            expire_after: chrono::Duration::seconds(config.cart_expire_after_secs as i64),
+        };
+        workers.push(spawn_cart_sweeper(
+            cart_service.clone(),
+            policy,
+            Duration::from_secs(config.cart_sweep_interval_secs),
+            shutdown_rx.clone(),
+        ));
+    }
+    workers.push(spawn_tax_rules_reloader(
+        cart_service.tax_rules.clone(),
+        Duration::from_secs(config.tax_rules_reload_secs),
+        shutdown_rx.clone(),
+    ));
+
+    let mut endpoints = vec![("product_service", config.product_service_url.clone())];
+    if let Some(url) = &config.user_service_url {
+        endpoints.push(("user_service", url.clone()));
+    }
+    let health = Arc::new(HealthCheck {
+        database: database.clone(),
+        http_client: reqwest::Client::builder().timeout(config.readiness_timeout()).build()?,
+        endpoints,
+        timeout: config.readiness_timeout(),
+        stopping: stopping_rx,
+    });
+
+    let app = routes::create_router(cart_service.clone(), health).layer(TimeoutLayer::new(config.request_timeout()));
+
+    // Stop accepting connections once the signal arrives and let in-flight requests finish
+    let server = Server::bind(&config.server_address)
         .serve(app.into_make_service())
-        .await?;
+        .with_graceful_shutdown(shutting_down(shutdown_rx.clone()));
+    tokio::pin!(server);
+
+    info!("Server running on http://{}", config.server_address);
+    tokio::select! {
+        result = &mut server => result?,
+        _ = shutting_down(shutdown_rx) => {
+            if tokio::time::timeout(config.shutdown_timeout(), &mut server).await.is_err() {
+                warn!("Requests still running after {}s, stopping anyway", config.shutdown_timeout_secs);
+            }
+        }
+    }
+
+    // Let the workers finish the run they are in, then publish what is left in the outbox
+    for worker in workers {
+        if tokio::time::timeout(config.shutdown_timeout(), worker).await.is_err() {
+            warn!("Background worker did not stop within {}s", config.shutdown_timeout_secs);
+        }
+    }
+    if config.outbox_relay_enabled {
+        match cart_service.flush_outbox().await {
+            Ok(count) => info!("Published {} cart events before shutdown", count),
+            Err(err) => error!("Failed to flush cart events: {:?}", err),
+        }
+    }
+
+    database.close().await;
+    info!("Shutdown complete");
 
     Ok(())
 }
 
-#[derive(Serialize)]
-struct User {
-    id: Uuid,
-    name: String,
-    email: String,
-}
+/// Resolve on the first SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for SIGINT: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
 
-async fn get_user(
-    Path(user_id): Path<Uuid>,
-    State(app_state): State<AppState>,
-) -> impl IntoResponse {
-    let result = services::user_service::get_user_by_id(&app_state.db_pool, user_id).await;
-
-    match result {
-        Ok(Some(user)) => Json(user).into_response(),
-        Ok(None) => (StatusCode::NOT_FOUND, "User not found").into_response(),
-        Err(err) => {
-            eprintln!("Error fetching user: {:?}", err);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                error!("Failed to listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Resolve once shutdown has begun
+async fn shutting_down(mut shutdown: watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|&stopping| stopping).await;
+}
+
+/// Install the global tracing subscriber in the configured format
+fn init_tracing(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = EnvFilter::try_new(&config.log_level)?;
+
+    match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt().with_env_filter(filter).init(),
+        LogFormat::Json => tracing_subscriber::fmt().json().with_env_filter(filter).init(),
+    }
+
+    Ok(())
+}
+
+/// Assemble the CartService and its collaborators from the configuration
+fn build_cart_service(config: &Config, database: &Database) -> Result<CartService, Box<dyn std::error::Error>> {
+    let http_client = reqwest::Client::builder()
+        .timeout(config.upstream_timeout())
+        .build()?;
+
+    let catalog = Arc::new(HttpProductCatalog::with_client(
+        &config.product_service_url,
+        &config.product_service_api_key,
+        http_client.clone(),
+    ));
+
+    let exchange_rates: Arc<dyn ExchangeRateProvider> = match &config.exchange_rates_url {
+        Some(url) => Arc::new(HttpExchangeRates::with_client(
+            url,
+            Duration::from_secs(config.exchange_rates_cache_secs),
+            http_client,
+        )),
+        None => Arc::new(StaticExchangeRates::from_file(&config.exchange_rates_file)?),
+    };
+
+    let publisher = config
+        .events_ndjson_path
+        .as_ref()
+        .map(|path| Arc::new(NdjsonFilePublisher::new(path)) as Arc<dyn EventPublisher>);
+
+    Ok(CartService {
+        repository: database.repository(),
+        cart_token_secret: config.cart_token_secret.clone(),
+        jwt_secret: config.jwt_secret.clone(),
+        internal_api_key: config.internal_api_key.clone(),
+        merge_strategy: config.merge_strategy,
+        catalog: catalog.clone(),
+        pricing: PricingEngine {
+            flat_shipping: config.flat_shipping,
+        },
+        limits: CartLimits {
+            max_lines: config.max_cart_lines,
+            max_quantity_per_product: config.max_quantity_per_product,
+        },
+        stock_ledger: catalog,
+        reservation_ttl: chrono::Duration::seconds(config.reservation_ttl_secs as i64),
+        notifier: Arc::new(LogNotifier),
+        idempotency_ttl: chrono::Duration::seconds(config.idempotency_ttl_secs as i64),
+        publisher,
+        catalog_currency: config.catalog_currency,
+        default_currency: config.default_currency,
+        exchange_rates,
+        shipping: Arc::new(TableShippingCalculator::from_file(&config.shipping_rates_file)?),
+        tax_rules: Arc::new(TaxRulesStore::from_file(&config.tax_rules_file)?),
+        default_tax_country: config.default_tax_country.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Router;
+    use std::net::SocketAddr;
+
+    #[tokio::test]
+    async fn in_flight_requests_finish_after_shutdown_begins() {
+        let app = Router::new().route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                "done"
+            }),
+        );
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(app.into_make_service());
+        let url = format!("http://{}/slow", server.local_addr());
+        let server = tokio::spawn(server.with_graceful_shutdown(shutting_down(shutdown_rx)));
+
+        let request = tokio::spawn(reqwest::get(url.clone()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown_tx.send(true).unwrap();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        tokio::time::timeout(Duration::from_secs(1), server)
+            .await
+            .expect("server did not stop after draining")
+            .unwrap()
+            .unwrap();
+        assert!(reqwest::get(url).await.is_err());
     }
 }
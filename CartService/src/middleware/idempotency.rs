@@ -0,0 +1,298 @@
+use axum::{
+    body::{self, Body, Bytes, HttpBody},
+    extract::State,
+    http::{HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use http_body::{LengthLimitError, Limited};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::error;
+use crate::repository::StoredResponse;
+use crate::services::CartService;
+use crate::utils::cart_token::CART_TOKEN_HEADER;
+
+/// Header clients use to make a mutating request safe to retry
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Largest response body stored for replay; larger responses are not made idempotent
+const MAX_STORED_RESPONSE_BYTES: u64 = 64 * 1024;
+
+/// Largest request body buffered to fingerprint it, the same as axum's default extractor limit
+const MAX_REQUEST_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Replays the stored response for a repeated `Idempotency-Key`, and rejects a key
+/// reused with a different request body with 422.
+pub async fn idempotency_middleware(
+    State(cart_service): State<Arc<CartService>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let is_mutating = matches!(
+        *req.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    );
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) if is_mutating => key.to_str().unwrap_or_default().to_string(),
+        _ => return next.run(req).await,
+    };
+
+    // Keys are scoped to the caller and the endpoint so they cannot collide across users.
+    // Signed-in users are identified by their token and guests by their cart token. A guest's
+    // first add has no cart token yet, so it is keyed on the Idempotency-Key alone; the replay
+    // carries the cart token issued the first time, so a retry lands in the same cart.
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let authorization = header("Authorization");
+    let cart_token = header(CART_TOKEN_HEADER);
+
+    let (parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(Limited::new(body, MAX_REQUEST_BODY_BYTES)).await {
+        Ok(body) => body,
+        Err(err) if err.is::<LengthLimitError>() => {
+            return (StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large").into_response()
+        }
+        Err(_) => return (StatusCode::BAD_REQUEST, "Failed to read request body").into_response(),
+    };
+
+    let key_hash = sha256_hex(&[
+        key.as_bytes(),
+        parts.method.as_str().as_bytes(),
+        parts.uri.path().as_bytes(),
+        authorization.as_bytes(),
+        cart_token.as_bytes(),
+    ]);
+    let fingerprint = sha256_hex(&[&body]);
+
+    match claim_key(&cart_service, &key_hash, &fingerprint).await {
+        Ok(Claim::Acquired) => {}
+        Ok(Claim::Replay(response)) => return response,
+        Ok(Claim::Mismatch) => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key was already used with a different request",
+            )
+                .into_response()
+        }
+        Ok(Claim::InFlight) => {
+            return (
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still being processed",
+            )
+                .into_response()
+        }
+        Err(err) => {
+            error!("Idempotency lookup failed: {:?}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response();
+        }
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    // Only buffer bodies of a known, bounded size; release the key for anything larger
+    let too_large = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_none_or(|size| size > MAX_STORED_RESPONSE_BYTES);
+    if too_large {
+        if let Err(err) = release_key(&cart_service, &key_hash).await {
+            error!("Failed to release idempotency key: {:?}", err);
+        }
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    if let Err(err) = store_response(&cart_service, &key_hash, parts.status, &parts.headers, &body).await {
+        error!("Failed to store idempotent response: {:?}", err);
+    }
+
+    Response::from_parts(parts, body::boxed(Body::from(body)))
+}
+
+enum Claim {
+    Acquired,
+    Replay(Response),
+    Mismatch,
+    InFlight,
+}
+
+fn sha256_hex(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Reserve the key for this request, or find the earlier request that used it
+async fn claim_key(
+    cart_service: &CartService,
+    key_hash: &str,
+    fingerprint: &str,
+) -> Result<Claim, sqlx::Error> {
+    let now = Utc::now();
+
+    // The claim is committed before the request runs so concurrent retries see it
+    let mut tx = cart_service.repository.begin().await?;
+    let claimed = tx
+        .claim_idempotency_key(key_hash, fingerprint, now, now + cart_service.idempotency_ttl)
+        .await?;
+    let stored = if claimed { None } else { tx.idempotency_key(key_hash).await? };
+    tx.commit().await?;
+
+    if claimed {
+        return Ok(Claim::Acquired);
+    }
+    let stored = stored.ok_or(sqlx::Error::RowNotFound)?;
+
+    if stored.fingerprint != fingerprint {
+        return Ok(Claim::Mismatch);
+    }
+
+    let stored = match stored.response {
+        Some(response) => response,
+        None => return Ok(Claim::InFlight),
+    };
+
+    let mut response = Response::new(body::boxed(Body::from(stored.body)));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            response.headers_mut().append(name, value);
+        }
+    }
+    response
+        .headers_mut()
+        .insert("idempotent-replayed", HeaderValue::from_static("true"));
+
+    Ok(Claim::Replay(response))
+}
+
+// Forget the key so a retry runs the request again
+async fn release_key(cart_service: &CartService, key_hash: &str) -> Result<(), sqlx::Error> {
+    let mut tx = cart_service.repository.begin().await?;
+    tx.delete_idempotency_key(key_hash).await?;
+    tx.commit().await
+}
+
+// Save the response so retries can replay it; server errors release the key instead
+async fn store_response(
+    cart_service: &CartService,
+    key_hash: &str,
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &Bytes,
+) -> Result<(), sqlx::Error> {
+    if status.is_server_error() {
+        return release_key(cart_service, key_hash).await;
+    }
+
+    let headers: Vec<(String, String)> = headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+
+    let mut tx = cart_service.repository.begin().await?;
+    tx.save_idempotent_response(
+        key_hash,
+        &StoredResponse {
+            status: status.as_u16(),
+            headers,
+            body: body.to_vec(),
+        },
+    )
+    .await?;
+
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+    use serde_json::json;
+
+    use crate::services::testing::{cart_service, product, spawn_app};
+
+    #[tokio::test]
+    async fn a_retried_guest_add_is_replayed_into_the_same_cart() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let url = spawn_app(cart_service([product.clone()])).await;
+        let client = reqwest::Client::new();
+        let add = || {
+            client
+                .post(format!("{}/guest-cart/add", url))
+                .header(IDEMPOTENCY_KEY_HEADER, "add-once")
+                .json(&json!({ "product_id": product.id, "quantity": 2 }))
+                .send()
+        };
+
+        let first = add().await.unwrap();
+        let retry = add().await.unwrap();
+
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers()["idempotent-replayed"], "true");
+        let token = first.headers()[CART_TOKEN_HEADER].clone();
+        assert_eq!(retry.headers()[CART_TOKEN_HEADER], token);
+
+        let cart: serde_json::Value = client
+            .get(format!("{}/guest-cart", url))
+            .header(CART_TOKEN_HEADER, token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(cart["lines"].as_array().unwrap().len(), 1);
+        assert_eq!(cart["lines"][0]["quantity"], 2);
+    }
+
+    #[tokio::test]
+    async fn a_key_reused_with_a_different_body_is_rejected() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let url = spawn_app(cart_service([product.clone()])).await;
+        let client = reqwest::Client::new();
+        let add = |quantity: u32| {
+            client
+                .post(format!("{}/guest-cart/add", url))
+                .header(IDEMPOTENCY_KEY_HEADER, "add-once")
+                .json(&json!({ "product_id": product.id, "quantity": quantity }))
+                .send()
+        };
+
+        assert_eq!(add(1).await.unwrap().status(), StatusCode::CREATED);
+        assert_eq!(add(2).await.unwrap().status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected_before_they_are_buffered() {
+        let url = spawn_app(cart_service([])).await;
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/guest-cart/add", url))
+            .header(IDEMPOTENCY_KEY_HEADER, "too-large")
+            .header("content-type", "application/json")
+            .body(vec![b' '; MAX_REQUEST_BODY_BYTES + 1])
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -1,81 +1,110 @@
+pub mod idempotency;
+
 use axum::{
     body::Body,
-    http::{Request, StatusCode},
+    extract::{Path, State},
+    http::{header, Request, StatusCode},
     middleware::Next,
-    response::{Response, IntoResponse},
-    extract::State,
+    response::{IntoResponse, Response},
 };
-use futures::future::{BoxFuture, FutureExt};
-use sqlx::MySqlPool;
-use chrono::Utc;
-use tracing::info;
-use crate::services::user_service::authenticate;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+use crate::services::CartService;
+use crate::utils::jwt;
 
-#[derive(Clone)]
-pub struct AppState {
-    pub db_pool: MySqlPool,
-}
+/// Logs each request and the status it was answered with
+pub async fn logger_middleware(req: Request<Body>, next: Next<Body>) -> Response {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
 
-pub struct LoggerMiddleware;
+    // Log the request details
+    info!("Incoming request: {} {}", method, path);
 
-impl LoggerMiddleware {
-    pub async fn handle<B>(
-        req: Request<B>,
-        next: Next<B>,
-        State(app_state): State<AppState>,
-    ) -> Response {
-        let method = req.method().clone();
-        let path = req.uri().path().to_string();
+    let response = next.run(req).await;
 
-        // Log the request details
-        info!("Incoming request: {} {}", method, path);
+    // Log the response status
+    info!("Response status: {}", response.status());
 
-        // Check for authentication token (if needed)
-        if let Some(auth_header) = req.headers().get("Authorization") {
-            let token = auth_header.to_str().unwrap_or_default().trim_start_matches("Bearer ");
+    response
+}
 
-            match authenticate(&app_state.db_pool, token).await {
-                Ok(is_valid) if is_valid => {
-                    // Proceed if token is valid
-                    let response = next.run(req).await;
+/// Header internal callers such as payment and fulfilment use to authenticate
+pub const INTERNAL_API_KEY_HEADER: &str = "x-internal-api-key";
 
-                    // Log the response status
-                    let status = response.status();
-                    info!("Response status: {}", status);
+/// Rejects requests without a valid, unrevoked bearer token issued by UserService,
+/// and requests for another user's `:user_id` with 403
+pub async fn auth_middleware(
+    State(cart_service): State<Arc<CartService>>,
+    params: Option<Path<HashMap<String, String>>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let token = match req.headers().get(header::AUTHORIZATION) {
+        Some(value) => value.to_str().unwrap_or_default().trim_start_matches("Bearer ").to_string(),
+        None => {
+            info!("No Authorization header found.");
+            return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+        }
+    };
 
-                    return response;
-                }
-                Ok(_) => {
-                    info!("Authentication failed for token: {}", token);
-                    return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
-                }
-                Err(err) => {
-                    eprintln!("Error during authentication: {:?}", err);
-                    return (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response();
-                }
-            }
+    let claims = match jwt::validate_token(&token, &cart_service.jwt_secret) {
+        Ok(claims) => claims,
+        Err(err) => {
+            info!("Authentication failed: {}", err);
+            return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
         }
+    };
 
-        // If no Authorization header is present
-        info!("No Authorization header found.");
-        (StatusCode::UNAUTHORIZED, "Unauthorized").into_response()
+    // Users may only act on their own carts and orders
+    if let Some(user_id) = params.as_ref().and_then(|Path(params)| params.get("user_id")) {
+        let subject = claims.sub.parse::<Uuid>().ok();
+        if subject.is_none() || user_id.parse::<Uuid>().ok() != subject {
+            info!("Rejected a request for another user's resources");
+            return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+        }
+    }
+
+    match cart_service.is_token_blacklisted(&token).await {
+        Ok(false) => next.run(req).await,
+        Ok(true) => {
+            info!("Rejected a revoked token");
+            (StatusCode::UNAUTHORIZED, "Unauthorized").into_response()
+        }
+        Err(err) => {
+            error!("Error during authentication: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+        }
     }
 }
 
-pub async fn is_token_blacklisted(
-    db_pool: &MySqlPool,
-    token: &str,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query_scalar!(
-        r#"
-        SELECT COUNT(*) FROM token_blacklist 
-        WHERE token = ? AND expires_at > ?
-        "#,
-        token,
-        Utc::now()
-    )
-    .fetch_one(db_pool)
-    .await?;
+/// Rejects requests without the internal API key; every request is rejected when none is configured
+pub async fn internal_auth_middleware(
+    State(cart_service): State<Arc<CartService>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let expected = match &cart_service.internal_api_key {
+        Some(key) => key,
+        None => {
+            info!("Rejected an internal request: no internal API key is configured");
+            return (StatusCode::FORBIDDEN, "Forbidden").into_response();
+        }
+    };
+
+    let provided = req
+        .headers()
+        .get(INTERNAL_API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    // Compare digests so the comparison takes the same time wherever the keys differ
+    if Sha256::digest(provided.as_bytes()) != Sha256::digest(expected.as_bytes()) {
+        info!("Rejected an internal request with a missing or wrong API key");
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
 
-    Ok(result.unwrap_or(0) > 0)
+    next.run(req).await
 }
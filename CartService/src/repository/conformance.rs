@@ -0,0 +1,498 @@
+//! Behaviour every `CartRepository` backend has to share. The suite runs against the in-memory
+//! backend, and against PostgreSQL and MySQL when `TEST_POSTGRES_URL` or `TEST_MYSQL_URL` is set:
+//!
+//! ```sh
+//! TEST_POSTGRES_URL=postgres://localhost/cart_test cargo test -- --ignored conformance
+//! ```
+//!
+//! Every check works on fresh ids so the suite can rerun against a database that has data in it.
+
+use chrono::{DateTime, Duration, SubsecRound, TimeZone, Utc};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use super::{CartLine, CartRepository, Reservation, SharedCartRecord, StoredResponse};
+use crate::services::currency::Currency;
+use crate::services::inventory::ReservationStatus;
+use crate::services::line_options::LineOptions;
+use crate::services::lists::ItemListKind;
+use crate::services::orders::{Order, OrderLine, OrderStatus};
+use crate::services::outbox::{CartEvent, OutboxMessage};
+use crate::services::sharing::SharedCartItem;
+use crate::services::shipping::{ShippingAddress, ShippingLine};
+
+/// Run every check against a repository
+pub async fn check_repository(repository: &dyn CartRepository) {
+    uncommitted_changes_are_discarded(repository).await;
+    cart_versions_count_changes(repository).await;
+    lines_are_merged_by_product_and_options(repository).await;
+    lines_belong_to_their_cart(repository).await;
+    idle_carts_are_abandoned_once(repository).await;
+    old_carts_are_purged(repository).await;
+    orders_round_trip(repository).await;
+    reservations_hold_stock_until_closed(repository).await;
+    lists_keep_their_entries(repository).await;
+    shared_carts_round_trip(repository).await;
+    events_are_published_once(repository).await;
+    idempotency_keys_are_claimed_once(repository).await;
+}
+
+// Whole seconds, which every backend stores exactly
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(0)
+}
+
+fn engraved(text: &str) -> LineOptions {
+    LineOptions {
+        engraving: Some(text.to_string()),
+        ..LineOptions::default()
+    }
+}
+
+fn line(product_id: Uuid, options: LineOptions, quantity: u32, updated_at: DateTime<Utc>) -> CartLine {
+    CartLine {
+        id: Uuid::new_v4(),
+        product_id,
+        options,
+        quantity,
+        updated_at,
+    }
+}
+
+fn order(user_id: Uuid, product_id: Uuid, quantity: u32, now: DateTime<Utc>) -> Order {
+    let total = Decimal::from(quantity);
+
+    Order {
+        id: Uuid::new_v4(),
+        user_id,
+        status: OrderStatus::PendingPayment,
+        currency: Currency::Eur,
+        lines: vec![OrderLine {
+            id: Uuid::new_v4(),
+            product_id,
+            name: "Widget".to_string(),
+            options: LineOptions::default(),
+            quantity,
+            unit_price: Decimal::ONE,
+            line_total: total,
+        }],
+        shipping: ShippingLine {
+            method: "standard".to_string(),
+            name: "Standard".to_string(),
+            address: ShippingAddress {
+                country: "DE".to_string(),
+                region: None,
+                postal_code: Some("10115".to_string()),
+            },
+            price: Decimal::ZERO,
+        },
+        discounts: Vec::new(),
+        subtotal: total,
+        discount_total: Decimal::ZERO,
+        tax_total: Decimal::ZERO,
+        tax: None,
+        shipping_total: Decimal::ZERO,
+        grand_total: total,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+async fn uncommitted_changes_are_discarded(repository: &dyn CartRepository) {
+    let user_id = Uuid::new_v4();
+
+    let mut tx = repository.begin().await.unwrap();
+    tx.touch_cart(user_id, now()).await.unwrap();
+    tx.insert_line(user_id, &line(Uuid::new_v4(), LineOptions::default(), 1, now()))
+        .await
+        .unwrap();
+    drop(tx);
+
+    let mut tx = repository.begin().await.unwrap();
+    assert!(tx.cart(user_id).await.unwrap().is_none());
+    assert!(tx.cart_lines(user_id, false).await.unwrap().is_empty());
+
+    tx.touch_cart(user_id, now()).await.unwrap();
+    tx.commit().await.unwrap();
+
+    let mut tx = repository.begin().await.unwrap();
+    assert_eq!(tx.cart(user_id).await.unwrap().unwrap().version, 1);
+}
+
+async fn cart_versions_count_changes(repository: &dyn CartRepository) {
+    let user_id = Uuid::new_v4();
+    let mut tx = repository.begin().await.unwrap();
+
+    assert_eq!(tx.lock_cart_version(user_id).await.unwrap(), None);
+    assert_eq!(tx.touch_cart(user_id, now()).await.unwrap(), 1);
+    assert_eq!(tx.touch_cart(user_id, now()).await.unwrap(), 2);
+    assert_eq!(tx.lock_cart_version(user_id).await.unwrap(), Some(2));
+
+    assert_eq!(tx.cart(user_id).await.unwrap().unwrap().currency, None);
+    tx.set_cart_currency(user_id, Currency::Gbp).await.unwrap();
+    assert_eq!(tx.cart(user_id).await.unwrap().unwrap().currency.as_deref(), Some("GBP"));
+
+    tx.delete_cart(user_id).await.unwrap();
+    assert!(tx.cart(user_id).await.unwrap().is_none());
+    tx.commit().await.unwrap();
+}
+
+async fn lines_are_merged_by_product_and_options(repository: &dyn CartRepository) {
+    let user_id = Uuid::new_v4();
+    let product_id = Uuid::new_v4();
+    let earlier = now() - Duration::seconds(10);
+    let mut tx = repository.begin().await.unwrap();
+    tx.touch_cart(user_id, earlier).await.unwrap();
+
+    tx.add_to_line(user_id, product_id, &LineOptions::default(), 1, earlier)
+        .await
+        .unwrap();
+    tx.add_to_line(user_id, product_id, &engraved("For Sam"), 1, now())
+        .await
+        .unwrap();
+    tx.add_to_line(user_id, product_id, &LineOptions::default(), 2, earlier)
+        .await
+        .unwrap();
+
+    let lines = tx.cart_lines(user_id, true).await.unwrap();
+    let quantities: Vec<(LineOptions, u32)> = lines.iter().map(|line| (line.options.clone(), line.quantity)).collect();
+    assert_eq!(quantities, vec![(LineOptions::default(), 3), (engraved("For Sam"), 1)]);
+
+    let plain = &lines[0];
+    let fetched = tx.cart_line(user_id, plain.id, true).await.unwrap().unwrap();
+    assert_eq!(fetched.product_id, product_id);
+    assert_eq!(fetched.quantity, 3);
+    tx.commit().await.unwrap();
+}
+
+async fn lines_belong_to_their_cart(repository: &dyn CartRepository) {
+    let (owner, other) = (Uuid::new_v4(), Uuid::new_v4());
+    let first = line(Uuid::new_v4(), LineOptions::default(), 1, now() - Duration::seconds(10));
+    let second = line(Uuid::new_v4(), engraved("Hi"), 2, now());
+    let mut tx = repository.begin().await.unwrap();
+    tx.touch_cart(owner, now()).await.unwrap();
+    tx.touch_cart(other, now()).await.unwrap();
+    tx.insert_line(owner, &first).await.unwrap();
+    tx.insert_line(owner, &second).await.unwrap();
+
+    assert!(tx.cart_line(other, first.id, false).await.unwrap().is_none());
+    assert!(!tx.set_line_quantity(other, first.id, 5, now()).await.unwrap());
+    assert!(tx.set_line_quantity(owner, first.id, 5, first.updated_at).await.unwrap());
+    assert_eq!(tx.cart_line(owner, first.id, false).await.unwrap().unwrap().quantity, 5);
+
+    tx.move_line(second.id, other).await.unwrap();
+    let moved = tx.cart_lines(other, false).await.unwrap();
+    assert_eq!(moved.len(), 1);
+    assert_eq!((moved[0].id, moved[0].options.clone(), moved[0].quantity), (second.id, engraved("Hi"), 2));
+
+    tx.delete_line(first.id).await.unwrap();
+    assert!(tx.cart_lines(owner, false).await.unwrap().is_empty());
+    tx.delete_lines(other).await.unwrap();
+    assert!(tx.cart_lines(other, false).await.unwrap().is_empty());
+    tx.commit().await.unwrap();
+}
+
+async fn idle_carts_are_abandoned_once(repository: &dyn CartRepository) {
+    let (idle, empty, busy) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let last_week = now() - Duration::days(7);
+    let mut tx = repository.begin().await.unwrap();
+    for user_id in [idle, empty] {
+        tx.touch_cart(user_id, last_week).await.unwrap();
+    }
+    tx.touch_cart(busy, now()).await.unwrap();
+    for user_id in [idle, busy] {
+        tx.add_to_line(user_id, Uuid::new_v4(), &LineOptions::default(), 2, last_week)
+            .await
+            .unwrap();
+    }
+
+    let idle_carts = tx.idle_carts(now() - Duration::days(1)).await.unwrap();
+    let ours: Vec<_> = idle_carts
+        .iter()
+        .filter(|cart| [idle, empty, busy].contains(&cart.user_id))
+        .collect();
+    assert_eq!(ours.len(), 1);
+    assert_eq!((ours[0].user_id, ours[0].item_count), (idle, 2));
+    assert_eq!(ours[0].last_activity_at, last_week);
+
+    // A cart that saw activity since it was found idle is left alone
+    assert!(!tx.mark_cart_abandoned(idle, last_week - Duration::hours(1), now()).await.unwrap());
+    assert!(tx.mark_cart_abandoned(idle, last_week, now()).await.unwrap());
+    assert!(!tx.mark_cart_abandoned(idle, last_week, now()).await.unwrap());
+    let still_idle = tx.idle_carts(now() - Duration::days(1)).await.unwrap();
+    assert!(still_idle.iter().all(|cart| cart.user_id != idle));
+
+    // Activity revives an abandoned cart
+    tx.touch_cart(idle, last_week + Duration::hours(1)).await.unwrap();
+    let revived = tx.idle_carts(now() - Duration::days(1)).await.unwrap();
+    assert!(revived.iter().any(|cart| cart.user_id == idle));
+    tx.commit().await.unwrap();
+}
+
+async fn old_carts_are_purged(repository: &dyn CartRepository) {
+    let (old, recent) = (Uuid::new_v4(), Uuid::new_v4());
+    let long_ago = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+    let mut tx = repository.begin().await.unwrap();
+    tx.touch_cart(old, long_ago).await.unwrap();
+    tx.add_to_line(old, Uuid::new_v4(), &LineOptions::default(), 1, long_ago)
+        .await
+        .unwrap();
+    tx.touch_cart(recent, long_ago + Duration::days(2)).await.unwrap();
+
+    assert!(tx.purge_carts(long_ago + Duration::days(1)).await.unwrap() >= 1);
+    assert!(tx.cart(old).await.unwrap().is_none());
+    assert!(tx.cart_lines(old, false).await.unwrap().is_empty());
+    assert!(tx.cart(recent).await.unwrap().is_some());
+    tx.delete_cart(recent).await.unwrap();
+    tx.commit().await.unwrap();
+}
+
+async fn orders_round_trip(repository: &dyn CartRepository) {
+    let (user_id, product_id) = (Uuid::new_v4(), Uuid::new_v4());
+    let order = order(user_id, product_id, 3, now());
+    let mut tx = repository.begin().await.unwrap();
+    tx.insert_order(&order).await.unwrap();
+
+    assert!(tx.order(Uuid::new_v4(), order.id).await.unwrap().is_none());
+    let stored = tx.order(user_id, order.id).await.unwrap().unwrap();
+    assert_eq!(stored.status, OrderStatus::PendingPayment);
+    assert_eq!(stored.currency, Currency::Eur);
+    assert_eq!(stored.lines.len(), 1);
+    assert_eq!((stored.lines[0].product_id, stored.lines[0].quantity), (product_id, 3));
+    assert_eq!(stored.grand_total, order.grand_total);
+    assert_eq!(stored.shipping.address.country, "DE");
+    assert_eq!(tx.purchased_quantity(user_id, product_id).await.unwrap(), 3);
+
+    tx.set_order_status(order.id, OrderStatus::Cancelled, now()).await.unwrap();
+    assert_eq!(
+        tx.lock_order_status(user_id, order.id).await.unwrap(),
+        Some(OrderStatus::Cancelled)
+    );
+    assert_eq!(tx.purchased_quantity(user_id, product_id).await.unwrap(), 0);
+    tx.commit().await.unwrap();
+}
+
+async fn reservations_hold_stock_until_closed(repository: &dyn CartRepository) {
+    let (shopper, other_shopper, product_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let (held, lapsed) = (order(shopper, product_id, 2, now()), order(other_shopper, product_id, 1, now()));
+    let reservation = |order: &Order, expires_at| Reservation {
+        id: Uuid::new_v4(),
+        order_id: order.id,
+        user_id: order.user_id,
+        product_id,
+        quantity: order.lines[0].quantity,
+        created_at: now(),
+        expires_at,
+    };
+    let mut tx = repository.begin().await.unwrap();
+    tx.insert_order(&held).await.unwrap();
+    tx.insert_order(&lapsed).await.unwrap();
+    tx.insert_reservation(&reservation(&held, now() + Duration::minutes(15)))
+        .await
+        .unwrap();
+    tx.insert_reservation(&reservation(&lapsed, now() - Duration::minutes(1)))
+        .await
+        .unwrap();
+
+    assert_eq!(tx.reserved_quantity(product_id, None, now(), true).await.unwrap(), 2);
+    assert_eq!(tx.reserved_quantity(product_id, Some(shopper), now(), false).await.unwrap(), 0);
+
+    assert!(tx.expire_reservations(now()).await.unwrap() >= 1);
+    assert!(tx.active_reservations(lapsed.id).await.unwrap().is_empty());
+    assert_eq!(
+        tx.lock_order_status(other_shopper, lapsed.id).await.unwrap(),
+        Some(OrderStatus::Cancelled)
+    );
+    assert_eq!(
+        tx.lock_order_status(shopper, held.id).await.unwrap(),
+        Some(OrderStatus::PendingPayment)
+    );
+
+    let active = tx.active_reservations(held.id).await.unwrap();
+    assert_eq!(active.len(), 1);
+    assert_eq!((active[0].product_id, active[0].quantity), (product_id, 2));
+    tx.close_reservations(held.id, ReservationStatus::Consumed).await.unwrap();
+    assert!(tx.active_reservations(held.id).await.unwrap().is_empty());
+    assert_eq!(tx.reserved_quantity(product_id, None, now(), false).await.unwrap(), 0);
+    tx.commit().await.unwrap();
+}
+
+async fn lists_keep_their_entries(repository: &dyn CartRepository) {
+    let (user_id, list_id, product_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let mut tx = repository.begin().await.unwrap();
+    tx.insert_list(list_id, user_id, "Birthday", ItemListKind::Wishlist, now())
+        .await
+        .unwrap();
+
+    assert!(tx.find_list(user_id, list_id).await.unwrap());
+    assert!(!tx.find_list(Uuid::new_v4(), list_id).await.unwrap());
+    assert_eq!(tx.list_of_kind(user_id, ItemListKind::Wishlist).await.unwrap(), Some(list_id));
+    assert_eq!(tx.list_of_kind(user_id, ItemListKind::SavedForLater).await.unwrap(), None);
+
+    tx.add_to_list(list_id, product_id, &LineOptions::default(), 1, now())
+        .await
+        .unwrap();
+    tx.add_to_list(list_id, product_id, &LineOptions::default(), 2, now())
+        .await
+        .unwrap();
+    assert!(!tx
+        .rename_list(user_id, list_id, ItemListKind::SavedForLater, "Later")
+        .await
+        .unwrap());
+    assert!(tx.rename_list(user_id, list_id, ItemListKind::Wishlist, "Holidays").await.unwrap());
+
+    let lists = tx.item_lists(user_id).await.unwrap();
+    assert_eq!(lists.len(), 1);
+    assert_eq!((lists[0].name.as_str(), lists[0].kind), ("Holidays", ItemListKind::Wishlist));
+    assert_eq!(lists[0].entries.len(), 1);
+    let entry = &lists[0].entries[0];
+    assert_eq!((entry.product_id, entry.quantity), (product_id, 3));
+
+    assert!(tx.list_entry(Uuid::new_v4(), list_id, entry.id).await.unwrap().is_none());
+    assert!(tx.list_entry(user_id, list_id, entry.id).await.unwrap().is_some());
+    assert!(tx.delete_list_entry(list_id, entry.id).await.unwrap());
+    assert!(!tx.delete_list_entry(list_id, entry.id).await.unwrap());
+
+    assert!(!tx.delete_list(user_id, list_id, ItemListKind::SavedForLater).await.unwrap());
+    assert!(tx.delete_list(user_id, list_id, ItemListKind::Wishlist).await.unwrap());
+    assert!(tx.item_lists(user_id).await.unwrap().is_empty());
+    tx.commit().await.unwrap();
+}
+
+async fn shared_carts_round_trip(repository: &dyn CartRepository) {
+    let shared = SharedCartRecord {
+        id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        items: vec![SharedCartItem {
+            product_id: Uuid::new_v4(),
+            quantity: 2,
+            options: engraved("Cheers"),
+        }],
+        currency: Currency::Chf,
+        created_at: now(),
+        expires_at: Some(now() + Duration::days(30)),
+    };
+    let mut tx = repository.begin().await.unwrap();
+    tx.insert_shared_cart(&shared).await.unwrap();
+
+    let stored = tx.shared_cart(shared.id).await.unwrap().unwrap();
+    assert_eq!(stored.user_id, shared.user_id);
+    assert_eq!(stored.currency, Currency::Chf);
+    assert_eq!(stored.expires_at, shared.expires_at);
+    assert_eq!(stored.items.len(), 1);
+    assert_eq!(stored.items[0].quantity, 2);
+    assert_eq!(stored.items[0].options, engraved("Cheers"));
+    assert!(tx.shared_cart(Uuid::new_v4()).await.unwrap().is_none());
+    tx.commit().await.unwrap();
+}
+
+async fn events_are_published_once(repository: &dyn CartRepository) {
+    let user_id = Uuid::new_v4();
+    let events: Vec<OutboxMessage> = (0..2)
+        .map(|_| OutboxMessage {
+            id: Uuid::new_v4(),
+            occurred_at: now(),
+            event: CartEvent::CartCleared { user_id },
+        })
+        .collect();
+    let ours = |messages: Vec<OutboxMessage>| -> Vec<Uuid> {
+        messages
+            .into_iter()
+            .filter(|message| events.iter().any(|event| event.id == message.id))
+            .map(|message| message.id)
+            .collect()
+    };
+
+    let mut tx = repository.begin().await.unwrap();
+    for event in &events {
+        tx.record_event(event).await.unwrap();
+    }
+    tx.commit().await.unwrap();
+
+    let mut tx = repository.begin().await.unwrap();
+    let pending = ours(tx.unpublished_events(10_000).await.unwrap());
+    assert_eq!(pending, vec![events[0].id, events[1].id]);
+    tx.mark_event_published(events[0].id, now()).await.unwrap();
+    tx.commit().await.unwrap();
+
+    let mut tx = repository.begin().await.unwrap();
+    assert_eq!(ours(tx.unpublished_events(10_000).await.unwrap()), vec![events[1].id]);
+    tx.mark_event_published(events[1].id, now()).await.unwrap();
+    tx.commit().await.unwrap();
+}
+
+async fn idempotency_keys_are_claimed_once(repository: &dyn CartRepository) {
+    let (key, lapsed_key) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+    let response = StoredResponse {
+        status: 201,
+        headers: vec![("content-type".to_string(), "application/json".to_string())],
+        body: br#"{"ok":true}"#.to_vec(),
+    };
+    let mut tx = repository.begin().await.unwrap();
+    let expires_at = now() + Duration::hours(1);
+
+    assert!(tx.claim_idempotency_key(&key, "first", now(), expires_at).await.unwrap());
+    assert!(!tx.claim_idempotency_key(&key, "second", now(), expires_at).await.unwrap());
+    let claimed = tx.idempotency_key(&key).await.unwrap().unwrap();
+    assert_eq!(claimed.fingerprint, "first");
+    assert!(claimed.response.is_none());
+
+    tx.save_idempotent_response(&key, &response).await.unwrap();
+    let stored = tx.idempotency_key(&key).await.unwrap().unwrap().response.unwrap();
+    assert_eq!(stored.status, 201);
+    assert_eq!(stored.headers, response.headers);
+    assert_eq!(stored.body, response.body);
+
+    tx.delete_idempotency_key(&key).await.unwrap();
+    assert!(tx.idempotency_key(&key).await.unwrap().is_none());
+
+    // An expired key can be claimed again
+    assert!(tx
+        .claim_idempotency_key(&lapsed_key, "first", now() - Duration::hours(2), now() - Duration::hours(1))
+        .await
+        .unwrap());
+    assert!(tx.claim_idempotency_key(&lapsed_key, "second", now(), expires_at).await.unwrap());
+    assert_eq!(tx.idempotency_key(&lapsed_key).await.unwrap().unwrap().fingerprint, "second");
+    tx.commit().await.unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{MySqlPool, PgPool};
+
+    use crate::db::Database;
+    use crate::repository::InMemoryCartRepository;
+
+    fn database_url(var: &str) -> String {
+        std::env::var(var).unwrap_or_else(|_| panic!("{} is not set", var))
+    }
+
+    async fn migrated(database: Database) -> Database {
+        database.migrate_up().await.unwrap();
+        database
+    }
+
+    #[tokio::test]
+    async fn memory_repository_conforms() {
+        check_repository(&InMemoryCartRepository::new()).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL database in TEST_POSTGRES_URL"]
+    async fn postgres_repository_conforms() {
+        let pool = PgPool::connect(&database_url("TEST_POSTGRES_URL")).await.unwrap();
+        let database = migrated(Database::Postgres(pool)).await;
+
+        check_repository(&*database.repository()).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a MySQL database in TEST_MYSQL_URL"]
+    async fn mysql_repository_conforms() {
+        let pool = MySqlPool::connect(&database_url("TEST_MYSQL_URL")).await.unwrap();
+        let database = migrated(Database::MySql(pool)).await;
+
+        check_repository(&*database.repository()).await;
+    }
+}
@@ -0,0 +1,742 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use uuid::Uuid;
+
+use super::{
+    CartLine, CartRecord, CartRepository, CartTransaction, IdempotencyRecord, IdleCart, Redemptions,
+    Reservation, SharedCartRecord, StoredResponse,
+};
+use crate::services::currency::Currency;
+use crate::services::inventory::ReservationStatus;
+use crate::services::line_options::LineOptions;
+use crate::services::lists::{ItemList, ItemListEntry, ItemListKind};
+use crate::services::orders::{Order, OrderStatus};
+use crate::services::outbox::OutboxMessage;
+use crate::services::promotions::Coupon;
+
+#[derive(Debug, Clone)]
+struct StoredCart {
+    version: u64,
+    last_activity_at: DateTime<Utc>,
+    abandoned: bool,
+    currency: Option<Currency>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredList {
+    id: Uuid,
+    user_id: Uuid,
+    name: String,
+    kind: ItemListKind,
+}
+
+#[derive(Debug, Clone)]
+struct StoredEvent {
+    message: OutboxMessage,
+    published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredIdempotencyKey {
+    fingerprint: String,
+    expires_at: DateTime<Utc>,
+    response: Option<StoredResponse>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MemoryState {
+    carts: HashMap<Uuid, StoredCart>,
+    lines: Vec<(Uuid, CartLine)>, // Keyed by user id, in insertion order
+    coupons: HashMap<String, Coupon>,
+    cart_coupons: Vec<(Uuid, String)>, // In the order they were applied
+    redemptions: Vec<(String, Uuid)>,
+    orders: Vec<Order>,
+    reservations: Vec<(Reservation, ReservationStatus)>,
+    lists: Vec<StoredList>,
+    list_entries: Vec<(Uuid, ItemListEntry)>, // Keyed by list id
+    shared_carts: HashMap<Uuid, SharedCartRecord>,
+    outbox: Vec<StoredEvent>,
+    idempotency_keys: HashMap<String, StoredIdempotencyKey>,
+}
+
+/// Repository that keeps everything in memory, used in tests and local runs.
+/// Transactions run one at a time and work on a copy that replaces the state on commit.
+#[derive(Default, Clone)]
+pub struct InMemoryCartRepository {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl InMemoryCartRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define a coupon; coupons are managed outside CartService
+    #[cfg(test)]
+    pub async fn upsert_coupon(&self, coupon: Coupon) {
+        self.state.lock().await.coupons.insert(coupon.code.clone(), coupon);
+    }
+}
+
+#[async_trait]
+impl CartRepository for InMemoryCartRepository {
+    async fn begin(&self) -> Result<Box<dyn CartTransaction>, sqlx::Error> {
+        let guard = self.state.clone().lock_owned().await;
+        let state = guard.clone();
+
+        Ok(Box::new(InMemoryTransaction { guard, state }))
+    }
+}
+
+struct InMemoryTransaction {
+    guard: OwnedMutexGuard<MemoryState>,
+    state: MemoryState,
+}
+
+impl InMemoryTransaction {
+    fn line_mut(&mut self, item_id: Uuid) -> Option<&mut (Uuid, CartLine)> {
+        self.state.lines.iter_mut().find(|(_, line)| line.id == item_id)
+    }
+}
+
+#[async_trait]
+impl CartTransaction for InMemoryTransaction {
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error> {
+        let InMemoryTransaction { mut guard, state } = *self;
+        *guard = state;
+
+        Ok(())
+    }
+
+    async fn cart(&mut self, user_id: Uuid) -> Result<Option<CartRecord>, sqlx::Error> {
+        Ok(self.state.carts.get(&user_id).map(|cart| CartRecord {
+            version: cart.version,
+            last_activity_at: cart.last_activity_at,
+            currency: cart.currency.map(|currency| currency.code().to_string()),
+        }))
+    }
+
+    async fn lock_cart_version(&mut self, user_id: Uuid) -> Result<Option<u64>, sqlx::Error> {
+        Ok(self.state.carts.get(&user_id).map(|cart| cart.version))
+    }
+
+    async fn touch_cart(&mut self, user_id: Uuid, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let cart = self.state.carts.entry(user_id).or_insert(StoredCart {
+            version: 0,
+            last_activity_at: now,
+            abandoned: false,
+            currency: None,
+        });
+        cart.version += 1;
+        cart.last_activity_at = now;
+        cart.abandoned = false;
+
+        Ok(cart.version)
+    }
+
+    async fn set_cart_currency(&mut self, user_id: Uuid, currency: Currency) -> Result<(), sqlx::Error> {
+        if let Some(cart) = self.state.carts.get_mut(&user_id) {
+            cart.currency = Some(currency);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_cart(&mut self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        self.state.carts.remove(&user_id);
+
+        Ok(())
+    }
+
+    async fn idle_carts(&mut self, before: DateTime<Utc>) -> Result<Vec<IdleCart>, sqlx::Error> {
+        let state = &self.state;
+
+        Ok(state
+            .carts
+            .iter()
+            .filter(|(_, cart)| !cart.abandoned && cart.last_activity_at < before)
+            .filter_map(|(&user_id, cart)| {
+                let item_count = state
+                    .lines
+                    .iter()
+                    .filter(|(owner, _)| *owner == user_id)
+                    .map(|(_, line)| line.quantity)
+                    .fold(0, u32::saturating_add);
+
+                (item_count > 0).then_some(IdleCart {
+                    user_id,
+                    last_activity_at: cart.last_activity_at,
+                    item_count,
+                })
+            })
+            .collect())
+    }
+
+    async fn mark_cart_abandoned(
+        &mut self,
+        user_id: Uuid,
+        last_activity_at: DateTime<Utc>,
+        _now: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        match self.state.carts.get_mut(&user_id) {
+            Some(cart) if !cart.abandoned && cart.last_activity_at == last_activity_at => {
+                cart.abandoned = true;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn purge_carts(&mut self, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let expired: Vec<Uuid> = self
+            .state
+            .carts
+            .iter()
+            .filter(|(_, cart)| cart.last_activity_at < before)
+            .map(|(&user_id, _)| user_id)
+            .collect();
+
+        self.state.lines.retain(|(user_id, _)| !expired.contains(user_id));
+        self.state.cart_coupons.retain(|(user_id, _)| !expired.contains(user_id));
+        for user_id in &expired {
+            self.state.carts.remove(user_id);
+        }
+
+        Ok(expired.len() as u64)
+    }
+
+    async fn cart_lines(&mut self, user_id: Uuid, _lock: bool) -> Result<Vec<CartLine>, sqlx::Error> {
+        Ok(self
+            .state
+            .lines
+            .iter()
+            .filter(|(owner, _)| *owner == user_id)
+            .map(|(_, line)| line.clone())
+            .collect())
+    }
+
+    async fn cart_line(
+        &mut self,
+        user_id: Uuid,
+        item_id: Uuid,
+        _lock: bool,
+    ) -> Result<Option<CartLine>, sqlx::Error> {
+        Ok(self
+            .state
+            .lines
+            .iter()
+            .find(|(owner, line)| *owner == user_id && line.id == item_id)
+            .map(|(_, line)| line.clone()))
+    }
+
+    async fn add_to_line(
+        &mut self,
+        user_id: Uuid,
+        product_id: Uuid,
+        options: &LineOptions,
+        quantity: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let existing = self.state.lines.iter_mut().find(|(owner, line)| {
+            *owner == user_id && line.product_id == product_id && line.options == *options
+        });
+
+        match existing {
+            Some((_, line)) => {
+                line.quantity = line.quantity.saturating_add(quantity);
+                line.updated_at = now;
+            }
+            None => self.state.lines.push((
+                user_id,
+                CartLine {
+                    id: Uuid::new_v4(),
+                    product_id,
+                    options: options.clone(),
+                    quantity,
+                    updated_at: now,
+                },
+            )),
+        }
+
+        Ok(())
+    }
+
+    async fn insert_line(&mut self, user_id: Uuid, line: &CartLine) -> Result<(), sqlx::Error> {
+        self.state.lines.push((user_id, line.clone()));
+
+        Ok(())
+    }
+
+    async fn set_line_quantity(
+        &mut self,
+        user_id: Uuid,
+        item_id: Uuid,
+        quantity: u32,
+        updated_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        match self.line_mut(item_id) {
+            Some((owner, line)) if *owner == user_id => {
+                line.quantity = quantity;
+                line.updated_at = updated_at;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn move_line(&mut self, item_id: Uuid, to_user_id: Uuid) -> Result<(), sqlx::Error> {
+        if let Some((owner, _)) = self.line_mut(item_id) {
+            *owner = to_user_id;
+        }
+
+        Ok(())
+    }
+
+    async fn delete_line(&mut self, item_id: Uuid) -> Result<(), sqlx::Error> {
+        self.state.lines.retain(|(_, line)| line.id != item_id);
+
+        Ok(())
+    }
+
+    async fn delete_lines(&mut self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        self.state.lines.retain(|(owner, _)| *owner != user_id);
+
+        Ok(())
+    }
+
+    async fn applied_coupons(&mut self, user_id: Uuid) -> Result<Vec<Coupon>, sqlx::Error> {
+        Ok(self
+            .state
+            .cart_coupons
+            .iter()
+            .filter(|(owner, _)| *owner == user_id)
+            .filter_map(|(_, code)| self.state.coupons.get(code).cloned())
+            .collect())
+    }
+
+    async fn coupon(&mut self, code: &str) -> Result<Option<Coupon>, sqlx::Error> {
+        Ok(self.state.coupons.get(code).cloned())
+    }
+
+    async fn lock_coupon(&mut self, _code: &str) -> Result<(), sqlx::Error> {
+        // Transactions already run one at a time
+        Ok(())
+    }
+
+    async fn coupon_redemptions(&mut self, code: &str, user_id: Uuid) -> Result<Redemptions, sqlx::Error> {
+        let redemptions = self.state.redemptions.iter().filter(|(c, _)| c == code);
+
+        Ok(redemptions.fold(Redemptions::default(), |mut counts, (_, redeemed_by)| {
+            counts.total += 1;
+            if *redeemed_by == user_id {
+                counts.by_user += 1;
+            }
+            counts
+        }))
+    }
+
+    async fn apply_coupon(&mut self, user_id: Uuid, code: &str, _now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        self.state.cart_coupons.push((user_id, code.to_string()));
+
+        Ok(())
+    }
+
+    async fn remove_coupon(&mut self, user_id: Uuid, code: &str) -> Result<bool, sqlx::Error> {
+        let before = self.state.cart_coupons.len();
+        self.state
+            .cart_coupons
+            .retain(|(owner, c)| !(*owner == user_id && c == code));
+
+        Ok(self.state.cart_coupons.len() < before)
+    }
+
+    async fn clear_coupons(&mut self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        self.state.cart_coupons.retain(|(owner, _)| *owner != user_id);
+
+        Ok(())
+    }
+
+    async fn record_redemption(
+        &mut self,
+        code: &str,
+        user_id: Uuid,
+        _order_id: Uuid,
+        _now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        self.state.redemptions.push((code.to_string(), user_id));
+
+        Ok(())
+    }
+
+    async fn insert_order(&mut self, order: &Order) -> Result<(), sqlx::Error> {
+        self.state.orders.push(order.clone());
+
+        Ok(())
+    }
+
+    async fn order(&mut self, user_id: Uuid, order_id: Uuid) -> Result<Option<Order>, sqlx::Error> {
+        Ok(self
+            .state
+            .orders
+            .iter()
+            .find(|order| order.user_id == user_id && order.id == order_id)
+            .cloned())
+    }
+
+    async fn lock_order_status(
+        &mut self,
+        user_id: Uuid,
+        order_id: Uuid,
+    ) -> Result<Option<OrderStatus>, sqlx::Error> {
+        Ok(self.order(user_id, order_id).await?.map(|order| order.status))
+    }
+
+    async fn set_order_status(
+        &mut self,
+        order_id: Uuid,
+        status: OrderStatus,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        if let Some(order) = self.state.orders.iter_mut().find(|order| order.id == order_id) {
+            order.status = status;
+            order.updated_at = now;
+        }
+
+        Ok(())
+    }
+
+    async fn purchased_quantity(&mut self, user_id: Uuid, product_id: Uuid) -> Result<u32, sqlx::Error> {
+        Ok(self
+            .state
+            .orders
+            .iter()
+            .filter(|order| {
+                order.user_id == user_id
+                    && !matches!(order.status, OrderStatus::Cancelled | OrderStatus::Refunded)
+            })
+            .flat_map(|order| &order.lines)
+            .filter(|line| line.product_id == product_id)
+            .map(|line| line.quantity)
+            .fold(0, u32::saturating_add))
+    }
+
+    async fn reserved_quantity(
+        &mut self,
+        product_id: Uuid,
+        excluding_user: Option<Uuid>,
+        now: DateTime<Utc>,
+        _lock: bool,
+    ) -> Result<u32, sqlx::Error> {
+        Ok(self
+            .state
+            .reservations
+            .iter()
+            .filter(|(reservation, status)| {
+                *status == ReservationStatus::Active
+                    && reservation.product_id == product_id
+                    && reservation.expires_at > now
+                    && Some(reservation.user_id) != excluding_user
+            })
+            .map(|(reservation, _)| reservation.quantity)
+            .fold(0, u32::saturating_add))
+    }
+
+    async fn insert_reservation(&mut self, reservation: &Reservation) -> Result<(), sqlx::Error> {
+        self.state
+            .reservations
+            .push((reservation.clone(), ReservationStatus::Active));
+
+        Ok(())
+    }
+
+    async fn active_reservations(&mut self, order_id: Uuid) -> Result<Vec<Reservation>, sqlx::Error> {
+        Ok(self
+            .state
+            .reservations
+            .iter()
+            .filter(|(reservation, status)| {
+                reservation.order_id == order_id && *status == ReservationStatus::Active
+            })
+            .map(|(reservation, _)| reservation.clone())
+            .collect())
+    }
+
+    async fn close_reservations(&mut self, order_id: Uuid, status: ReservationStatus) -> Result<(), sqlx::Error> {
+        for (reservation, current) in &mut self.state.reservations {
+            if reservation.order_id == order_id && *current == ReservationStatus::Active {
+                *current = status;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn expire_reservations(&mut self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let mut released = 0;
+        let mut expired_orders = Vec::new();
+        for (reservation, status) in &mut self.state.reservations {
+            if *status == ReservationStatus::Active && reservation.expires_at <= now {
+                *status = ReservationStatus::Released;
+                expired_orders.push(reservation.order_id);
+                released += 1;
+            }
+        }
+
+        for order in &mut self.state.orders {
+            if order.status == OrderStatus::PendingPayment && expired_orders.contains(&order.id) {
+                order.status = OrderStatus::Cancelled;
+                order.updated_at = now;
+            }
+        }
+
+        Ok(released)
+    }
+
+    async fn item_lists(&mut self, user_id: Uuid) -> Result<Vec<ItemList>, sqlx::Error> {
+        let state = &self.state;
+
+        Ok(state
+            .lists
+            .iter()
+            .filter(|list| list.user_id == user_id)
+            .map(|list| ItemList {
+                id: list.id,
+                name: list.name.clone(),
+                kind: list.kind,
+                entries: state
+                    .list_entries
+                    .iter()
+                    .filter(|(list_id, _)| *list_id == list.id)
+                    .map(|(_, entry)| entry.clone())
+                    .collect(),
+            })
+            .collect())
+    }
+
+    async fn find_list(&mut self, user_id: Uuid, list_id: Uuid) -> Result<bool, sqlx::Error> {
+        Ok(self
+            .state
+            .lists
+            .iter()
+            .any(|list| list.user_id == user_id && list.id == list_id))
+    }
+
+    async fn list_of_kind(&mut self, user_id: Uuid, kind: ItemListKind) -> Result<Option<Uuid>, sqlx::Error> {
+        Ok(self
+            .state
+            .lists
+            .iter()
+            .find(|list| list.user_id == user_id && list.kind == kind)
+            .map(|list| list.id))
+    }
+
+    async fn insert_list(
+        &mut self,
+        list_id: Uuid,
+        user_id: Uuid,
+        name: &str,
+        kind: ItemListKind,
+        _now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        self.state.lists.push(StoredList {
+            id: list_id,
+            user_id,
+            name: name.to_string(),
+            kind,
+        });
+
+        Ok(())
+    }
+
+    async fn rename_list(
+        &mut self,
+        user_id: Uuid,
+        list_id: Uuid,
+        kind: ItemListKind,
+        name: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let list = self
+            .state
+            .lists
+            .iter_mut()
+            .find(|list| list.user_id == user_id && list.id == list_id && list.kind == kind);
+
+        match list {
+            Some(list) => {
+                list.name = name.to_string();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete_list(&mut self, user_id: Uuid, list_id: Uuid, kind: ItemListKind) -> Result<bool, sqlx::Error> {
+        let before = self.state.lists.len();
+        self.state
+            .lists
+            .retain(|list| !(list.user_id == user_id && list.id == list_id && list.kind == kind));
+        if self.state.lists.len() == before {
+            return Ok(false);
+        }
+
+        self.state.list_entries.retain(|(id, _)| *id != list_id);
+
+        Ok(true)
+    }
+
+    async fn add_to_list(
+        &mut self,
+        list_id: Uuid,
+        product_id: Uuid,
+        options: &LineOptions,
+        quantity: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let existing = self.state.list_entries.iter_mut().find(|(id, entry)| {
+            *id == list_id && entry.product_id == product_id && entry.options == *options
+        });
+
+        match existing {
+            Some((_, entry)) => entry.quantity = entry.quantity.saturating_add(quantity),
+            None => self.state.list_entries.push((
+                list_id,
+                ItemListEntry {
+                    id: Uuid::new_v4(),
+                    product_id,
+                    options: options.clone(),
+                    quantity,
+                    added_at: now,
+                },
+            )),
+        }
+
+        Ok(())
+    }
+
+    async fn list_entry(
+        &mut self,
+        user_id: Uuid,
+        list_id: Uuid,
+        entry_id: Uuid,
+    ) -> Result<Option<ItemListEntry>, sqlx::Error> {
+        if !self.find_list(user_id, list_id).await? {
+            return Ok(None);
+        }
+
+        Ok(self
+            .state
+            .list_entries
+            .iter()
+            .find(|(id, entry)| *id == list_id && entry.id == entry_id)
+            .map(|(_, entry)| entry.clone()))
+    }
+
+    async fn delete_list_entry(&mut self, list_id: Uuid, entry_id: Uuid) -> Result<bool, sqlx::Error> {
+        let before = self.state.list_entries.len();
+        self.state
+            .list_entries
+            .retain(|(id, entry)| !(*id == list_id && entry.id == entry_id));
+
+        Ok(self.state.list_entries.len() < before)
+    }
+
+    async fn insert_shared_cart(&mut self, shared: &SharedCartRecord) -> Result<(), sqlx::Error> {
+        self.state.shared_carts.insert(shared.id, shared.clone());
+
+        Ok(())
+    }
+
+    async fn shared_cart(&mut self, id: Uuid) -> Result<Option<SharedCartRecord>, sqlx::Error> {
+        Ok(self.state.shared_carts.get(&id).cloned())
+    }
+
+    async fn record_event(&mut self, message: &OutboxMessage) -> Result<(), sqlx::Error> {
+        self.state.outbox.push(StoredEvent {
+            message: message.clone(),
+            published_at: None,
+        });
+
+        Ok(())
+    }
+
+    async fn unpublished_events(&mut self, limit: u32) -> Result<Vec<OutboxMessage>, sqlx::Error> {
+        Ok(self
+            .state
+            .outbox
+            .iter()
+            .filter(|event| event.published_at.is_none())
+            .take(limit as usize)
+            .map(|event| event.message.clone())
+            .collect())
+    }
+
+    async fn mark_event_published(&mut self, id: Uuid, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        if let Some(event) = self.state.outbox.iter_mut().find(|event| event.message.id == id) {
+            event.published_at = Some(now);
+        }
+
+        Ok(())
+    }
+
+    async fn claim_idempotency_key(
+        &mut self,
+        key_hash: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let keys = &mut self.state.idempotency_keys;
+        keys.retain(|_, key| key.expires_at > now);
+        if keys.contains_key(key_hash) {
+            return Ok(false);
+        }
+
+        keys.insert(
+            key_hash.to_string(),
+            StoredIdempotencyKey {
+                fingerprint: fingerprint.to_string(),
+                expires_at,
+                response: None,
+            },
+        );
+
+        Ok(true)
+    }
+
+    async fn idempotency_key(&mut self, key_hash: &str) -> Result<Option<IdempotencyRecord>, sqlx::Error> {
+        Ok(self
+            .state
+            .idempotency_keys
+            .get(key_hash)
+            .map(|key| IdempotencyRecord {
+                fingerprint: key.fingerprint.clone(),
+                response: key.response.clone(),
+            }))
+    }
+
+    async fn save_idempotent_response(
+        &mut self,
+        key_hash: &str,
+        response: &StoredResponse,
+    ) -> Result<(), sqlx::Error> {
+        if let Some(key) = self.state.idempotency_keys.get_mut(key_hash) {
+            key.response = Some(response.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn delete_idempotency_key(&mut self, key_hash: &str) -> Result<(), sqlx::Error> {
+        self.state.idempotency_keys.remove(key_hash);
+
+        Ok(())
+    }
+
+    async fn is_token_blacklisted(&mut self, _token: &str, _now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+        // Tokens are revoked through UserService, which never writes to this backend
+        Ok(false)
+    }
+}
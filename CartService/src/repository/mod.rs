@@ -0,0 +1,406 @@
+#[cfg(test)]
+mod conformance;
+pub mod memory;
+pub mod mysql;
+pub mod postgres;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::services::currency::Currency;
+use crate::services::inventory::ReservationStatus;
+use crate::services::line_options::LineOptions;
+use crate::services::lists::{ItemList, ItemListEntry, ItemListKind};
+use crate::services::orders::{Order, OrderStatus};
+use crate::services::outbox::OutboxMessage;
+use crate::services::promotions::Coupon;
+use crate::services::sharing::SharedCartItem;
+use crate::services::CartItem;
+
+pub use memory::InMemoryCartRepository;
+pub use mysql::MySqlCartRepository;
+pub use postgres::PostgresCartRepository;
+
+/// Which database the cart data lives in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseBackend {
+    Postgres,
+    MySql,
+    /// Nothing is persisted; for tests and local runs
+    Memory,
+}
+
+impl DatabaseBackend {
+    /// Guess the backend from the scheme of a database URL
+    pub fn from_url(db_url: &str) -> Option<Self> {
+        match db_url.split("://").next()? {
+            "postgres" | "postgresql" => Some(DatabaseBackend::Postgres),
+            "mysql" | "mariadb" => Some(DatabaseBackend::MySql),
+            "memory" => Some(DatabaseBackend::Memory),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DatabaseBackend::Postgres => "postgres",
+            DatabaseBackend::MySql => "mysql",
+            DatabaseBackend::Memory => "memory",
+        }
+    }
+}
+
+impl fmt::Display for DatabaseBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DatabaseBackend {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "postgres" | "postgresql" => Ok(DatabaseBackend::Postgres),
+            "mysql" => Ok(DatabaseBackend::MySql),
+            "memory" => Ok(DatabaseBackend::Memory),
+            other => Err(format!("Unknown database backend: {}", other)),
+        }
+    }
+}
+
+/// The version row kept for every cart that has been changed
+#[derive(Debug, Clone)]
+pub struct CartRecord {
+    pub version: u64,
+    pub last_activity_at: DateTime<Utc>,
+    pub currency: Option<String>, // Missing until the shopper picks a currency
+}
+
+/// A cart line as stored
+#[derive(Debug, Clone)]
+pub struct CartLine {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub options: LineOptions,
+    pub quantity: u32,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<CartLine> for CartItem {
+    fn from(line: CartLine) -> Self {
+        CartItem {
+            id: line.id,
+            product_id: line.product_id,
+            quantity: line.quantity,
+            options: line.options,
+        }
+    }
+}
+
+/// A cart that has been idle since `last_activity_at` and still has items
+#[derive(Debug, Clone)]
+pub struct IdleCart {
+    pub user_id: Uuid,
+    pub last_activity_at: DateTime<Utc>,
+    pub item_count: u32,
+}
+
+/// How often a coupon has been redeemed, overall and by one user
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Redemptions {
+    pub total: u64,
+    pub by_user: u64,
+}
+
+/// Stock held for an order until it is paid or the hold expires
+#[derive(Debug, Clone)]
+pub struct Reservation {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub product_id: Uuid,
+    pub quantity: u32,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A frozen copy of a cart behind a share link
+#[derive(Debug, Clone)]
+pub struct SharedCartRecord {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub items: Vec<SharedCartItem>,
+    pub currency: Currency,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A claimed Idempotency-Key; the response is missing while the first request is in flight
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub fingerprint: String,
+    pub response: Option<StoredResponse>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Storage for everything CartService persists
+#[async_trait]
+pub trait CartRepository: Send + Sync {
+    /// Start a transaction. Changes are discarded unless it is committed.
+    async fn begin(&self) -> Result<Box<dyn CartTransaction>, sqlx::Error>;
+}
+
+/// Reads and writes made inside one transaction. Methods that `lock` hold the rows they
+/// read until the transaction ends, so concurrent changes to them wait.
+#[async_trait]
+pub trait CartTransaction: Send {
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error>;
+
+    // Carts
+
+    async fn cart(&mut self, user_id: Uuid) -> Result<Option<CartRecord>, sqlx::Error>;
+
+    /// Lock a cart's version row and return its version, if the cart has one
+    async fn lock_cart_version(&mut self, user_id: Uuid) -> Result<Option<u64>, sqlx::Error>;
+
+    /// Record activity on a cart, bumping its version and reviving it if it was marked abandoned.
+    /// Returns the new version.
+    async fn touch_cart(&mut self, user_id: Uuid, now: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    async fn set_cart_currency(&mut self, user_id: Uuid, currency: Currency) -> Result<(), sqlx::Error>;
+
+    async fn delete_cart(&mut self, user_id: Uuid) -> Result<(), sqlx::Error>;
+
+    /// Active carts with items that saw no activity since `before`
+    async fn idle_carts(&mut self, before: DateTime<Utc>) -> Result<Vec<IdleCart>, sqlx::Error>;
+
+    /// Mark a cart abandoned unless it saw activity after `last_activity_at`
+    async fn mark_cart_abandoned(
+        &mut self,
+        user_id: Uuid,
+        last_activity_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Delete carts idle since `before` along with their lines and coupons
+    async fn purge_carts(&mut self, before: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    // Cart lines
+
+    async fn cart_lines(&mut self, user_id: Uuid, lock: bool) -> Result<Vec<CartLine>, sqlx::Error>;
+
+    async fn cart_line(
+        &mut self,
+        user_id: Uuid,
+        item_id: Uuid,
+        lock: bool,
+    ) -> Result<Option<CartLine>, sqlx::Error>;
+
+    /// Add units to the line for a product and options, creating the line if there is none
+    async fn add_to_line(
+        &mut self,
+        user_id: Uuid,
+        product_id: Uuid,
+        options: &LineOptions,
+        quantity: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn insert_line(&mut self, user_id: Uuid, line: &CartLine) -> Result<(), sqlx::Error>;
+
+    /// Returns false if the cart has no such line
+    async fn set_line_quantity(
+        &mut self,
+        user_id: Uuid,
+        item_id: Uuid,
+        quantity: u32,
+        updated_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Move a line to another cart as-is
+    async fn move_line(&mut self, item_id: Uuid, to_user_id: Uuid) -> Result<(), sqlx::Error>;
+
+    async fn delete_line(&mut self, item_id: Uuid) -> Result<(), sqlx::Error>;
+
+    async fn delete_lines(&mut self, user_id: Uuid) -> Result<(), sqlx::Error>;
+
+    // Coupons
+
+    /// Coupons applied to a cart, in the order they were applied
+    async fn applied_coupons(&mut self, user_id: Uuid) -> Result<Vec<Coupon>, sqlx::Error>;
+
+    async fn coupon(&mut self, code: &str) -> Result<Option<Coupon>, sqlx::Error>;
+
+    /// Lock a coupon until the transaction ends, so its redemptions can be counted and added
+    /// without racing other checkouts
+    async fn lock_coupon(&mut self, code: &str) -> Result<(), sqlx::Error>;
+
+    async fn coupon_redemptions(&mut self, code: &str, user_id: Uuid) -> Result<Redemptions, sqlx::Error>;
+
+    async fn apply_coupon(&mut self, user_id: Uuid, code: &str, now: DateTime<Utc>) -> Result<(), sqlx::Error>;
+
+    /// Returns false if the coupon was not applied to the cart
+    async fn remove_coupon(&mut self, user_id: Uuid, code: &str) -> Result<bool, sqlx::Error>;
+
+    async fn clear_coupons(&mut self, user_id: Uuid) -> Result<(), sqlx::Error>;
+
+    async fn record_redemption(
+        &mut self,
+        code: &str,
+        user_id: Uuid,
+        order_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    // Orders
+
+    /// Store an order with its lines
+    async fn insert_order(&mut self, order: &Order) -> Result<(), sqlx::Error>;
+
+    async fn order(&mut self, user_id: Uuid, order_id: Uuid) -> Result<Option<Order>, sqlx::Error>;
+
+    async fn lock_order_status(
+        &mut self,
+        user_id: Uuid,
+        order_id: Uuid,
+    ) -> Result<Option<OrderStatus>, sqlx::Error>;
+
+    async fn set_order_status(
+        &mut self,
+        order_id: Uuid,
+        status: OrderStatus,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Units of a product a user bought, not counting cancelled and refunded orders
+    async fn purchased_quantity(&mut self, user_id: Uuid, product_id: Uuid) -> Result<u32, sqlx::Error>;
+
+    // Inventory reservations
+
+    /// Units of a product held by active reservations, optionally leaving out one user's
+    async fn reserved_quantity(
+        &mut self,
+        product_id: Uuid,
+        excluding_user: Option<Uuid>,
+        now: DateTime<Utc>,
+        lock: bool,
+    ) -> Result<u32, sqlx::Error>;
+
+    async fn insert_reservation(&mut self, reservation: &Reservation) -> Result<(), sqlx::Error>;
+
+    /// Lock and return the active reservations of an order
+    async fn active_reservations(&mut self, order_id: Uuid) -> Result<Vec<Reservation>, sqlx::Error>;
+
+    /// Move the active reservations of an order to a final status
+    async fn close_reservations(&mut self, order_id: Uuid, status: ReservationStatus) -> Result<(), sqlx::Error>;
+
+    /// Release expired reservations and cancel the orders still waiting for payment on them.
+    /// Returns the number of reservations released.
+    async fn expire_reservations(&mut self, now: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    // Item lists
+
+    /// All of a user's lists with their entries, oldest first
+    async fn item_lists(&mut self, user_id: Uuid) -> Result<Vec<ItemList>, sqlx::Error>;
+
+    async fn find_list(&mut self, user_id: Uuid, list_id: Uuid) -> Result<bool, sqlx::Error>;
+
+    async fn list_of_kind(&mut self, user_id: Uuid, kind: ItemListKind) -> Result<Option<Uuid>, sqlx::Error>;
+
+    async fn insert_list(
+        &mut self,
+        list_id: Uuid,
+        user_id: Uuid,
+        name: &str,
+        kind: ItemListKind,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Returns false if the user has no list of that kind with the id
+    async fn rename_list(
+        &mut self,
+        user_id: Uuid,
+        list_id: Uuid,
+        kind: ItemListKind,
+        name: &str,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Delete a list and its entries. Returns false if the user has no list of that kind with the id.
+    async fn delete_list(&mut self, user_id: Uuid, list_id: Uuid, kind: ItemListKind) -> Result<bool, sqlx::Error>;
+
+    /// Add units to the entry for a product and options, creating the entry if there is none
+    async fn add_to_list(
+        &mut self,
+        list_id: Uuid,
+        product_id: Uuid,
+        options: &LineOptions,
+        quantity: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn list_entry(
+        &mut self,
+        user_id: Uuid,
+        list_id: Uuid,
+        entry_id: Uuid,
+    ) -> Result<Option<ItemListEntry>, sqlx::Error>;
+
+    /// Returns false if the entry was already gone
+    async fn delete_list_entry(&mut self, list_id: Uuid, entry_id: Uuid) -> Result<bool, sqlx::Error>;
+
+    // Shared carts
+
+    async fn insert_shared_cart(&mut self, shared: &SharedCartRecord) -> Result<(), sqlx::Error>;
+
+    async fn shared_cart(&mut self, id: Uuid) -> Result<Option<SharedCartRecord>, sqlx::Error>;
+
+    // Outbox
+
+    async fn record_event(&mut self, message: &OutboxMessage) -> Result<(), sqlx::Error>;
+
+    /// Lock the oldest unpublished events, skipping ones another relay holds
+    async fn unpublished_events(&mut self, limit: u32) -> Result<Vec<OutboxMessage>, sqlx::Error>;
+
+    async fn mark_event_published(&mut self, id: Uuid, now: DateTime<Utc>) -> Result<(), sqlx::Error>;
+
+    // Idempotency keys
+
+    /// Drop expired keys and claim `key_hash`. Returns false if the key is already taken.
+    async fn claim_idempotency_key(
+        &mut self,
+        key_hash: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn idempotency_key(&mut self, key_hash: &str) -> Result<Option<IdempotencyRecord>, sqlx::Error>;
+
+    async fn save_idempotent_response(
+        &mut self,
+        key_hash: &str,
+        response: &StoredResponse,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn delete_idempotency_key(&mut self, key_hash: &str) -> Result<(), sqlx::Error>;
+
+    // Access tokens
+
+    /// Whether UserService revoked the token before it expired
+    async fn is_token_blacklisted(&mut self, token: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error>;
+}
+
+/// Error for a stored value the domain types cannot represent
+pub(crate) fn decode_error(message: String) -> sqlx::Error {
+    sqlx::Error::Decode(message.into())
+}
@@ -0,0 +1,1311 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgRow;
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use uuid::Uuid;
+
+use super::{
+    decode_error, CartLine, CartRecord, CartRepository, CartTransaction, IdempotencyRecord, IdleCart,
+    Redemptions, Reservation, SharedCartRecord, StoredResponse,
+};
+use crate::services::currency::Currency;
+use crate::services::inventory::ReservationStatus;
+use crate::services::line_options::LineOptions;
+use crate::services::lists::{ItemList, ItemListEntry, ItemListKind};
+use crate::services::orders::{Order, OrderLine, OrderStatus};
+use crate::services::outbox::{CartEvent, OutboxMessage};
+use crate::services::promotions::{Coupon, DiscountKind};
+use crate::services::sharing::SharedCartItem;
+
+/// Repository backed by PostgreSQL
+#[derive(Clone)]
+pub struct PostgresCartRepository {
+    pool: PgPool,
+}
+
+impl PostgresCartRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CartRepository for PostgresCartRepository {
+    async fn begin(&self) -> Result<Box<dyn CartTransaction>, sqlx::Error> {
+        Ok(Box::new(PostgresTransaction {
+            tx: self.pool.begin().await?,
+        }))
+    }
+}
+
+struct PostgresTransaction {
+    tx: Transaction<'static, Postgres>,
+}
+
+// Postgres has no unsigned integers; quantities are stored as INTEGER and counters as BIGINT
+fn int(value: u32) -> i32 {
+    i32::try_from(value).unwrap_or(i32::MAX)
+}
+
+fn count(value: i64) -> u32 {
+    u32::try_from(value.max(0)).unwrap_or(u32::MAX)
+}
+
+fn options(row: &PgRow) -> Result<LineOptions, sqlx::Error> {
+    let options: Option<Json<LineOptions>> = row.try_get("options")?;
+    Ok(options.map(|options| options.0).unwrap_or_default())
+}
+
+fn cart_line(row: &PgRow) -> Result<CartLine, sqlx::Error> {
+    Ok(CartLine {
+        id: row.try_get("id")?,
+        product_id: row.try_get("product_id")?,
+        options: options(row)?,
+        quantity: count(row.try_get::<i32, _>("quantity")?.into()),
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn coupon(row: &PgRow) -> Result<Coupon, sqlx::Error> {
+    let kind: Json<DiscountKind> = row.try_get("rules")?;
+    let max_uses: Option<i32> = row.try_get("max_uses")?;
+    let max_uses_per_user: Option<i32> = row.try_get("max_uses_per_user")?;
+
+    Ok(Coupon {
+        code: row.try_get("code")?,
+        kind: kind.0,
+        min_spend: row.try_get("min_spend")?,
+        category: row.try_get("category")?,
+        max_uses: max_uses.map(|max| count(max.into())),
+        max_uses_per_user: max_uses_per_user.map(|max| count(max.into())),
+        valid_from: row.try_get("valid_from")?,
+        valid_until: row.try_get("valid_until")?,
+    })
+}
+
+fn reservation(row: &PgRow) -> Result<Reservation, sqlx::Error> {
+    Ok(Reservation {
+        id: row.try_get("id")?,
+        order_id: row.try_get("order_id")?,
+        user_id: row.try_get("user_id")?,
+        product_id: row.try_get("product_id")?,
+        quantity: count(row.try_get::<i32, _>("quantity")?.into()),
+        created_at: row.try_get("created_at")?,
+        expires_at: row.try_get("expires_at")?,
+    })
+}
+
+fn list_entry(row: &PgRow) -> Result<ItemListEntry, sqlx::Error> {
+    Ok(ItemListEntry {
+        id: row.try_get("id")?,
+        product_id: row.try_get("product_id")?,
+        options: options(row)?,
+        quantity: count(row.try_get::<i32, _>("quantity")?.into()),
+        added_at: row.try_get("added_at")?,
+    })
+}
+
+fn parse<T: std::str::FromStr<Err = String>>(row: &PgRow, column: &str) -> Result<T, sqlx::Error> {
+    row.try_get::<String, _>(column)?.parse().map_err(decode_error)
+}
+
+#[async_trait]
+impl CartTransaction for PostgresTransaction {
+    async fn commit(self: Box<Self>) -> Result<(), sqlx::Error> {
+        self.tx.commit().await
+    }
+
+    async fn cart(&mut self, user_id: Uuid) -> Result<Option<CartRecord>, sqlx::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT version, last_activity_at, currency
+            FROM carts
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+
+        row.map(|row| {
+            Ok(CartRecord {
+                version: row.try_get::<i64, _>("version")? as u64,
+                last_activity_at: row.try_get("last_activity_at")?,
+                currency: row.try_get("currency")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn lock_cart_version(&mut self, user_id: Uuid) -> Result<Option<u64>, sqlx::Error> {
+        let version: Option<i64> = sqlx::query_scalar(
+            r#"
+            SELECT version
+            FROM carts
+            WHERE user_id = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+
+        Ok(version.map(|version| version as u64))
+    }
+
+    async fn touch_cart(&mut self, user_id: Uuid, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let version: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO carts (user_id, version, last_activity_at, status)
+            VALUES ($1, 1, $2, 'active')
+            ON CONFLICT (user_id) DO UPDATE SET
+                version = carts.version + 1,
+                last_activity_at = EXCLUDED.last_activity_at,
+                status = 'active',
+                abandoned_at = NULL
+            RETURNING version
+            "#,
+        )
+        .bind(user_id)
+        .bind(now)
+        .fetch_one(&mut *self.tx)
+        .await?;
+
+        Ok(version as u64)
+    }
+
+    async fn set_cart_currency(&mut self, user_id: Uuid, currency: Currency) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE carts
+            SET currency = $1
+            WHERE user_id = $2
+            "#,
+        )
+        .bind(currency.code())
+        .bind(user_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_cart(&mut self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM carts
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn idle_carts(&mut self, before: DateTime<Utc>) -> Result<Vec<IdleCart>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT c.user_id, c.last_activity_at, SUM(i.quantity) AS item_count
+            FROM carts c
+            JOIN cart_items i ON i.user_id = c.user_id
+            WHERE c.status = 'active' AND c.last_activity_at < $1
+            GROUP BY c.user_id, c.last_activity_at
+            "#,
+        )
+        .bind(before)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(IdleCart {
+                    user_id: row.try_get("user_id")?,
+                    last_activity_at: row.try_get("last_activity_at")?,
+                    item_count: count(row.try_get("item_count")?),
+                })
+            })
+            .collect()
+    }
+
+    async fn mark_cart_abandoned(
+        &mut self,
+        user_id: Uuid,
+        last_activity_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE carts
+            SET status = 'abandoned', abandoned_at = $1
+            WHERE user_id = $2 AND status = 'active' AND last_activity_at = $3
+            "#,
+        )
+        .bind(now)
+        .bind(user_id)
+        .bind(last_activity_at)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn purge_carts(&mut self, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM cart_items
+            WHERE user_id IN (SELECT user_id FROM carts WHERE last_activity_at < $1)
+            "#,
+        )
+        .bind(before)
+        .execute(&mut *self.tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM cart_coupons
+            WHERE user_id IN (SELECT user_id FROM carts WHERE last_activity_at < $1)
+            "#,
+        )
+        .bind(before)
+        .execute(&mut *self.tx)
+        .await?;
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM carts
+            WHERE last_activity_at < $1
+            "#,
+        )
+        .bind(before)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn cart_lines(&mut self, user_id: Uuid, lock: bool) -> Result<Vec<CartLine>, sqlx::Error> {
+        let sql = if lock {
+            r#"
+            SELECT id, product_id, options, quantity, updated_at
+            FROM cart_items
+            WHERE user_id = $1
+            ORDER BY updated_at
+            FOR UPDATE
+            "#
+        } else {
+            r#"
+            SELECT id, product_id, options, quantity, updated_at
+            FROM cart_items
+            WHERE user_id = $1
+            ORDER BY updated_at
+            "#
+        };
+
+        let rows = sqlx::query(sql).bind(user_id).fetch_all(&mut *self.tx).await?;
+        rows.iter().map(cart_line).collect()
+    }
+
+    async fn cart_line(
+        &mut self,
+        user_id: Uuid,
+        item_id: Uuid,
+        lock: bool,
+    ) -> Result<Option<CartLine>, sqlx::Error> {
+        let sql = if lock {
+            r#"
+            SELECT id, product_id, options, quantity, updated_at
+            FROM cart_items
+            WHERE user_id = $1 AND id = $2
+            FOR UPDATE
+            "#
+        } else {
+            r#"
+            SELECT id, product_id, options, quantity, updated_at
+            FROM cart_items
+            WHERE user_id = $1 AND id = $2
+            "#
+        };
+
+        let row = sqlx::query(sql)
+            .bind(user_id)
+            .bind(item_id)
+            .fetch_optional(&mut *self.tx)
+            .await?;
+        row.as_ref().map(cart_line).transpose()
+    }
+
+    async fn add_to_line(
+        &mut self,
+        user_id: Uuid,
+        product_id: Uuid,
+        options: &LineOptions,
+        quantity: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO cart_items (id, user_id, product_id, options, options_key, quantity, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (user_id, product_id, options_key) DO UPDATE SET
+                quantity = cart_items.quantity + EXCLUDED.quantity,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(product_id)
+        .bind(Json(options))
+        .bind(options.key())
+        .bind(int(quantity))
+        .bind(now)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn insert_line(&mut self, user_id: Uuid, line: &CartLine) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO cart_items (id, user_id, product_id, options, options_key, quantity, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(line.id)
+        .bind(user_id)
+        .bind(line.product_id)
+        .bind(Json(&line.options))
+        .bind(line.options.key())
+        .bind(int(line.quantity))
+        .bind(line.updated_at)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn set_line_quantity(
+        &mut self,
+        user_id: Uuid,
+        item_id: Uuid,
+        quantity: u32,
+        updated_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE cart_items
+            SET quantity = $1, updated_at = $2
+            WHERE user_id = $3 AND id = $4
+            "#,
+        )
+        .bind(int(quantity))
+        .bind(updated_at)
+        .bind(user_id)
+        .bind(item_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn move_line(&mut self, item_id: Uuid, to_user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE cart_items
+            SET user_id = $1
+            WHERE id = $2
+            "#,
+        )
+        .bind(to_user_id)
+        .bind(item_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_line(&mut self, item_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM cart_items
+            WHERE id = $1
+            "#,
+        )
+        .bind(item_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_lines(&mut self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM cart_items
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn applied_coupons(&mut self, user_id: Uuid) -> Result<Vec<Coupon>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT c.code, c.rules, c.min_spend, c.category,
+                   c.max_uses, c.max_uses_per_user, c.valid_from, c.valid_until
+            FROM cart_coupons cc
+            JOIN coupons c ON c.code = cc.code
+            WHERE cc.user_id = $1
+            ORDER BY cc.applied_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        rows.iter().map(coupon).collect()
+    }
+
+    async fn coupon(&mut self, code: &str) -> Result<Option<Coupon>, sqlx::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT code, rules, min_spend, category,
+                   max_uses, max_uses_per_user, valid_from, valid_until
+            FROM coupons
+            WHERE code = $1
+            "#,
+        )
+        .bind(code)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+
+        row.as_ref().map(coupon).transpose()
+    }
+
+    async fn lock_coupon(&mut self, code: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT code FROM coupons WHERE code = $1 FOR UPDATE")
+            .bind(code)
+            .fetch_optional(&mut *self.tx)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn coupon_redemptions(&mut self, code: &str, user_id: Uuid) -> Result<Redemptions, sqlx::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS total, COUNT(*) FILTER (WHERE user_id = $1) AS by_user
+            FROM coupon_redemptions
+            WHERE code = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(code)
+        .fetch_one(&mut *self.tx)
+        .await?;
+
+        Ok(Redemptions {
+            total: row.try_get::<i64, _>("total")? as u64,
+            by_user: row.try_get::<i64, _>("by_user")? as u64,
+        })
+    }
+
+    async fn apply_coupon(&mut self, user_id: Uuid, code: &str, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO cart_coupons (user_id, code, applied_at)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(code)
+        .bind(now)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove_coupon(&mut self, user_id: Uuid, code: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM cart_coupons
+            WHERE user_id = $1 AND code = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(code)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn clear_coupons(&mut self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM cart_coupons
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_redemption(
+        &mut self,
+        code: &str,
+        user_id: Uuid,
+        order_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO coupon_redemptions (code, user_id, order_id, redeemed_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(code)
+        .bind(user_id)
+        .bind(order_id)
+        .bind(now)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn insert_order(&mut self, order: &Order) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO orders (
+                id, user_id, status, currency, shipping, discounts, subtotal, discount_total,
+                tax_total, tax_breakdown, shipping_total, grand_total, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            "#,
+        )
+        .bind(order.id)
+        .bind(order.user_id)
+        .bind(order.status.as_str())
+        .bind(order.currency.code())
+        .bind(Json(&order.shipping))
+        .bind(Json(&order.discounts))
+        .bind(order.subtotal)
+        .bind(order.discount_total)
+        .bind(order.tax_total)
+        .bind(Json(&order.tax))
+        .bind(order.shipping_total)
+        .bind(order.grand_total)
+        .bind(order.created_at)
+        .bind(order.updated_at)
+        .execute(&mut *self.tx)
+        .await?;
+
+        for line in &order.lines {
+            sqlx::query(
+                r#"
+                INSERT INTO order_lines (id, order_id, product_id, name, options, quantity, unit_price, line_total)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                "#,
+            )
+            .bind(line.id)
+            .bind(order.id)
+            .bind(line.product_id)
+            .bind(&line.name)
+            .bind(Json(&line.options))
+            .bind(int(line.quantity))
+            .bind(line.unit_price)
+            .bind(line.line_total)
+            .execute(&mut *self.tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn order(&mut self, user_id: Uuid, order_id: Uuid) -> Result<Option<Order>, sqlx::Error> {
+        let row = match sqlx::query(
+            r#"
+            SELECT id, user_id, status, currency, shipping, discounts,
+                   subtotal, discount_total, tax_total, tax_breakdown,
+                   shipping_total, grand_total, created_at, updated_at
+            FROM orders
+            WHERE user_id = $1 AND id = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(order_id)
+        .fetch_optional(&mut *self.tx)
+        .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let lines = sqlx::query(
+            r#"
+            SELECT id, product_id, name, options, quantity, unit_price, line_total
+            FROM order_lines
+            WHERE order_id = $1
+            "#,
+        )
+        .bind(order_id)
+        .fetch_all(&mut *self.tx)
+        .await?
+        .iter()
+        .map(|line| {
+            Ok(OrderLine {
+                id: line.try_get("id")?,
+                product_id: line.try_get("product_id")?,
+                name: line.try_get("name")?,
+                options: options(line)?,
+                quantity: count(line.try_get::<i32, _>("quantity")?.into()),
+                unit_price: line.try_get("unit_price")?,
+                line_total: line.try_get("line_total")?,
+            })
+        })
+        .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+        Ok(Some(Order {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            status: parse(&row, "status")?,
+            currency: parse(&row, "currency")?,
+            lines,
+            shipping: row.try_get::<Json<_>, _>("shipping")?.0,
+            discounts: row.try_get::<Json<_>, _>("discounts")?.0,
+            subtotal: row.try_get("subtotal")?,
+            discount_total: row.try_get("discount_total")?,
+            tax_total: row.try_get("tax_total")?,
+            tax: row.try_get::<Json<_>, _>("tax_breakdown")?.0,
+            shipping_total: row.try_get("shipping_total")?,
+            grand_total: row.try_get("grand_total")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        }))
+    }
+
+    async fn lock_order_status(
+        &mut self,
+        user_id: Uuid,
+        order_id: Uuid,
+    ) -> Result<Option<OrderStatus>, sqlx::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT status
+            FROM orders
+            WHERE user_id = $1 AND id = $2
+            FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .bind(order_id)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+
+        row.map(|row| parse(&row, "status")).transpose()
+    }
+
+    async fn set_order_status(
+        &mut self,
+        order_id: Uuid,
+        status: OrderStatus,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET status = $1, updated_at = $2
+            WHERE id = $3
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(now)
+        .bind(order_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn purchased_quantity(&mut self, user_id: Uuid, product_id: Uuid) -> Result<u32, sqlx::Error> {
+        let purchased: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(l.quantity), 0)
+            FROM order_lines l
+            JOIN orders o ON o.id = l.order_id
+            WHERE o.user_id = $1 AND l.product_id = $2 AND o.status NOT IN ('cancelled', 'refunded')
+            "#,
+        )
+        .bind(user_id)
+        .bind(product_id)
+        .fetch_one(&mut *self.tx)
+        .await?;
+
+        Ok(count(purchased))
+    }
+
+    async fn reserved_quantity(
+        &mut self,
+        product_id: Uuid,
+        excluding_user: Option<Uuid>,
+        now: DateTime<Utc>,
+        lock: bool,
+    ) -> Result<u32, sqlx::Error> {
+        if lock {
+            // Row locks do not cover the reservations other checkouts are about to insert,
+            // so concurrent checkouts of the same product queue on a lock for the product instead
+            sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1::text, 0))")
+                .bind(product_id)
+                .execute(&mut *self.tx)
+                .await?;
+        }
+
+        let reserved: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(quantity), 0)
+            FROM inventory_reservations
+            WHERE product_id = $1 AND ($2::uuid IS NULL OR user_id <> $2)
+                AND status = 'active' AND expires_at > $3
+            "#,
+        )
+        .bind(product_id)
+        .bind(excluding_user)
+        .bind(now)
+        .fetch_one(&mut *self.tx)
+        .await?;
+
+        Ok(count(reserved))
+    }
+
+    async fn insert_reservation(&mut self, reservation: &Reservation) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO inventory_reservations (
+                id, order_id, user_id, product_id, quantity, status, created_at, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, 'active', $6, $7)
+            "#,
+        )
+        .bind(reservation.id)
+        .bind(reservation.order_id)
+        .bind(reservation.user_id)
+        .bind(reservation.product_id)
+        .bind(int(reservation.quantity))
+        .bind(reservation.created_at)
+        .bind(reservation.expires_at)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn active_reservations(&mut self, order_id: Uuid) -> Result<Vec<Reservation>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, order_id, user_id, product_id, quantity, created_at, expires_at
+            FROM inventory_reservations
+            WHERE order_id = $1 AND status = 'active'
+            FOR UPDATE
+            "#,
+        )
+        .bind(order_id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        rows.iter().map(reservation).collect()
+    }
+
+    async fn close_reservations(&mut self, order_id: Uuid, status: ReservationStatus) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE inventory_reservations
+            SET status = $1
+            WHERE order_id = $2 AND status = 'active'
+            "#,
+        )
+        .bind(status.as_str())
+        .bind(order_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn expire_reservations(&mut self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET status = 'cancelled', updated_at = $1
+            WHERE status = 'pending_payment' AND id IN (
+                SELECT order_id
+                FROM inventory_reservations
+                WHERE status = 'active' AND expires_at <= $1
+            )
+            "#,
+        )
+        .bind(now)
+        .execute(&mut *self.tx)
+        .await?;
+
+        let result = sqlx::query(
+            r#"
+            UPDATE inventory_reservations
+            SET status = 'released'
+            WHERE status = 'active' AND expires_at <= $1
+            "#,
+        )
+        .bind(now)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn item_lists(&mut self, user_id: Uuid) -> Result<Vec<ItemList>, sqlx::Error> {
+        let lists = sqlx::query(
+            r#"
+            SELECT id, name, kind
+            FROM item_lists
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        let mut result = Vec::with_capacity(lists.len());
+        for list in &lists {
+            let id: Uuid = list.try_get("id")?;
+            let entries = sqlx::query(
+                r#"
+                SELECT id, product_id, options, quantity, added_at
+                FROM item_list_entries
+                WHERE list_id = $1
+                ORDER BY added_at
+                "#,
+            )
+            .bind(id)
+            .fetch_all(&mut *self.tx)
+            .await?;
+
+            result.push(ItemList {
+                id,
+                name: list.try_get("name")?,
+                kind: parse(list, "kind")?,
+                entries: entries.iter().map(list_entry).collect::<Result<_, _>>()?,
+            });
+        }
+
+        Ok(result)
+    }
+
+    async fn find_list(&mut self, user_id: Uuid, list_id: Uuid) -> Result<bool, sqlx::Error> {
+        let found: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            SELECT id
+            FROM item_lists
+            WHERE user_id = $1 AND id = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(list_id)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+
+        Ok(found.is_some())
+    }
+
+    async fn list_of_kind(&mut self, user_id: Uuid, kind: ItemListKind) -> Result<Option<Uuid>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT id
+            FROM item_lists
+            WHERE user_id = $1 AND kind = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(kind.as_str())
+        .fetch_optional(&mut *self.tx)
+        .await
+    }
+
+    async fn insert_list(
+        &mut self,
+        list_id: Uuid,
+        user_id: Uuid,
+        name: &str,
+        kind: ItemListKind,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO item_lists (id, user_id, name, kind, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(list_id)
+        .bind(user_id)
+        .bind(name)
+        .bind(kind.as_str())
+        .bind(now)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn rename_list(
+        &mut self,
+        user_id: Uuid,
+        list_id: Uuid,
+        kind: ItemListKind,
+        name: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE item_lists
+            SET name = $1
+            WHERE user_id = $2 AND id = $3 AND kind = $4
+            "#,
+        )
+        .bind(name)
+        .bind(user_id)
+        .bind(list_id)
+        .bind(kind.as_str())
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_list(&mut self, user_id: Uuid, list_id: Uuid, kind: ItemListKind) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM item_lists
+            WHERE user_id = $1 AND id = $2 AND kind = $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(list_id)
+        .bind(kind.as_str())
+        .execute(&mut *self.tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            r#"
+            DELETE FROM item_list_entries
+            WHERE list_id = $1
+            "#,
+        )
+        .bind(list_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(true)
+    }
+
+    async fn add_to_list(
+        &mut self,
+        list_id: Uuid,
+        product_id: Uuid,
+        options: &LineOptions,
+        quantity: u32,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO item_list_entries (id, list_id, product_id, options, options_key, quantity, added_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (list_id, product_id, options_key) DO UPDATE SET
+                quantity = item_list_entries.quantity + EXCLUDED.quantity
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(list_id)
+        .bind(product_id)
+        .bind(Json(options))
+        .bind(options.key())
+        .bind(int(quantity))
+        .bind(now)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list_entry(
+        &mut self,
+        user_id: Uuid,
+        list_id: Uuid,
+        entry_id: Uuid,
+    ) -> Result<Option<ItemListEntry>, sqlx::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT e.id, e.product_id, e.options, e.quantity, e.added_at
+            FROM item_list_entries e
+            JOIN item_lists l ON l.id = e.list_id
+            WHERE l.user_id = $1 AND l.id = $2 AND e.id = $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(list_id)
+        .bind(entry_id)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+
+        row.as_ref().map(list_entry).transpose()
+    }
+
+    async fn delete_list_entry(&mut self, list_id: Uuid, entry_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM item_list_entries
+            WHERE list_id = $1 AND id = $2
+            "#,
+        )
+        .bind(list_id)
+        .bind(entry_id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn insert_shared_cart(&mut self, shared: &SharedCartRecord) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO shared_carts (id, user_id, items, currency, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(shared.id)
+        .bind(shared.user_id)
+        .bind(Json(&shared.items))
+        .bind(shared.currency.code())
+        .bind(shared.created_at)
+        .bind(shared.expires_at)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn shared_cart(&mut self, id: Uuid) -> Result<Option<SharedCartRecord>, sqlx::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, user_id, items, currency, created_at, expires_at
+            FROM shared_carts
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+
+        row.map(|row| {
+            Ok(SharedCartRecord {
+                id: row.try_get("id")?,
+                user_id: row.try_get("user_id")?,
+                items: row.try_get::<Json<Vec<SharedCartItem>>, _>("items")?.0,
+                currency: parse(&row, "currency")?,
+                created_at: row.try_get("created_at")?,
+                expires_at: row.try_get("expires_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn record_event(&mut self, message: &OutboxMessage) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO cart_outbox (id, occurred_at, event)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(message.id)
+        .bind(message.occurred_at)
+        .bind(Json(&message.event))
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn unpublished_events(&mut self, limit: u32) -> Result<Vec<OutboxMessage>, sqlx::Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, occurred_at, event
+            FROM cart_outbox
+            WHERE published_at IS NULL
+            ORDER BY sequence
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+        )
+        .bind(i64::from(limit))
+        .fetch_all(&mut *self.tx)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(OutboxMessage {
+                    id: row.try_get("id")?,
+                    occurred_at: row.try_get("occurred_at")?,
+                    event: row.try_get::<Json<CartEvent>, _>("event")?.0,
+                })
+            })
+            .collect()
+    }
+
+    async fn mark_event_published(&mut self, id: Uuid, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE cart_outbox
+            SET published_at = $1
+            WHERE id = $2
+            "#,
+        )
+        .bind(now)
+        .bind(id)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn claim_idempotency_key(
+        &mut self,
+        key_hash: &str,
+        fingerprint: &str,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE expires_at <= $1
+            "#,
+        )
+        .bind(now)
+        .execute(&mut *self.tx)
+        .await?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (key_hash, fingerprint, created_at, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (key_hash) DO NOTHING
+            "#,
+        )
+        .bind(key_hash)
+        .bind(fingerprint)
+        .bind(now)
+        .bind(expires_at)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn idempotency_key(&mut self, key_hash: &str) -> Result<Option<IdempotencyRecord>, sqlx::Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT fingerprint, response_status, response_headers, response_body
+            FROM idempotency_keys
+            WHERE key_hash = $1
+            "#,
+        )
+        .bind(key_hash)
+        .fetch_optional(&mut *self.tx)
+        .await?;
+
+        row.map(|row| {
+            let status: Option<i32> = row.try_get("response_status")?;
+            let headers: Option<Json<Vec<(String, String)>>> = row.try_get("response_headers")?;
+            let body: Option<Vec<u8>> = row.try_get("response_body")?;
+
+            Ok(IdempotencyRecord {
+                fingerprint: row.try_get("fingerprint")?,
+                response: match (status, headers, body) {
+                    (Some(status), Some(headers), Some(body)) => Some(StoredResponse {
+                        status: u16::try_from(status).unwrap_or_default(),
+                        headers: headers.0,
+                        body,
+                    }),
+                    _ => None,
+                },
+            })
+        })
+        .transpose()
+    }
+
+    async fn save_idempotent_response(
+        &mut self,
+        key_hash: &str,
+        response: &StoredResponse,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE idempotency_keys
+            SET response_status = $1, response_headers = $2, response_body = $3
+            WHERE key_hash = $4
+            "#,
+        )
+        .bind(i32::from(response.status))
+        .bind(Json(&response.headers))
+        .bind(&response.body)
+        .bind(key_hash)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_idempotency_key(&mut self, key_hash: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE key_hash = $1
+            "#,
+        )
+        .bind(key_hash)
+        .execute(&mut *self.tx)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn is_token_blacklisted(&mut self, token: &str, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+        let blacklisted: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM token_blacklist
+                WHERE token = $1 AND expires_at > $2
+            )
+            "#,
+        )
+        .bind(token)
+        .bind(now)
+        .fetch_one(&mut *self.tx)
+        .await?;
+
+        Ok(blacklisted)
+    }
+}
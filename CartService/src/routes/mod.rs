@@ -1,26 +1,98 @@
 use axum::{
     middleware,
-    routing::{get, post, delete},
+    routing::{get, post, put, patch, delete},
     Router,
 };
 use crate::{
-    handlers::{add_cart_item, clear_cart, get_cart, remove_cart_item},
-    middleware::{logger_middleware, auth_middleware},
+    handlers::{
+        add_cart_item, clear_cart, get_cart, remove_cart_item, update_cart_item_quantity, set_cart_currency,
+        apply_coupon, remove_coupon, merge_guest_cart, get_guest_cart, add_guest_cart_item, update_guest_cart_item_quantity,
+        remove_guest_cart_item, clear_guest_cart,
+        batch::apply_cart_batch,
+        health::{healthz, readyz, HealthCheck},
+        lists::{
+            get_item_lists, create_wishlist, rename_wishlist, delete_wishlist,
+            move_cart_item_to_list, move_list_entry_to_cart,
+        },
+        orders::{checkout, get_order, update_order_status},
+        sharing::{share_cart, get_shared_cart, import_shared_cart},
+        shipping::get_shipping_options,
+    },
+    middleware::{logger_middleware, auth_middleware, internal_auth_middleware, idempotency::idempotency_middleware},
     services::CartService,
 };
 use std::sync::Arc;
 
 /// Create the main router for the cart service
-pub fn create_router(cart_service: Arc<CartService>) -> Router {
+pub fn create_router(cart_service: Arc<CartService>, health: Arc<HealthCheck>) -> Router {
+    // Probes for the orchestrator; unauthenticated and kept out of the request log
+    let health_routes = Router::new()
+        .route("/healthz", get(healthz)) // Liveness
+        .route("/readyz", get(readyz)) // Readiness, with a breakdown per dependency
+        .with_state(health);
+
+    // Guest carts are identified by the X-Cart-Token header and do not require authentication
+    let guest_routes = Router::new()
+        .route("/guest-cart", get(get_guest_cart)) // Get all items in the guest cart
+        .route("/guest-cart/add", post(add_guest_cart_item)) // Add an item, issuing a cart token if needed
+        .route("/guest-cart/items/:item_id", patch(update_guest_cart_item_quantity)) // Set an item's quantity
+        .route("/guest-cart/remove/:item_id", delete(remove_guest_cart_item)) // Remove an item
+        .route("/guest-cart/clear", delete(clear_guest_cart)) // Clear the guest cart
+        .layer(middleware::from_fn_with_state(
+            cart_service.clone(),
+            idempotency_middleware,
+        )) // Replay retried writes sent with an Idempotency-Key
+        .layer(middleware::from_fn(logger_middleware)); // Attach the logger middleware
+
+    // Anyone holding a share link can view the snapshot without signing in
+    let shared_routes = Router::new()
+        .route("/shared-carts/:token", get(get_shared_cart)) // View a shared cart snapshot
+        .layer(middleware::from_fn(logger_middleware)); // Attach the logger middleware
+
+    // Order status is driven by payment and fulfilment, which authenticate with the internal API key
+    let internal_routes = Router::new()
+        .route("/orders/:user_id/:order_id/status", post(update_order_status)) // Move an order to a new status
+        .layer(middleware::from_fn(logger_middleware)) // Attach the logger middleware
+        .layer(middleware::from_fn_with_state(
+            cart_service.clone(),
+            internal_auth_middleware,
+        )); // Require the internal API key
+
     Router::new()
         .route("/cart/:user_id", get(get_cart)) // Get all items in the user's cart
         .route("/cart/:user_id/add", post(add_cart_item)) // Add an item to the cart
+        .route("/cart/:user_id/items/:item_id", patch(update_cart_item_quantity)) // Set an item's quantity
         .route("/cart/:user_id/remove/:item_id", delete(remove_cart_item)) // Remove an item
         .route("/cart/:user_id/clear", delete(clear_cart)) // Clear the user's cart
+        .route("/cart/:user_id/currency", put(set_cart_currency)) // Choose the currency the cart is priced in
+        .route("/cart/:user_id/batch", post(apply_cart_batch)) // Apply several cart changes atomically
+        .route("/cart/:user_id/coupons", post(apply_coupon)) // Apply a coupon code
+        .route("/cart/:user_id/coupons/:code", delete(remove_coupon)) // Remove a coupon code
+        .route("/cart/:user_id/merge", post(merge_guest_cart)) // Merge a guest cart into the user's cart
+        .route("/cart/:user_id/items/:item_id/move-to-list", post(move_cart_item_to_list)) // Save an item for later or to a wishlist
+        .route("/cart/:user_id/lists", get(get_item_lists).post(create_wishlist)) // List or create item lists
+        .route("/cart/:user_id/lists/:list_id", patch(rename_wishlist).delete(delete_wishlist)) // Rename or delete a wishlist
+        .route(
+            "/cart/:user_id/lists/:list_id/entries/:entry_id/move-to-cart",
+            post(move_list_entry_to_cart),
+        ) // Move a list entry back into the cart
+        .route("/cart/:user_id/shipping-options", get(get_shipping_options)) // Quote shipping to an address
+        .route("/cart/:user_id/share", post(share_cart)) // Snapshot the cart behind a share link
+        .route("/cart/:user_id/shared-carts/:token/import", post(import_shared_cart)) // Add a shared snapshot to the cart
+        .route("/cart/:user_id/checkout", post(checkout)) // Convert the cart into an order
+        .route("/orders/:user_id/:order_id", get(get_order)) // Get an order
+        .layer(middleware::from_fn_with_state(
+            cart_service.clone(),
+            idempotency_middleware,
+        )) // Replay retried writes sent with an Idempotency-Key
         .layer(middleware::from_fn(logger_middleware)) // Attach the logger middleware
         .layer(middleware::from_fn_with_state(
             cart_service.clone(),
             auth_middleware,
         )) // Attach authentication middleware with state
+        .merge(guest_routes)
+        .merge(shared_routes)
+        .merge(internal_routes)
         .with_state(cart_service) // Inject the shared CartService
+        .merge(health_routes)
 }
@@ -0,0 +1,267 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use super::{CartService, CartServiceError};
+
+/// Emitted when a cart has been idle longer than the abandonment threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct AbandonedCartEvent {
+    pub user_id: Uuid,
+    pub item_count: u32,
+    pub last_activity_at: DateTime<Utc>,
+    pub abandoned_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Error)]
+#[error("Failed to deliver notification: {0}")]
+pub struct NotifierError(pub String);
+
+/// Receives cart lifecycle notifications, e.g. to send recovery emails
+#[async_trait]
+pub trait CartNotifier: Send + Sync {
+    async fn cart_abandoned(&self, event: &AbandonedCartEvent) -> Result<(), NotifierError>;
+}
+
+/// Notifier that only writes to the log
+pub struct LogNotifier;
+
+#[async_trait]
+impl CartNotifier for LogNotifier {
+    async fn cart_abandoned(&self, event: &AbandonedCartEvent) -> Result<(), NotifierError> {
+        info!(
+            "Cart abandoned: user {} with {} items, idle since {}",
+            event.user_id, event.item_count, event.last_activity_at
+        );
+        Ok(())
+    }
+}
+
+/// Notifier that keeps events in memory, used in tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryNotifier {
+    pub events: std::sync::Mutex<Vec<AbandonedCartEvent>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl CartNotifier for InMemoryNotifier {
+    async fn cart_abandoned(&self, event: &AbandonedCartEvent) -> Result<(), NotifierError> {
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+/// When idle carts are considered abandoned and when they are purged
+#[derive(Debug, Clone, Copy)]
+pub struct AbandonmentPolicy {
+    pub abandon_after: Duration,
+    pub expire_after: Duration,
+}
+
+impl CartService {
+    // Mark idle carts as abandoned and notify about each one
+    pub async fn mark_abandoned_carts(&self, policy: AbandonmentPolicy) -> Result<u64, CartServiceError> {
+        let now = Utc::now();
+
+        let mut tx = self.repository.begin().await?;
+        let idle_carts = tx.idle_carts(now - policy.abandon_after).await?;
+        tx.commit().await?;
+
+        let mut abandoned = 0;
+        for cart in idle_carts {
+            // Skip carts that saw activity since they were selected
+            let mut tx = self.repository.begin().await?;
+            let marked = tx
+                .mark_cart_abandoned(cart.user_id, cart.last_activity_at, now)
+                .await?;
+            tx.commit().await?;
+
+            if !marked {
+                continue;
+            }
+            abandoned += 1;
+
+            let event = AbandonedCartEvent {
+                user_id: cart.user_id,
+                item_count: cart.item_count,
+                last_activity_at: cart.last_activity_at,
+                abandoned_at: now,
+            };
+            if let Err(err) = self.notifier.cart_abandoned(&event).await {
+                error!("Failed to notify about abandoned cart {}: {}", cart.user_id, err);
+            }
+        }
+
+        Ok(abandoned)
+    }
+
+    // Delete carts idle past the hard expiry
+    pub async fn purge_expired_carts(&self, policy: AbandonmentPolicy) -> Result<u64, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+        let purged = tx.purge_carts(Utc::now() - policy.expire_after).await?;
+        tx.commit().await?;
+
+        Ok(purged)
+    }
+}
+
+/// Periodically mark abandoned carts and purge expired ones until shutdown begins
+pub fn spawn_cart_sweeper(
+    cart_service: Arc<CartService>,
+    policy: AbandonmentPolicy,
+    interval: std::time::Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.changed() => break,
+            }
+
+            match cart_service.mark_abandoned_carts(policy).await {
+                Ok(0) => {}
+                Ok(count) => info!("Marked {} carts as abandoned", count),
+                Err(err) => error!("Failed to mark abandoned carts: {:?}", err),
+            }
+
+            match cart_service.purge_expired_carts(policy).await {
+                Ok(0) => {}
+                Ok(count) => info!("Purged {} expired carts", count),
+                Err(err) => error!("Failed to purge expired carts: {:?}", err),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    use crate::services::line_options::LineOptions;
+    use crate::services::testing::{cart_service, product};
+
+    const POLICY: AbandonmentPolicy = AbandonmentPolicy {
+        abandon_after: Duration::hours(1),
+        expire_after: Duration::days(30),
+    };
+
+    struct FailingNotifier;
+
+    #[async_trait]
+    impl CartNotifier for FailingNotifier {
+        async fn cart_abandoned(&self, _event: &AbandonedCartEvent) -> Result<(), NotifierError> {
+            Err(NotifierError("mail server is down".to_string()))
+        }
+    }
+
+    // A service whose abandonment notifications can be inspected, selling one product
+    fn notified_service() -> (CartService, Arc<InMemoryNotifier>, Uuid) {
+        let product = product(Decimal::new(1000, 2), 100);
+        let mut service = cart_service([product.clone()]).service;
+        let notifier = Arc::new(InMemoryNotifier::default());
+        service.notifier = notifier.clone();
+        (service, notifier, product.id)
+    }
+
+    // Fill a cart, then make it look like it was last touched `idle_for` ago
+    async fn idle_cart(service: &CartService, product_id: Uuid, quantity: u32, idle_for: Duration) -> Uuid {
+        let user_id = Uuid::new_v4();
+        if quantity > 0 {
+            service
+                .add_item_to_cart(user_id, product_id, quantity, &LineOptions::default(), None)
+                .await
+                .unwrap();
+        }
+        backdate(service, user_id, idle_for).await;
+        user_id
+    }
+
+    async fn backdate(service: &CartService, user_id: Uuid, idle_for: Duration) {
+        let mut tx = service.repository.begin().await.unwrap();
+        tx.touch_cart(user_id, Utc::now() - idle_for).await.unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle_carts_with_items_are_abandoned_once() {
+        let (service, notifier, product_id) = notified_service();
+        let idle = idle_cart(&service, product_id, 3, Duration::hours(2)).await;
+        idle_cart(&service, product_id, 0, Duration::hours(2)).await;
+        idle_cart(&service, product_id, 1, Duration::minutes(5)).await;
+
+        assert_eq!(service.mark_abandoned_carts(POLICY).await.unwrap(), 1);
+        assert_eq!(service.mark_abandoned_carts(POLICY).await.unwrap(), 0);
+
+        let events = notifier.events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].user_id, events[0].item_count), (idle, 3));
+        assert!(events[0].last_activity_at < events[0].abandoned_at - Duration::hours(1));
+    }
+
+    #[tokio::test]
+    async fn carts_used_again_can_be_abandoned_again() {
+        let (service, notifier, product_id) = notified_service();
+        let user_id = idle_cart(&service, product_id, 1, Duration::hours(2)).await;
+        service.mark_abandoned_carts(POLICY).await.unwrap();
+
+        service
+            .add_item_to_cart(user_id, product_id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(service.mark_abandoned_carts(POLICY).await.unwrap(), 0);
+        backdate(&service, user_id, Duration::hours(3)).await;
+        assert_eq!(service.mark_abandoned_carts(POLICY).await.unwrap(), 1);
+
+        let events = notifier.events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].item_count, 2);
+    }
+
+    #[tokio::test]
+    async fn failed_notifications_do_not_stop_the_sweep() {
+        let (mut service, _, product_id) = notified_service();
+        service.notifier = Arc::new(FailingNotifier);
+        idle_cart(&service, product_id, 1, Duration::hours(2)).await;
+        idle_cart(&service, product_id, 1, Duration::hours(2)).await;
+
+        assert_eq!(service.mark_abandoned_carts(POLICY).await.unwrap(), 2);
+        assert_eq!(service.mark_abandoned_carts(POLICY).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn expired_carts_are_purged() {
+        let (service, _, product_id) = notified_service();
+        let expired = idle_cart(&service, product_id, 2, Duration::days(31)).await;
+        let abandoned = idle_cart(&service, product_id, 2, Duration::days(2)).await;
+
+        assert_eq!(service.purge_expired_carts(POLICY).await.unwrap(), 1);
+
+        assert!(service.get_cart(expired).await.unwrap().items.is_empty());
+        assert_eq!(service.get_cart(abandoned).await.unwrap().items[0].quantity, 2);
+    }
+
+    #[tokio::test]
+    async fn the_sweeper_stops_when_shutdown_begins() {
+        let (service, _, _) = notified_service();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let sweeper = spawn_cart_sweeper(Arc::new(service), POLICY, std::time::Duration::from_secs(3600), shutdown_rx);
+
+        shutdown_tx.send(true).unwrap();
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), sweeper)
+            .await
+            .expect("sweeper did not stop")
+            .unwrap();
+    }
+}
@@ -0,0 +1,450 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::limits::{check_added_quantity, LimitViolation};
+use super::line_options::LineOptions;
+use super::outbox::{record_event, CartEvent};
+use super::{CartService, CartServiceError};
+use crate::repository::{CartLine, CartTransaction};
+
+/// Most operations accepted in one batch
+pub const MAX_BATCH_OPERATIONS: usize = 100;
+
+/// One change to apply as part of a batch
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum CartOperation {
+    Add {
+        product_id: Uuid,
+        quantity: u32,
+        #[serde(default)]
+        options: LineOptions,
+    },
+    Remove { item_id: Uuid },
+    SetQuantity { item_id: Uuid, quantity: u32 }, // A quantity of 0 removes the line
+    Clear,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    pub operations: Vec<CartOperation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Applied,
+    /// Succeeded, but undone because a later operation failed
+    RolledBack,
+    Failed,
+    /// Not attempted because an earlier operation failed
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OperationResult {
+    pub index: usize,
+    pub status: OperationStatus,
+    pub item_id: Option<Uuid>, // The cart line the operation touched
+    pub error: Option<String>,
+    pub violation: Option<LimitViolation>, // Set when the operation broke a cart limit or purchase rule
+}
+
+/// Outcome of a batch; either every operation was applied or none was
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub applied: bool,
+    pub version: u64,
+    pub results: Vec<OperationResult>,
+}
+
+// Cart lines as the batch has left them so far
+struct BatchLine {
+    item_id: Uuid,
+    product_id: Uuid,
+    options_key: String,
+    quantity: u32,
+}
+
+// Quantity of a product across the batch's lines, optionally leaving one line out
+fn product_quantity(lines: &[BatchLine], product_id: Uuid, excluding_item: Option<Uuid>) -> u32 {
+    lines
+        .iter()
+        .filter(|line| line.product_id == product_id && Some(line.item_id) != excluding_item)
+        .map(|line| line.quantity)
+        .fold(0, u32::saturating_add)
+}
+
+impl CartService {
+    // Apply a list of operations to a cart in a single transaction
+    pub async fn apply_batch(
+        &self,
+        user_id: Uuid,
+        operations: &[CartOperation],
+        expected_version: Option<u64>,
+    ) -> Result<BatchResult, CartServiceError> {
+        if operations.len() > MAX_BATCH_OPERATIONS {
+            return Err(CartServiceError::BatchTooLarge(MAX_BATCH_OPERATIONS));
+        }
+
+        let mut tx = self.repository.begin().await?;
+        let current_version = self.check_version(&mut *tx, user_id, expected_version).await?;
+
+        // Lock the cart lines so the batch sees and changes a consistent cart
+        let mut lines: Vec<BatchLine> = tx
+            .cart_lines(user_id, true)
+            .await?
+            .into_iter()
+            .map(|line| BatchLine {
+                item_id: line.id,
+                product_id: line.product_id,
+                options_key: line.options.key(),
+                quantity: line.quantity,
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(operations.len());
+        for (index, operation) in operations.iter().enumerate() {
+            match self.apply_operation(&mut *tx, user_id, &mut lines, operation).await {
+                Ok(item_id) => results.push(OperationResult {
+                    index,
+                    status: OperationStatus::Applied,
+                    item_id,
+                    error: None,
+                    violation: None,
+                }),
+                // Infrastructure failures abort the request rather than being reported per operation
+                Err(err @ (CartServiceError::DatabaseError(_) | CartServiceError::CatalogError(_))) => {
+                    return Err(err)
+                }
+                Err(err) => {
+                    for result in &mut results {
+                        result.status = OperationStatus::RolledBack;
+                    }
+                    let violation = match &err {
+                        CartServiceError::LimitExceeded(violation) => Some(violation.clone()),
+                        _ => None,
+                    };
+                    results.push(OperationResult {
+                        index,
+                        status: OperationStatus::Failed,
+                        item_id: None,
+                        error: Some(err.to_string()),
+                        violation,
+                    });
+                    results.extend((index + 1..operations.len()).map(|index| OperationResult {
+                        index,
+                        status: OperationStatus::Skipped,
+                        item_id: None,
+                        error: None,
+                        violation: None,
+                    }));
+
+                    // Dropping the transaction without committing undoes the applied operations
+                    drop(tx);
+                    return Ok(BatchResult {
+                        applied: false,
+                        version: current_version,
+                        results,
+                    });
+                }
+            }
+        }
+
+        let version = if operations.is_empty() {
+            current_version
+        } else {
+            tx.touch_cart(user_id, Utc::now()).await?
+        };
+        tx.commit().await?;
+
+        Ok(BatchResult {
+            applied: true,
+            version,
+            results,
+        })
+    }
+
+    // Apply one batch operation, returning the cart line it touched
+    async fn apply_operation(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        lines: &mut Vec<BatchLine>,
+        operation: &CartOperation,
+    ) -> Result<Option<Uuid>, CartServiceError> {
+        match *operation {
+            CartOperation::Add {
+                product_id,
+                quantity,
+                ref options,
+            } => {
+                check_added_quantity(product_id, quantity)?;
+                options.validate()?;
+                let in_cart = product_quantity(lines, product_id, None);
+                self.validate_product(&mut *tx, user_id, product_id, in_cart.saturating_add(quantity))
+                    .await?;
+
+                let now = Utc::now();
+                let options_key = options.key();
+                let item_id = match lines
+                    .iter_mut()
+                    .find(|line| line.product_id == product_id && line.options_key == options_key)
+                {
+                    Some(line) => {
+                        line.quantity = line.quantity.saturating_add(quantity);
+                        tx.set_line_quantity(user_id, line.item_id, line.quantity, now).await?;
+                        line.item_id
+                    }
+                    None => {
+                        self.limits.check_line_count(lines.len() + 1)?;
+                        let line = CartLine {
+                            id: Uuid::new_v4(),
+                            product_id,
+                            options: options.clone(),
+                            quantity,
+                            updated_at: now,
+                        };
+                        tx.insert_line(user_id, &line).await?;
+                        lines.push(BatchLine {
+                            item_id: line.id,
+                            product_id,
+                            options_key,
+                            quantity,
+                        });
+                        line.id
+                    }
+                };
+
+                record_event(
+                    &mut *tx,
+                    CartEvent::ItemAdded {
+                        user_id,
+                        product_id,
+                        quantity,
+                    },
+                )
+                .await?;
+
+                Ok(Some(item_id))
+            }
+            CartOperation::SetQuantity { item_id, quantity: 0 } | CartOperation::Remove { item_id } => {
+                let index = lines
+                    .iter()
+                    .position(|line| line.item_id == item_id)
+                    .ok_or(CartServiceError::ItemNotFound)?;
+                let product_id = lines[index].product_id;
+
+                tx.delete_line(item_id).await?;
+
+                record_event(
+                    &mut *tx,
+                    CartEvent::ItemRemoved {
+                        user_id,
+                        item_id,
+                        product_id,
+                    },
+                )
+                .await?;
+
+                lines.remove(index);
+                Ok(Some(item_id))
+            }
+            CartOperation::SetQuantity { item_id, quantity } => {
+                let index = lines
+                    .iter()
+                    .position(|line| line.item_id == item_id)
+                    .ok_or(CartServiceError::ItemNotFound)?;
+                let product_id = lines[index].product_id;
+                let other_lines = product_quantity(lines, product_id, Some(item_id));
+                self.validate_product(&mut *tx, user_id, product_id, other_lines.saturating_add(quantity))
+                    .await?;
+
+                tx.set_line_quantity(user_id, item_id, quantity, Utc::now()).await?;
+
+                record_event(
+                    &mut *tx,
+                    CartEvent::ItemQuantityChanged {
+                        user_id,
+                        item_id,
+                        product_id,
+                        quantity,
+                    },
+                )
+                .await?;
+
+                lines[index].quantity = quantity;
+                Ok(Some(item_id))
+            }
+            CartOperation::Clear => {
+                tx.delete_lines(user_id).await?;
+
+                record_event(&mut *tx, CartEvent::CartCleared { user_id }).await?;
+
+                lines.clear();
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    use crate::services::testing::{cart_service, product};
+
+    fn add(product_id: Uuid, quantity: u32) -> CartOperation {
+        CartOperation::Add {
+            product_id,
+            quantity,
+            options: LineOptions::default(),
+        }
+    }
+
+    // Number of events waiting in the outbox
+    async fn pending_events(service: &CartService) -> usize {
+        let mut tx = service.repository.begin().await.unwrap();
+        tx.unpublished_events(1000).await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn adding_nothing_fails_the_batch() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+        let add = |quantity| CartOperation::Add {
+            product_id: product.id,
+            quantity,
+            options: LineOptions::default(),
+        };
+
+        let result = service.apply_batch(user_id, &[add(1), add(0)], None).await.unwrap();
+
+        assert!(!result.applied);
+        assert_eq!(result.results[0].status, OperationStatus::RolledBack);
+        assert_eq!(result.results[1].status, OperationStatus::Failed);
+        assert_eq!(
+            result.results[1].violation,
+            Some(LimitViolation::ZeroQuantity { product_id: product.id })
+        );
+        assert!(service.get_cart(user_id).await.unwrap().items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn batches_apply_every_operation_as_one_change() {
+        let (first, second) = (product(Decimal::new(1000, 2), 5), product(Decimal::new(500, 2), 5));
+        let service = cart_service([first.clone(), second.clone()]).service;
+        let user_id = Uuid::new_v4();
+        let version = service
+            .add_item_to_cart(user_id, first.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        let item_id = service.get_cart(user_id).await.unwrap().items[0].id;
+
+        let result = service
+            .apply_batch(
+                user_id,
+                &[
+                    add(second.id, 2),
+                    CartOperation::SetQuantity { item_id, quantity: 3 },
+                    add(second.id, 1),
+                ],
+                Some(version),
+            )
+            .await
+            .unwrap();
+
+        assert!(result.applied);
+        assert_eq!(result.version, version + 1);
+        assert!(result.results.iter().all(|result| result.status == OperationStatus::Applied));
+        assert_eq!(result.results[0].item_id, result.results[2].item_id);
+        let cart = service.get_cart(user_id).await.unwrap();
+        let quantities: Vec<u32> = cart.items.iter().map(|item| item.quantity).collect();
+        assert_eq!(quantities, vec![3, 3]);
+    }
+
+    #[tokio::test]
+    async fn a_failed_operation_undoes_the_ones_before_it() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+        let version = service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        let item_id = service.get_cart(user_id).await.unwrap().items[0].id;
+        let events = pending_events(&service).await;
+
+        let result = service
+            .apply_batch(
+                user_id,
+                &[
+                    CartOperation::SetQuantity { item_id, quantity: 4 },
+                    CartOperation::Clear,
+                    CartOperation::Remove { item_id: Uuid::new_v4() },
+                    add(product.id, 1),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert!(!result.applied);
+        assert_eq!(result.version, version);
+        let statuses: Vec<OperationStatus> = result.results.iter().map(|result| result.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                OperationStatus::RolledBack,
+                OperationStatus::RolledBack,
+                OperationStatus::Failed,
+                OperationStatus::Skipped,
+            ]
+        );
+        assert!(result.results[2].error.is_some());
+        let cart = service.get_cart(user_id).await.unwrap();
+        assert_eq!((cart.version, cart.items.len(), cart.items[0].quantity), (version, 1, 1));
+        assert_eq!(pending_events(&service).await, events);
+    }
+
+    #[tokio::test]
+    async fn stock_is_checked_across_the_whole_batch() {
+        let product = product(Decimal::new(1000, 2), 3);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+
+        let result = service
+            .apply_batch(user_id, &[add(product.id, 2), add(product.id, 2)], None)
+            .await
+            .unwrap();
+
+        assert!(!result.applied);
+        assert_eq!(result.results[1].status, OperationStatus::Failed);
+        assert!(service.get_cart(user_id).await.unwrap().items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stale_and_oversized_batches_are_refused() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+        let version = service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.apply_batch(user_id, &[CartOperation::Clear], Some(version - 1)).await,
+            Err(CartServiceError::VersionMismatch { current }) if current == version
+        ));
+        let too_many = vec![CartOperation::Clear; MAX_BATCH_OPERATIONS + 1];
+        assert!(matches!(
+            service.apply_batch(user_id, &too_many, None).await,
+            Err(CartServiceError::BatchTooLarge(MAX_BATCH_OPERATIONS))
+        ));
+        assert_eq!(service.get_cart(user_id).await.unwrap().items.len(), 1);
+    }
+}
@@ -0,0 +1,208 @@
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+#[cfg(test)]
+use std::sync::{Mutex, RwLock};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::limits::PurchaseRules;
+
+/// A product as seen by the cart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Product {
+    pub id: Uuid,
+    pub name: String,
+    pub category: String,
+    pub price: Decimal,
+    pub quantity: u32, // Units in stock
+    pub is_active: bool,
+    #[serde(default)]
+    pub weight_grams: u32, // Shipping weight of one unit
+    #[serde(default)]
+    pub purchase_rules: PurchaseRules,
+}
+
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error("Product service request failed: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Product service returned status {0}")]
+    UnexpectedStatus(StatusCode),
+
+    #[error("Product service returned an invalid price: {0}")]
+    InvalidPrice(String),
+}
+
+/// Read access to the product catalog owned by ProductService
+#[async_trait]
+pub trait ProductCatalog: Send + Sync {
+    /// Fetch a product, returning `None` if it does not exist
+    async fn get_product(&self, product_id: Uuid) -> Result<Option<Product>, CatalogError>;
+
+    /// Fetch several products, skipping the ones that do not exist
+    async fn get_products(&self, product_ids: &[Uuid]) -> Result<HashMap<Uuid, Product>, CatalogError> {
+        let mut products = HashMap::new();
+        for product_id in product_ids {
+            if let Some(product) = self.get_product(*product_id).await? {
+                products.insert(product.id, product);
+            }
+        }
+        Ok(products)
+    }
+}
+
+/// Header ProductService reads the API key from
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Catalog backed by the ProductService REST API
+pub struct HttpProductCatalog {
+    base_url: String,
+    api_key: String, // ProductService rejects requests without a key
+    client: reqwest::Client,
+}
+
+impl HttpProductCatalog {
+    /// Use a preconfigured client, for example one with timeouts set
+    pub fn with_client(base_url: impl Into<String>, api_key: impl Into<String>, client: reqwest::Client) -> Self {
+        Self {
+            base_url: base_url.into(),
+            api_key: api_key.into(),
+            client,
+        }
+    }
+
+    /// Start an authenticated request to a path under `/api/products/:id`
+    pub(super) fn request(&self, method: reqwest::Method, product_id: Uuid, path: &str) -> reqwest::RequestBuilder {
+        let url = format!(
+            "{}/api/products/{}{}",
+            self.base_url.trim_end_matches('/'),
+            product_id,
+            path
+        );
+        self.client.request(method, url).header(API_KEY_HEADER, &self.api_key)
+    }
+}
+
+/// Product document as returned by `GET /api/products/:id/details`, where `:id` is the product's `uuid`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProductResponse {
+    name: String,
+    category: String,
+    price: serde_json::Number, // Parsed from its textual form to keep it exact
+    quantity: u32,
+    is_active: Option<bool>,
+    weight_grams: Option<u32>,
+    min_order_quantity: Option<u32>,
+    max_order_quantity: Option<u32>,
+    pack_size: Option<u32>,
+    purchase_limit: Option<u32>, // Per customer, across orders
+}
+
+#[async_trait]
+impl ProductCatalog for HttpProductCatalog {
+    async fn get_product(&self, product_id: Uuid) -> Result<Option<Product>, CatalogError> {
+        let response = self.request(reqwest::Method::GET, product_id, "/details").send().await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let product: ProductResponse = response.json().await?;
+                let price = Decimal::from_str(&product.price.to_string())
+                    .map_err(|_| CatalogError::InvalidPrice(product.price.to_string()))?;
+
+                Ok(Some(Product {
+                    id: product_id,
+                    name: product.name,
+                    category: product.category,
+                    price,
+                    quantity: product.quantity,
+                    // ProductService has no active flag yet; products are active unless marked otherwise
+                    is_active: product.is_active.unwrap_or(true),
+                    // Products without a weight do not count towards weight-based shipping rates
+                    weight_grams: product.weight_grams.unwrap_or(0),
+                    purchase_rules: PurchaseRules {
+                        min_quantity: product.min_order_quantity,
+                        max_quantity: product.max_order_quantity,
+                        pack_size: product.pack_size,
+                        customer_limit: product.purchase_limit,
+                    },
+                }))
+            }
+            status => Err(CatalogError::UnexpectedStatus(status)),
+        }
+    }
+}
+
+/// In-memory catalog, used in tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryProductCatalog {
+    pub(super) products: RwLock<HashMap<Uuid, Product>>,
+    pub(super) stock_decrements: Mutex<HashMap<Uuid, (Uuid, u32)>>, // Product and quantity by decrement key
+}
+
+#[cfg(test)]
+impl InMemoryProductCatalog {
+    pub fn new(products: impl IntoIterator<Item = Product>) -> Self {
+        Self {
+            products: RwLock::new(products.into_iter().map(|p| (p.id, p)).collect()),
+            stock_decrements: Mutex::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl ProductCatalog for InMemoryProductCatalog {
+    async fn get_product(&self, product_id: Uuid) -> Result<Option<Product>, CatalogError> {
+        Ok(self.products.read().unwrap().get(&product_id).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::testing::{product, spawn_product_service, PRODUCT_SERVICE_API_KEY};
+
+    #[tokio::test]
+    async fn products_are_fetched_by_uuid_from_product_service() {
+        let widget = product(Decimal::new(1250, 2), 3);
+        let (url, _) = spawn_product_service([widget.clone()]).await;
+        let catalog = HttpProductCatalog::with_client(url, PRODUCT_SERVICE_API_KEY, reqwest::Client::new());
+
+        let fetched = catalog.get_product(widget.id).await.unwrap().unwrap();
+
+        assert_eq!(fetched.id, widget.id);
+        assert_eq!(fetched.name, widget.name);
+        assert_eq!(fetched.category, widget.category);
+        assert_eq!(fetched.price, Decimal::new(1250, 2));
+        assert_eq!(fetched.quantity, 3);
+        assert!(fetched.is_active);
+    }
+
+    #[tokio::test]
+    async fn unknown_products_are_none() {
+        let (url, _) = spawn_product_service([]).await;
+        let catalog = HttpProductCatalog::with_client(url, PRODUCT_SERVICE_API_KEY, reqwest::Client::new());
+
+        assert!(catalog.get_product(Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn a_rejected_api_key_is_an_error() {
+        let widget = product(Decimal::new(1250, 2), 3);
+        let (url, _) = spawn_product_service([widget.clone()]).await;
+        let catalog = HttpProductCatalog::with_client(url, "wrong-key", reqwest::Client::new());
+
+        assert!(matches!(
+            catalog.get_product(widget.id).await,
+            Err(CatalogError::UnexpectedStatus(StatusCode::FORBIDDEN))
+        ));
+    }
+}
@@ -0,0 +1,334 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use super::{CartService, CartServiceError};
+
+/// Currencies carts can be priced in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Usd,
+    Eur,
+    Gbp,
+    Chf,
+    Sek,
+    Dkk,
+    Nok,
+    Pln,
+    Czk,
+    Huf,
+    Jpy,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Chf => "CHF",
+            Currency::Sek => "SEK",
+            Currency::Dkk => "DKK",
+            Currency::Nok => "NOK",
+            Currency::Pln => "PLN",
+            Currency::Czk => "CZK",
+            Currency::Huf => "HUF",
+            Currency::Jpy => "JPY",
+        }
+    }
+
+    /// Number of decimal places in the currency's smallest unit, per ISO 4217
+    pub fn minor_units(&self) -> u32 {
+        match self {
+            Currency::Jpy => 0,
+            _ => 2,
+        }
+    }
+
+    /// Rounds an amount to the currency's minor units, rounding halves away from zero
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(self.minor_units(), RoundingStrategy::MidpointAwayFromZero)
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_uppercase().as_str() {
+            "USD" => Ok(Currency::Usd),
+            "EUR" => Ok(Currency::Eur),
+            "GBP" => Ok(Currency::Gbp),
+            "CHF" => Ok(Currency::Chf),
+            "SEK" => Ok(Currency::Sek),
+            "DKK" => Ok(Currency::Dkk),
+            "NOK" => Ok(Currency::Nok),
+            "PLN" => Ok(Currency::Pln),
+            "CZK" => Ok(Currency::Czk),
+            "HUF" => Ok(Currency::Huf),
+            "JPY" => Ok(Currency::Jpy),
+            other => Err(format!("Unsupported currency: {}", other)),
+        }
+    }
+}
+
+/// Converts catalog amounts into the currency a cart is priced in
+#[derive(Debug, Clone, Copy)]
+pub struct CurrencyConversion {
+    pub currency: Currency,
+    pub rate: Decimal, // Units of `currency` per unit of the catalog currency
+}
+
+impl CurrencyConversion {
+    /// A conversion that leaves amounts in the given currency
+    pub fn identity(currency: Currency) -> Self {
+        Self {
+            currency,
+            rate: Decimal::ONE,
+        }
+    }
+
+    pub fn convert(&self, amount: Decimal) -> Decimal {
+        self.currency.round(amount * self.rate)
+    }
+}
+
+/// Exchange rates quoted against a single base currency, e.g.
+/// `{"version": "2024-06-01", "base": "EUR", "rates": {"USD": "1.0812", "GBP": "0.8514"}}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateTable {
+    pub version: Option<String>,
+    pub base: Currency,
+    pub rates: HashMap<Currency, Decimal>,
+}
+
+impl RateTable {
+    /// Cross rate between two currencies via the base currency
+    pub fn rate(&self, from: Currency, to: Currency) -> Option<Decimal> {
+        if from == to {
+            return Some(Decimal::ONE);
+        }
+
+        let per_base = |currency: Currency| {
+            if currency == self.base {
+                Some(Decimal::ONE)
+            } else {
+                self.rates.get(&currency).copied().filter(|rate| !rate.is_zero())
+            }
+        };
+
+        Some(per_base(to)? / per_base(from)?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ExchangeRateError {
+    #[error("Request to rate service failed: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Rate service returned {0}")]
+    UnexpectedStatus(reqwest::StatusCode),
+
+    #[error("Failed to read rate file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid rate table: {0}")]
+    InvalidRates(#[from] serde_json::Error),
+
+    #[error("No exchange rate from {from} to {to}")]
+    UnsupportedPair { from: Currency, to: Currency },
+}
+
+/// Source of exchange rates used to price carts in the shopper's currency
+#[async_trait]
+pub trait ExchangeRateProvider: Send + Sync {
+    async fn rate(&self, from: Currency, to: Currency) -> Result<Decimal, ExchangeRateError>;
+}
+
+/// Rates loaded once from a JSON file
+pub struct StaticExchangeRates {
+    table: RateTable,
+}
+
+impl StaticExchangeRates {
+    pub fn new(table: RateTable) -> Self {
+        Self { table }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ExchangeRateError> {
+        let contents = std::fs::read_to_string(path)?;
+        let table: RateTable = serde_json::from_str(&contents)?;
+        if let Some(version) = &table.version {
+            info!("Loaded exchange rates version {}", version);
+        }
+
+        Ok(Self::new(table))
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for StaticExchangeRates {
+    async fn rate(&self, from: Currency, to: Currency) -> Result<Decimal, ExchangeRateError> {
+        self.table
+            .rate(from, to)
+            .ok_or(ExchangeRateError::UnsupportedPair { from, to })
+    }
+}
+
+/// Rates fetched from an HTTP endpoint serving a rate table, cached for `ttl`
+pub struct HttpExchangeRates {
+    client: reqwest::Client,
+    url: String,
+    ttl: Duration,
+    cache: RwLock<Option<(Instant, RateTable)>>,
+}
+
+impl HttpExchangeRates {
+    /// Use a preconfigured client, for example one with timeouts set
+    pub fn with_client(url: impl Into<String>, ttl: Duration, client: reqwest::Client) -> Self {
+        Self {
+            client,
+            url: url.into(),
+            ttl,
+            cache: RwLock::new(None),
+        }
+    }
+
+    async fn fetch(&self) -> Result<RateTable, ExchangeRateError> {
+        let response = self.client.get(&self.url).send().await?;
+
+        if !response.status().is_success() {
+            return Err(ExchangeRateError::UnexpectedStatus(response.status()));
+        }
+
+        let table: RateTable = serde_json::from_slice(&response.bytes().await?)?;
+        if let Some(version) = &table.version {
+            debug!("Fetched exchange rates version {}", version);
+        }
+
+        Ok(table)
+    }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for HttpExchangeRates {
+    async fn rate(&self, from: Currency, to: Currency) -> Result<Decimal, ExchangeRateError> {
+        if from == to {
+            return Ok(Decimal::ONE);
+        }
+
+        if let Some((fetched_at, table)) = self.cache.read().await.as_ref() {
+            if fetched_at.elapsed() < self.ttl {
+                return table.rate(from, to).ok_or(ExchangeRateError::UnsupportedPair { from, to });
+            }
+        }
+
+        let mut cache = self.cache.write().await;
+        // Another request may have refreshed the cache while we waited for the lock
+        if !matches!(cache.as_ref(), Some((fetched_at, _)) if fetched_at.elapsed() < self.ttl) {
+            *cache = Some((Instant::now(), self.fetch().await?));
+        }
+
+        let (_, table) = cache.as_ref().expect("rate cache was just filled");
+        table.rate(from, to).ok_or(ExchangeRateError::UnsupportedPair { from, to })
+    }
+}
+
+impl CartService {
+    // Look up the rate to convert catalog prices into the given currency
+    pub(crate) async fn conversion_to(&self, currency: Currency) -> Result<CurrencyConversion, CartServiceError> {
+        let rate = self.exchange_rates.rate(self.catalog_currency, currency).await?;
+
+        Ok(CurrencyConversion { currency, rate })
+    }
+
+    // Change the currency a cart is priced and checked out in. Returns the new cart version.
+    pub async fn set_cart_currency(
+        &self,
+        user_id: Uuid,
+        currency: Currency,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CartServiceError> {
+        // Fail early if the cart could not be priced in this currency
+        self.conversion_to(currency).await?;
+
+        let mut tx = self.repository.begin().await?;
+        self.check_version(&mut *tx, user_id, expected_version).await?;
+
+        let version = tx.touch_cart(user_id, Utc::now()).await?;
+        tx.set_cart_currency(user_id, currency).await?;
+
+        tx.commit().await?;
+
+        Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> RateTable {
+        RateTable {
+            version: None,
+            base: Currency::Eur,
+            rates: HashMap::from([
+                (Currency::Usd, Decimal::new(10812, 4)),
+                (Currency::Gbp, Decimal::new(8514, 4)),
+                (Currency::Chf, Decimal::ZERO),
+            ]),
+        }
+    }
+
+    #[test]
+    fn rates_are_quoted_against_the_base() {
+        let table = table();
+
+        assert_eq!(table.rate(Currency::Eur, Currency::Usd), Some(Decimal::new(10812, 4)));
+        assert_eq!(table.rate(Currency::Usd, Currency::Eur), Some(Decimal::ONE / Decimal::new(10812, 4)));
+        assert_eq!(table.rate(Currency::Jpy, Currency::Jpy), Some(Decimal::ONE));
+    }
+
+    #[test]
+    fn cross_rates_go_through_the_base() {
+        let rate = table().rate(Currency::Gbp, Currency::Usd).unwrap();
+
+        assert_eq!(Currency::Usd.round(Decimal::ONE_HUNDRED * rate), Decimal::new(12699, 2));
+    }
+
+    #[test]
+    fn unknown_and_zero_rates_are_unsupported() {
+        let table = table();
+
+        assert_eq!(table.rate(Currency::Usd, Currency::Sek), None);
+        assert_eq!(table.rate(Currency::Chf, Currency::Eur), None);
+    }
+
+    #[test]
+    fn amounts_round_to_minor_units() {
+        let conversion = CurrencyConversion {
+            currency: Currency::Jpy,
+            rate: Decimal::new(1575, 1),
+        };
+
+        assert_eq!(conversion.convert(Decimal::new(1001, 2)), Decimal::new(1577, 0));
+        assert_eq!(Currency::Usd.round(Decimal::new(1005, 3)), Decimal::new(101, 2));
+    }
+}
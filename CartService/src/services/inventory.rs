@@ -0,0 +1,388 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::Method;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use super::catalog::{CatalogError, HttpProductCatalog};
+#[cfg(test)]
+use super::catalog::InMemoryProductCatalog;
+#[cfg(test)]
+use reqwest::StatusCode;
+use super::orders::OrderLine;
+use super::{CartService, CartServiceError};
+use crate::repository::{CartTransaction, Reservation};
+
+/// State of a stock hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReservationStatus {
+    Active,
+    Consumed, // Turned into a stock decrement when the order was paid
+    Released,
+}
+
+impl ReservationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReservationStatus::Active => "active",
+            ReservationStatus::Consumed => "consumed",
+            ReservationStatus::Released => "released",
+        }
+    }
+}
+
+/// Write access to product stock levels
+#[async_trait]
+pub trait StockLedger: Send + Sync {
+    /// Permanently take `quantity` units of a product out of stock. Decrements are keyed,
+    /// so a retry with the same key takes the stock only once.
+    async fn decrement_stock(&self, product_id: Uuid, quantity: u32, key: Uuid) -> Result<(), CatalogError>;
+
+    /// Put back the stock taken by the decrement made with `key`, if there was one
+    async fn reverse_decrement(&self, product_id: Uuid, key: Uuid) -> Result<(), CatalogError>;
+}
+
+#[async_trait]
+impl StockLedger for HttpProductCatalog {
+    async fn decrement_stock(&self, product_id: Uuid, quantity: u32, key: Uuid) -> Result<(), CatalogError> {
+        let response = self
+            .request(Method::POST, product_id, "/stock-decrements")
+            .json(&json!({ "quantity": quantity, "key": key }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(CatalogError::UnexpectedStatus(response.status()));
+        }
+
+        Ok(())
+    }
+
+    async fn reverse_decrement(&self, product_id: Uuid, key: Uuid) -> Result<(), CatalogError> {
+        let response = self
+            .request(Method::DELETE, product_id, &format!("/stock-decrements/{}", key))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(CatalogError::UnexpectedStatus(response.status()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl StockLedger for InMemoryProductCatalog {
+    async fn decrement_stock(&self, product_id: Uuid, quantity: u32, key: Uuid) -> Result<(), CatalogError> {
+        let mut decrements = self.stock_decrements.lock().unwrap();
+        if decrements.contains_key(&key) {
+            return Ok(());
+        }
+
+        let mut products = self.products.write().unwrap();
+        let product = products
+            .get_mut(&product_id)
+            .ok_or(CatalogError::UnexpectedStatus(StatusCode::NOT_FOUND))?;
+        if product.quantity < quantity {
+            return Err(CatalogError::UnexpectedStatus(StatusCode::CONFLICT));
+        }
+        product.quantity -= quantity;
+        decrements.insert(key, (product_id, quantity));
+
+        Ok(())
+    }
+
+    async fn reverse_decrement(&self, _product_id: Uuid, key: Uuid) -> Result<(), CatalogError> {
+        if let Some((product_id, quantity)) = self.stock_decrements.lock().unwrap().remove(&key) {
+            if let Some(product) = self.products.write().unwrap().get_mut(&product_id) {
+                product.quantity += quantity;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CartService {
+    // Units of a product held by active reservations of other users
+    pub(crate) async fn reserved_elsewhere(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        product_id: Uuid,
+    ) -> Result<u32, CartServiceError> {
+        Ok(tx.reserved_quantity(product_id, Some(user_id), Utc::now(), false).await?)
+    }
+
+    // Hold stock for every line of a new order; fails if another shopper holds the stock
+    pub(crate) async fn reserve_order_lines(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        order_id: Uuid,
+        lines: &[OrderLine],
+        available: impl Fn(Uuid) -> u32,
+    ) -> Result<(), CartServiceError> {
+        let now = Utc::now();
+        let expires_at = now + self.reservation_ttl;
+
+        // Take the product locks in a fixed order so checkouts of the same products cannot deadlock
+        let mut lines: Vec<&OrderLine> = lines.iter().collect();
+        lines.sort_by_key(|line| line.product_id);
+
+        for line in lines {
+            // Locking read so concurrent checkouts of the same product are serialised
+            let reserved = tx.reserved_quantity(line.product_id, None, now, true).await?;
+
+            let free = available(line.product_id).saturating_sub(reserved);
+            if line.quantity > free {
+                return Err(CartServiceError::StockReserved {
+                    product_id: line.product_id,
+                    requested: line.quantity,
+                    available: free,
+                });
+            }
+
+            tx.insert_reservation(&Reservation {
+                id: Uuid::new_v4(),
+                order_id,
+                user_id,
+                product_id: line.product_id,
+                quantity: line.quantity,
+                created_at: now,
+                expires_at,
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    // Turn an order's holds into permanent stock decrements once it is paid. Each decrement is
+    // keyed by its reservation, so retrying a failed confirmation never takes stock twice.
+    // Returns the reservations decremented, for `reverse_decrements` if the transaction fails.
+    pub(crate) async fn confirm_reservations(
+        &self,
+        tx: &mut dyn CartTransaction,
+        order_id: Uuid,
+    ) -> Result<Vec<Reservation>, CartServiceError> {
+        let reservations = tx.active_reservations(order_id).await?;
+
+        for (done, reservation) in reservations.iter().enumerate() {
+            if let Err(err) = self
+                .stock_ledger
+                .decrement_stock(reservation.product_id, reservation.quantity, reservation.id)
+                .await
+            {
+                self.reverse_decrements(&reservations[..done]).await;
+                return Err(err.into());
+            }
+        }
+
+        tx.close_reservations(order_id, ReservationStatus::Consumed).await?;
+
+        Ok(reservations)
+    }
+
+    // Put back the stock taken for reservations whose order did not end up paid
+    pub(crate) async fn reverse_decrements(&self, reservations: &[Reservation]) {
+        for reservation in reservations {
+            if let Err(err) = self
+                .stock_ledger
+                .reverse_decrement(reservation.product_id, reservation.id)
+                .await
+            {
+                error!(
+                    "Failed to put back {} units of product {} for order {}: {:?}",
+                    reservation.quantity, reservation.product_id, reservation.order_id, err
+                );
+            }
+        }
+    }
+
+    // Give an order's held stock back
+    pub(crate) async fn release_reservations(
+        &self,
+        tx: &mut dyn CartTransaction,
+        order_id: Uuid,
+    ) -> Result<(), CartServiceError> {
+        tx.close_reservations(order_id, ReservationStatus::Released).await?;
+
+        Ok(())
+    }
+
+    // Release expired holds and cancel the orders still waiting for payment on them
+    pub async fn release_expired_reservations(&self) -> Result<u64, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+        let released = tx.expire_reservations(Utc::now()).await?;
+        tx.commit().await?;
+
+        Ok(released)
+    }
+}
+
+/// Periodically release expired inventory reservations until shutdown begins
+pub fn spawn_reservation_sweeper(
+    cart_service: Arc<CartService>,
+    interval: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.changed() => break,
+            }
+            match cart_service.release_expired_reservations().await {
+                Ok(0) => {}
+                Ok(released) => info!("Released {} expired inventory reservations", released),
+                Err(err) => error!("Failed to release expired reservations: {:?}", err),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    use crate::services::catalog::Product;
+    use crate::services::line_options::LineOptions;
+    use crate::services::orders::{CheckoutRequest, Order, OrderStatus};
+    use crate::services::shipping::ShippingAddress;
+    use crate::services::testing::{
+        cart_service, product, spawn_product_service, ProductServiceState, PRODUCT_SERVICE_API_KEY,
+    };
+
+    fn checkout_request() -> CheckoutRequest {
+        CheckoutRequest {
+            shipping_address: ShippingAddress {
+                country: "US".to_string(),
+                region: None,
+                postal_code: None,
+            },
+            shipping_method: "standard".to_string(),
+        }
+    }
+
+    async fn check_out(service: &CartService, user_id: Uuid, items: &[(Uuid, u32)]) -> Order {
+        for &(product_id, quantity) in items {
+            service
+                .add_item_to_cart(user_id, product_id, quantity, &LineOptions::default(), None)
+                .await
+                .unwrap();
+        }
+        service.checkout(user_id, &checkout_request()).await.unwrap()
+    }
+
+    // A service whose catalog and stock ledger are the ProductService routes
+    async fn service_over_http(products: Vec<Product>) -> (CartService, Arc<ProductServiceState>) {
+        let (url, state) = spawn_product_service(products.clone()).await;
+        let http = Arc::new(HttpProductCatalog::with_client(url, PRODUCT_SERVICE_API_KEY, reqwest::Client::new()));
+        let mut service = cart_service(products).service;
+        service.catalog = http.clone();
+        service.stock_ledger = http;
+        (service, state)
+    }
+
+    #[tokio::test]
+    async fn checked_out_stock_is_held_from_other_shoppers() {
+        let product = product(Decimal::new(1000, 2), 3);
+        let service = cart_service([product.clone()]).service;
+
+        check_out(&service, Uuid::new_v4(), &[(product.id, 2)]).await;
+
+        assert!(matches!(
+            service
+                .add_item_to_cart(Uuid::new_v4(), product.id, 2, &LineOptions::default(), None)
+                .await,
+            Err(CartServiceError::StockReserved { available: 1, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn expired_holds_are_released_and_their_orders_cancelled() {
+        let product = product(Decimal::new(1000, 2), 3);
+        let mut service = cart_service([product.clone()]).service;
+        service.reservation_ttl = chrono::Duration::zero();
+        let user_id = Uuid::new_v4();
+
+        let order = check_out(&service, user_id, &[(product.id, 3)]).await;
+
+        assert_eq!(service.release_expired_reservations().await.unwrap(), 1);
+        assert_eq!(service.release_expired_reservations().await.unwrap(), 0);
+        assert_eq!(
+            service.get_order(user_id, order.id).await.unwrap().status,
+            OrderStatus::Cancelled
+        );
+        service
+            .add_item_to_cart(Uuid::new_v4(), product.id, 3, &LineOptions::default(), None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn paying_decrements_stock_through_product_service() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let (service, state) = service_over_http(vec![product.clone()]).await;
+        let user_id = Uuid::new_v4();
+
+        let order = check_out(&service, user_id, &[(product.id, 2)]).await;
+        assert_eq!(state.products.lock().unwrap()[&product.id].quantity, 5);
+
+        service.transition_order(user_id, order.id, OrderStatus::Paid).await.unwrap();
+
+        assert_eq!(state.products.lock().unwrap()[&product.id].quantity, 3);
+        let decrements = state.decrements.lock().unwrap();
+        assert_eq!(decrements.len(), 1);
+        assert!(decrements.values().all(|&(id, quantity)| id == product.id && quantity == 2));
+    }
+
+    #[tokio::test]
+    async fn decrements_are_taken_once_per_key_and_can_be_reversed() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let (service, state) = service_over_http(vec![product.clone()]).await;
+        let key = Uuid::new_v4();
+
+        service.stock_ledger.decrement_stock(product.id, 2, key).await.unwrap();
+        service.stock_ledger.decrement_stock(product.id, 2, key).await.unwrap();
+        assert_eq!(state.products.lock().unwrap()[&product.id].quantity, 3);
+
+        service.stock_ledger.reverse_decrement(product.id, key).await.unwrap();
+        service.stock_ledger.reverse_decrement(product.id, key).await.unwrap();
+        assert_eq!(state.products.lock().unwrap()[&product.id].quantity, 5);
+    }
+
+    #[tokio::test]
+    async fn a_rejected_decrement_puts_back_the_others_and_leaves_the_order_unpaid() {
+        let first = product(Decimal::new(1000, 2), 5);
+        let second = product(Decimal::new(1000, 2), 5);
+        let (service, state) = service_over_http(vec![first.clone(), second.clone()]).await;
+        let user_id = Uuid::new_v4();
+
+        let order = check_out(&service, user_id, &[(first.id, 2), (second.id, 2)]).await;
+        // Stock sold elsewhere after the hold was taken
+        state.products.lock().unwrap().get_mut(&second.id).unwrap().quantity = 1;
+
+        assert!(matches!(
+            service.transition_order(user_id, order.id, OrderStatus::Paid).await,
+            Err(CartServiceError::CatalogError(CatalogError::UnexpectedStatus(StatusCode::CONFLICT)))
+        ));
+        assert_eq!(state.products.lock().unwrap()[&first.id].quantity, 5);
+        assert_eq!(state.products.lock().unwrap()[&second.id].quantity, 1);
+        assert!(state.decrements.lock().unwrap().is_empty());
+        assert_eq!(
+            service.get_order(user_id, order.id).await.unwrap().status,
+            OrderStatus::PendingPayment
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::catalog::Product;
+use super::{CartService, CartServiceError};
+use crate::repository::CartTransaction;
+
+/// Limits that apply to every cart, set in configuration
+#[derive(Debug, Clone, Copy)]
+pub struct CartLimits {
+    pub max_lines: usize,              // Distinct lines in one cart
+    pub max_quantity_per_product: u32, // Units of one product in one cart, across its lines
+}
+
+impl Default for CartLimits {
+    fn default() -> Self {
+        Self {
+            max_lines: 100,
+            max_quantity_per_product: 999,
+        }
+    }
+}
+
+/// Purchase rules a product can carry in the catalog; rules that are not set do not apply
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PurchaseRules {
+    pub min_quantity: Option<u32>,
+    pub max_quantity: Option<u32>,   // Can only tighten the cart-wide maximum
+    pub pack_size: Option<u32>,      // Quantities must be a multiple of this
+    pub customer_limit: Option<u32>, // Most units one customer can buy across all their orders
+}
+
+/// A cart limit or purchase rule that a change would break
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Error)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum LimitViolation {
+    #[error("Quantity of product {product_id} must be at least 1")]
+    ZeroQuantity { product_id: Uuid },
+
+    #[error("A cart can have at most {max} lines")]
+    TooManyLines { max: usize },
+
+    #[error("At most {max} of product {product_id} can be in a cart; requested {requested}")]
+    AboveMaxQuantity { product_id: Uuid, max: u32, requested: u32 },
+
+    #[error("Product {product_id} must be ordered in quantities of at least {min}; requested {requested}")]
+    BelowMinQuantity { product_id: Uuid, min: u32, requested: u32 },
+
+    #[error("Product {product_id} is sold in packs of {pack_size}; requested {requested}")]
+    NotPackMultiple { product_id: Uuid, pack_size: u32, requested: u32 },
+
+    #[error("Product {product_id} is limited to {limit} per customer; {purchased} already purchased, requested {requested}")]
+    CustomerLimitExceeded {
+        product_id: Uuid,
+        limit: u32,
+        purchased: u32,
+        requested: u32,
+    },
+}
+
+/// Check the quantity of a product being added to a cart; adding nothing is a caller error,
+/// not a no-op
+pub fn check_added_quantity(product_id: Uuid, quantity: u32) -> Result<(), LimitViolation> {
+    if quantity == 0 {
+        return Err(LimitViolation::ZeroQuantity { product_id });
+    }
+
+    Ok(())
+}
+
+impl CartLimits {
+    /// Check the number of lines a cart would have after a change
+    pub fn check_line_count(&self, lines: usize) -> Result<(), LimitViolation> {
+        if lines > self.max_lines {
+            return Err(LimitViolation::TooManyLines { max: self.max_lines });
+        }
+
+        Ok(())
+    }
+
+    /// Check the quantity of a product in a cart, summed over its lines, against the
+    /// cart-wide maximum and the product's own rules
+    pub fn check_quantity(&self, product: &Product, requested: u32) -> Result<(), LimitViolation> {
+        let rules = &product.purchase_rules;
+        let product_id = product.id;
+
+        let max = rules
+            .max_quantity
+            .map_or(self.max_quantity_per_product, |max| max.min(self.max_quantity_per_product));
+        if requested > max {
+            return Err(LimitViolation::AboveMaxQuantity { product_id, max, requested });
+        }
+
+        if let Some(min) = rules.min_quantity {
+            if requested < min {
+                return Err(LimitViolation::BelowMinQuantity { product_id, min, requested });
+            }
+        }
+
+        if let Some(pack_size) = rules.pack_size.filter(|&size| size > 1) {
+            if !requested.is_multiple_of(pack_size) {
+                return Err(LimitViolation::NotPackMultiple {
+                    product_id,
+                    pack_size,
+                    requested,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CartService {
+    // Check that adding a line for a product and options would not take the cart over its line limit.
+    // Adding to an existing line never does.
+    pub(crate) async fn check_line_limit(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        product_id: Uuid,
+        options_key: &str,
+    ) -> Result<(), CartServiceError> {
+        let lines = tx.cart_lines(user_id, false).await?;
+
+        let exists = lines
+            .iter()
+            .any(|line| line.product_id == product_id && line.options.key() == options_key);
+        if !exists {
+            self.limits.check_line_count(lines.len() + 1)?;
+        }
+
+        Ok(())
+    }
+
+    // Check a product's per-customer cap against what the user already bought.
+    // Cancelled and refunded orders do not count.
+    pub(crate) async fn check_customer_limit(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        product: &Product,
+        requested: u32,
+    ) -> Result<(), CartServiceError> {
+        let limit = match product.purchase_rules.customer_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let purchased = tx.purchased_quantity(user_id, product.id).await?;
+
+        if purchased.saturating_add(requested) > limit {
+            return Err(LimitViolation::CustomerLimitExceeded {
+                product_id: product.id,
+                limit,
+                purchased,
+                requested,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    fn product(purchase_rules: PurchaseRules) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "Widget".to_string(),
+            category: "tools".to_string(),
+            price: Decimal::new(1000, 2),
+            quantity: 100,
+            is_active: true,
+            weight_grams: 0,
+            purchase_rules,
+        }
+    }
+
+    #[test]
+    fn line_count_is_limited() {
+        let limits = CartLimits { max_lines: 2, ..CartLimits::default() };
+
+        assert!(limits.check_line_count(2).is_ok());
+        assert_eq!(limits.check_line_count(3), Err(LimitViolation::TooManyLines { max: 2 }));
+    }
+
+    #[test]
+    fn product_maximum_only_tightens_the_cart_maximum() {
+        let limits = CartLimits { max_quantity_per_product: 10, ..CartLimits::default() };
+
+        let loose = product(PurchaseRules { max_quantity: Some(50), ..PurchaseRules::default() });
+        assert!(matches!(
+            limits.check_quantity(&loose, 11),
+            Err(LimitViolation::AboveMaxQuantity { max: 10, requested: 11, .. })
+        ));
+
+        let tight = product(PurchaseRules { max_quantity: Some(3), ..PurchaseRules::default() });
+        assert!(limits.check_quantity(&tight, 3).is_ok());
+        assert!(matches!(
+            limits.check_quantity(&tight, 4),
+            Err(LimitViolation::AboveMaxQuantity { max: 3, requested: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn minimum_and_pack_size_are_enforced() {
+        let limits = CartLimits::default();
+        let packs = product(PurchaseRules {
+            min_quantity: Some(6),
+            pack_size: Some(6),
+            ..PurchaseRules::default()
+        });
+
+        assert!(matches!(
+            limits.check_quantity(&packs, 3),
+            Err(LimitViolation::BelowMinQuantity { min: 6, requested: 3, .. })
+        ));
+        assert!(matches!(
+            limits.check_quantity(&packs, 8),
+            Err(LimitViolation::NotPackMultiple { pack_size: 6, requested: 8, .. })
+        ));
+        assert!(limits.check_quantity(&packs, 12).is_ok());
+    }
+
+    #[test]
+    fn pack_size_of_one_allows_any_quantity() {
+        let limits = CartLimits::default();
+        let single = product(PurchaseRules { pack_size: Some(1), ..PurchaseRules::default() });
+
+        assert!(limits.check_quantity(&single, 7).is_ok());
+    }
+}
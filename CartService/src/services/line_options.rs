@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub const MAX_VARIANT_ID_LENGTH: usize = 64;
+pub const MAX_ENGRAVING_LENGTH: usize = 40;
+pub const MAX_GIFT_MESSAGE_LENGTH: usize = 500;
+pub const MAX_METADATA_ENTRIES: usize = 20;
+pub const MAX_METADATA_KEY_LENGTH: usize = 64;
+pub const MAX_METADATA_VALUE_LENGTH: usize = 256;
+
+/// Options that distinguish one cart line from another line of the same product
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LineOptions {
+    pub variant_id: Option<String>, // Variant or SKU id, e.g. a size and colour combination
+    pub engraving: Option<String>,
+    pub gift_message: Option<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, Value>, // Flat map of string, number or boolean values
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LineOptionsError {
+    #[error("Variant id must be 1 to {} characters", MAX_VARIANT_ID_LENGTH)]
+    InvalidVariantId,
+
+    #[error("Engraving must be at most {} characters", MAX_ENGRAVING_LENGTH)]
+    EngravingTooLong,
+
+    #[error("Gift message must be at most {} characters", MAX_GIFT_MESSAGE_LENGTH)]
+    GiftMessageTooLong,
+
+    #[error("Metadata can have at most {} entries", MAX_METADATA_ENTRIES)]
+    TooManyMetadataEntries,
+
+    #[error("Invalid metadata key {0:?}: use up to {} letters, digits, '_', '-' or '.'", MAX_METADATA_KEY_LENGTH)]
+    InvalidMetadataKey(String),
+
+    #[error("Metadata value for {0:?} must be a string of at most {} characters, a number or a boolean", MAX_METADATA_VALUE_LENGTH)]
+    InvalidMetadataValue(String),
+}
+
+impl LineOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == LineOptions::default()
+    }
+
+    /// Check the options against the length and shape limits
+    pub fn validate(&self) -> Result<(), LineOptionsError> {
+        if let Some(variant_id) = &self.variant_id {
+            if variant_id.trim().is_empty() || variant_id.chars().count() > MAX_VARIANT_ID_LENGTH {
+                return Err(LineOptionsError::InvalidVariantId);
+            }
+        }
+        if matches!(&self.engraving, Some(text) if text.chars().count() > MAX_ENGRAVING_LENGTH) {
+            return Err(LineOptionsError::EngravingTooLong);
+        }
+        if matches!(&self.gift_message, Some(text) if text.chars().count() > MAX_GIFT_MESSAGE_LENGTH) {
+            return Err(LineOptionsError::GiftMessageTooLong);
+        }
+        if self.metadata.len() > MAX_METADATA_ENTRIES {
+            return Err(LineOptionsError::TooManyMetadataEntries);
+        }
+
+        for (key, value) in &self.metadata {
+            let valid_key = !key.is_empty()
+                && key.len() <= MAX_METADATA_KEY_LENGTH
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+            if !valid_key {
+                return Err(LineOptionsError::InvalidMetadataKey(key.clone()));
+            }
+
+            let valid_value = match value {
+                Value::String(text) => text.chars().count() <= MAX_METADATA_VALUE_LENGTH,
+                Value::Number(_) | Value::Bool(_) => true,
+                _ => false,
+            };
+            if !valid_value {
+                return Err(LineOptionsError::InvalidMetadataValue(key.clone()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stable key for the options, stored next to the line and part of its unique key.
+    /// Lines without options use an empty key.
+    pub fn key(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+
+        // Struct fields serialize in declaration order and the metadata map is sorted,
+        // so equal options always produce the same JSON
+        let canonical = serde_json::to_vec(self).expect("line options serialize to JSON");
+        format!("{:x}", Sha256::digest(&canonical))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn options(value: Value) -> LineOptions {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn options_at_the_limits_are_valid() {
+        let metadata: BTreeMap<String, Value> = (0..MAX_METADATA_ENTRIES)
+            .map(|i| (format!("key_{}", i), json!("v".repeat(MAX_METADATA_VALUE_LENGTH))))
+            .collect();
+        let options = LineOptions {
+            variant_id: Some("v".repeat(MAX_VARIANT_ID_LENGTH)),
+            // Lengths count characters, not bytes
+            engraving: Some("é".repeat(MAX_ENGRAVING_LENGTH)),
+            gift_message: Some("g".repeat(MAX_GIFT_MESSAGE_LENGTH)),
+            metadata,
+        };
+
+        assert_eq!(options.validate(), Ok(()));
+    }
+
+    #[test]
+    fn options_past_the_limits_are_rejected() {
+        let too_many_entries: BTreeMap<String, Value> =
+            (0..=MAX_METADATA_ENTRIES).map(|i| (format!("key_{}", i), json!(i))).collect();
+        let cases = [
+            (json!({ "variant_id": " " }), LineOptionsError::InvalidVariantId),
+            (
+                json!({ "variant_id": "v".repeat(MAX_VARIANT_ID_LENGTH + 1) }),
+                LineOptionsError::InvalidVariantId,
+            ),
+            (
+                json!({ "engraving": "e".repeat(MAX_ENGRAVING_LENGTH + 1) }),
+                LineOptionsError::EngravingTooLong,
+            ),
+            (
+                json!({ "gift_message": "g".repeat(MAX_GIFT_MESSAGE_LENGTH + 1) }),
+                LineOptionsError::GiftMessageTooLong,
+            ),
+            (
+                json!({ "metadata": too_many_entries }),
+                LineOptionsError::TooManyMetadataEntries,
+            ),
+            (
+                json!({ "metadata": { "has space": 1 } }),
+                LineOptionsError::InvalidMetadataKey("has space".to_string()),
+            ),
+            (
+                json!({ "metadata": { "nested": { "a": 1 } } }),
+                LineOptionsError::InvalidMetadataValue("nested".to_string()),
+            ),
+            (
+                json!({ "metadata": { "note": "n".repeat(MAX_METADATA_VALUE_LENGTH + 1) } }),
+                LineOptionsError::InvalidMetadataValue("note".to_string()),
+            ),
+        ];
+
+        for (value, error) in cases {
+            assert_eq!(options(value).validate(), Err(error));
+        }
+    }
+
+    #[test]
+    fn lines_without_options_have_an_empty_key() {
+        assert_eq!(LineOptions::default().key(), "");
+        assert_eq!(options(json!({ "variant_id": null, "metadata": {} })).key(), "");
+    }
+
+    #[test]
+    fn keys_depend_only_on_the_options() {
+        let a = options(json!({ "engraving": "Hi", "metadata": { "size": "L", "gift": true } }));
+        let b = options(json!({ "metadata": { "gift": true, "size": "L" }, "engraving": "Hi" }));
+        let c = options(json!({ "engraving": "Hi", "metadata": { "size": "M", "gift": true } }));
+
+        assert_eq!(a.key(), b.key());
+        assert_ne!(a.key(), c.key());
+        assert_eq!(a.key().len(), 64);
+    }
+
+    #[test]
+    fn keys_do_not_change_between_releases() {
+        // Keys are stored with cart lines, so changing how they are derived splits existing lines
+        let options = options(json!({ "variant_id": "sku-1", "engraving": "Hi", "metadata": { "size": "L" } }));
+
+        assert_eq!(
+            options.key(),
+            "416c2e4dfb8bfef62254a743a4b38e6871a82acd2e79cd9540d04dd1ac90ac1c"
+        );
+    }
+}
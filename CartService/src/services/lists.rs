@@ -0,0 +1,368 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::line_options::LineOptions;
+use super::outbox::{record_event, CartEvent};
+use super::{CartService, CartServiceError};
+use crate::repository::CartTransaction;
+
+/// Name of the list every user gets for items saved for later
+pub const SAVED_FOR_LATER: &str = "Saved for later";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemListKind {
+    SavedForLater,
+    Wishlist,
+}
+
+impl ItemListKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemListKind::SavedForLater => "saved_for_later",
+            ItemListKind::Wishlist => "wishlist",
+        }
+    }
+}
+
+impl FromStr for ItemListKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "saved_for_later" => Ok(ItemListKind::SavedForLater),
+            "wishlist" => Ok(ItemListKind::Wishlist),
+            other => Err(format!("Unknown list kind: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemListEntry {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub options: LineOptions,
+    pub quantity: u32,
+    pub added_at: DateTime<Utc>,
+}
+
+/// A named list of products kept alongside the cart
+#[derive(Debug, Serialize)]
+pub struct ItemList {
+    pub id: Uuid,
+    pub name: String,
+    pub kind: ItemListKind,
+    pub entries: Vec<ItemListEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ItemListNameRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveToListRequest {
+    pub list_id: Option<Uuid>, // Defaults to the saved-for-later list
+}
+
+impl CartService {
+    // Retrieve all of a user's lists with their entries
+    pub async fn get_item_lists(&self, user_id: Uuid) -> Result<Vec<ItemList>, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+        let lists = tx.item_lists(user_id).await?;
+        tx.commit().await?;
+
+        Ok(lists)
+    }
+
+    // Create a user wishlist
+    pub async fn create_wishlist(&self, user_id: Uuid, name: &str) -> Result<Uuid, CartServiceError> {
+        let list_id = Uuid::new_v4();
+
+        let mut tx = self.repository.begin().await?;
+        tx.insert_list(list_id, user_id, name, ItemListKind::Wishlist, Utc::now())
+            .await?;
+        tx.commit().await?;
+
+        Ok(list_id)
+    }
+
+    // Rename a user wishlist; the saved-for-later list keeps its name
+    pub async fn rename_wishlist(
+        &self,
+        user_id: Uuid,
+        list_id: Uuid,
+        name: &str,
+    ) -> Result<(), CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+
+        if !tx.rename_list(user_id, list_id, ItemListKind::Wishlist, name).await? {
+            return Err(CartServiceError::ListNotFound);
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Delete a user wishlist and its entries
+    pub async fn delete_wishlist(&self, user_id: Uuid, list_id: Uuid) -> Result<(), CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+
+        if !tx.delete_list(user_id, list_id, ItemListKind::Wishlist).await? {
+            return Err(CartServiceError::ListNotFound);
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Find the target list for a move, creating the saved-for-later list on first use
+    async fn resolve_list(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        list_id: Option<Uuid>,
+    ) -> Result<Uuid, CartServiceError> {
+        if let Some(list_id) = list_id {
+            if !tx.find_list(user_id, list_id).await? {
+                return Err(CartServiceError::ListNotFound);
+            }
+            return Ok(list_id);
+        }
+
+        if let Some(list_id) = tx.list_of_kind(user_id, ItemListKind::SavedForLater).await? {
+            return Ok(list_id);
+        }
+
+        let list_id = Uuid::new_v4();
+        tx.insert_list(list_id, user_id, SAVED_FOR_LATER, ItemListKind::SavedForLater, Utc::now())
+            .await?;
+
+        Ok(list_id)
+    }
+
+    // Move a cart line into a list in one transaction
+    pub async fn move_cart_item_to_list(
+        &self,
+        user_id: Uuid,
+        item_id: Uuid,
+        list_id: Option<Uuid>,
+    ) -> Result<Uuid, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+
+        let item = tx
+            .cart_line(user_id, item_id, true)
+            .await?
+            .ok_or(CartServiceError::ItemNotFound)?;
+
+        let list_id = self.resolve_list(&mut *tx, user_id, list_id).await?;
+
+        let now = Utc::now();
+        tx.add_to_list(list_id, item.product_id, &item.options, item.quantity, now)
+            .await?;
+        tx.delete_line(item_id).await?;
+
+        record_event(
+            &mut *tx,
+            CartEvent::ItemRemoved {
+                user_id,
+                item_id,
+                product_id: item.product_id,
+            },
+        )
+        .await?;
+
+        tx.touch_cart(user_id, now).await?;
+
+        tx.commit().await?;
+
+        Ok(list_id)
+    }
+
+    // Move a list entry back into the cart in one transaction
+    pub async fn move_list_entry_to_cart(
+        &self,
+        user_id: Uuid,
+        list_id: Uuid,
+        entry_id: Uuid,
+    ) -> Result<(), CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+
+        let entry = tx
+            .list_entry(user_id, list_id, entry_id)
+            .await?
+            .ok_or(CartServiceError::ListEntryNotFound)?;
+
+        self.check_line_limit(&mut *tx, user_id, entry.product_id, &entry.options.key())
+            .await?;
+        let in_cart = self.quantity_in_cart(&mut *tx, user_id, entry.product_id, None).await?;
+        self.validate_product(&mut *tx, user_id, entry.product_id, in_cart.saturating_add(entry.quantity))
+            .await?;
+
+        // Another request moved the entry first
+        if !tx.delete_list_entry(list_id, entry_id).await? {
+            return Err(CartServiceError::ListEntryNotFound);
+        }
+
+        let now = Utc::now();
+        tx.add_to_line(user_id, entry.product_id, &entry.options, entry.quantity, now)
+            .await?;
+
+        record_event(
+            &mut *tx,
+            CartEvent::ItemAdded {
+                user_id,
+                product_id: entry.product_id,
+                quantity: entry.quantity,
+            },
+        )
+        .await?;
+
+        tx.touch_cart(user_id, now).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    use crate::services::testing::{cart_service, product};
+
+    fn gift_wrapped() -> LineOptions {
+        LineOptions {
+            gift_message: Some("Happy birthday".to_string()),
+            ..LineOptions::default()
+        }
+    }
+
+    // Put a line in the cart and return its id
+    async fn add_line(service: &CartService, user_id: Uuid, product_id: Uuid, quantity: u32) -> Uuid {
+        service
+            .add_item_to_cart(user_id, product_id, quantity, &gift_wrapped(), None)
+            .await
+            .unwrap();
+        let cart = service.get_cart(user_id).await.unwrap();
+        cart.items.iter().find(|item| item.product_id == product_id).unwrap().id
+    }
+
+    #[tokio::test]
+    async fn cart_lines_are_saved_for_later_in_one_list() {
+        let product = product(Decimal::new(1000, 2), 10);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+
+        let item_id = add_line(&service, user_id, product.id, 2).await;
+        let list_id = service.move_cart_item_to_list(user_id, item_id, None).await.unwrap();
+        let item_id = add_line(&service, user_id, product.id, 1).await;
+        let again = service.move_cart_item_to_list(user_id, item_id, None).await.unwrap();
+
+        assert_eq!(again, list_id);
+        assert!(service.get_cart(user_id).await.unwrap().items.is_empty());
+        let lists = service.get_item_lists(user_id).await.unwrap();
+        assert_eq!(lists.len(), 1);
+        assert_eq!((lists[0].name.as_str(), lists[0].kind), (SAVED_FOR_LATER, ItemListKind::SavedForLater));
+        assert_eq!(lists[0].entries.len(), 1);
+        assert_eq!(lists[0].entries[0].quantity, 3);
+        assert_eq!(lists[0].entries[0].options, gift_wrapped());
+    }
+
+    #[tokio::test]
+    async fn list_entries_move_back_into_the_cart_once() {
+        let product = product(Decimal::new(1000, 2), 10);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+        let list_id = service.create_wishlist(user_id, "Ideas").await.unwrap();
+        let item_id = add_line(&service, user_id, product.id, 2).await;
+        service
+            .move_cart_item_to_list(user_id, item_id, Some(list_id))
+            .await
+            .unwrap();
+        let entry_id = service.get_item_lists(user_id).await.unwrap()[0].entries[0].id;
+
+        service.move_list_entry_to_cart(user_id, list_id, entry_id).await.unwrap();
+
+        let cart = service.get_cart(user_id).await.unwrap();
+        assert_eq!(cart.items.len(), 1);
+        assert_eq!((cart.items[0].quantity, &cart.items[0].options), (2, &gift_wrapped()));
+        assert!(service.get_item_lists(user_id).await.unwrap()[0].entries.is_empty());
+        assert!(matches!(
+            service.move_list_entry_to_cart(user_id, list_id, entry_id).await,
+            Err(CartServiceError::ListEntryNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn entries_stay_listed_when_the_stock_is_gone() {
+        let product = product(Decimal::new(1000, 2), 2);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+        let item_id = add_line(&service, user_id, product.id, 2).await;
+        let list_id = service.move_cart_item_to_list(user_id, item_id, None).await.unwrap();
+        let entry_id = service.get_item_lists(user_id).await.unwrap()[0].entries[0].id;
+        service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.move_list_entry_to_cart(user_id, list_id, entry_id).await,
+            Err(CartServiceError::InsufficientStock { .. })
+        ));
+        assert_eq!(service.get_item_lists(user_id).await.unwrap()[0].entries.len(), 1);
+        assert_eq!(service.get_cart(user_id).await.unwrap().items[0].quantity, 1);
+    }
+
+    #[tokio::test]
+    async fn lists_are_only_reachable_by_their_owner() {
+        let product = product(Decimal::new(1000, 2), 10);
+        let service = cart_service([product.clone()]).service;
+        let (owner, stranger) = (Uuid::new_v4(), Uuid::new_v4());
+        let list_id = service.create_wishlist(owner, "Ideas").await.unwrap();
+        let item_id = add_line(&service, stranger, product.id, 1).await;
+
+        assert!(matches!(
+            service.rename_wishlist(stranger, list_id, "Mine").await,
+            Err(CartServiceError::ListNotFound)
+        ));
+        assert!(matches!(
+            service.delete_wishlist(stranger, list_id).await,
+            Err(CartServiceError::ListNotFound)
+        ));
+        assert!(matches!(
+            service.move_cart_item_to_list(stranger, item_id, Some(list_id)).await,
+            Err(CartServiceError::ListNotFound)
+        ));
+        assert_eq!(service.get_cart(stranger).await.unwrap().items.len(), 1);
+
+        service.rename_wishlist(owner, list_id, "Gifts").await.unwrap();
+        service.delete_wishlist(owner, list_id).await.unwrap();
+        assert!(service.get_item_lists(owner).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_saved_for_later_list_is_not_a_wishlist() {
+        let product = product(Decimal::new(1000, 2), 10);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+        let item_id = add_line(&service, user_id, product.id, 1).await;
+        let list_id = service.move_cart_item_to_list(user_id, item_id, None).await.unwrap();
+
+        assert!(matches!(
+            service.rename_wishlist(user_id, list_id, "Renamed").await,
+            Err(CartServiceError::ListNotFound)
+        ));
+        assert!(matches!(
+            service.delete_wishlist(user_id, list_id).await,
+            Err(CartServiceError::ListNotFound)
+        ));
+    }
+}
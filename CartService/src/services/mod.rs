@@ -1,19 +1,131 @@
-use sqlx::MySqlPool;
+pub mod abandonment;
+pub mod batch;
+pub mod catalog;
+pub mod currency;
+pub mod inventory;
+pub mod limits;
+pub mod line_options;
+pub mod lists;
+pub mod orders;
+pub mod outbox;
+pub mod pricing;
+pub mod promotions;
+pub mod sharing;
+pub mod shipping;
+pub mod tax;
+#[cfg(test)]
+pub mod testing;
+
 use uuid::Uuid;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::repository::{CartRepository, CartTransaction};
+use crate::utils::cart_token;
+use catalog::{CatalogError, Product, ProductCatalog};
+use currency::{Currency, ExchangeRateError, ExchangeRateProvider};
+use pricing::{PricedCart, PricingContext, PricingEngine};
+use promotions::CouponRejection;
+use shipping::{ShippingCalculator, ShippingError};
+use tax::TaxRulesStore;
+use orders::OrderStatus;
+use inventory::StockLedger;
+use limits::{CartLimits, LimitViolation};
+use line_options::{LineOptions, LineOptionsError};
+use abandonment::CartNotifier;
+use outbox::{record_event, CartEvent, EventPublisher};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CartItem {
     pub id: Uuid,
     pub product_id: Uuid,
     pub quantity: u32,
+    #[serde(default)]
+    pub options: LineOptions,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Cart {
     pub user_id: Uuid,
     pub items: Vec<CartItem>,
+    pub version: u64, // Incremented on every change, exposed as the ETag
+    pub last_activity_at: Option<DateTime<Utc>>,
+    pub currency: Currency,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PriceCartQuery {
+    pub currency: Option<Currency>, // Defaults to the cart's own currency
+    pub country: Option<String>,    // Where to compute tax for; defaults to the default tax country
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetCartCurrencyRequest {
+    pub currency: Currency,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddCartItemRequest {
+    pub product_id: Uuid,
+    pub quantity: u32,
+    #[serde(default)]
+    pub options: LineOptions, // Lines with different options are kept separate
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateCartItemRequest {
+    pub quantity: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeGuestCartRequest {
+    pub cart_token: String,
+    pub strategy: Option<MergeStrategy>,
+}
+
+/// How to resolve a product that is in both the guest cart and the user cart
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Add the guest quantity to the user quantity
+    #[default]
+    Sum,
+    /// Keep the quantity of the most recently updated line
+    KeepNewest,
+    /// Keep the larger of the two quantities
+    KeepMax,
+}
+
+impl MergeStrategy {
+    pub fn resolve(
+        &self,
+        existing: (u32, DateTime<Utc>),
+        incoming: (u32, DateTime<Utc>),
+    ) -> u32 {
+        match self {
+            MergeStrategy::Sum => existing.0.saturating_add(incoming.0),
+            MergeStrategy::KeepNewest if incoming.1 > existing.1 => incoming.0,
+            MergeStrategy::KeepNewest => existing.0,
+            MergeStrategy::KeepMax => existing.0.max(incoming.0),
+        }
+    }
+}
+
+impl FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "sum" => Ok(MergeStrategy::Sum),
+            "keep_newest" => Ok(MergeStrategy::KeepNewest),
+            "keep_max" => Ok(MergeStrategy::KeepMax),
+            other => Err(format!("Unknown cart merge strategy: {}", other)),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -23,91 +135,576 @@ pub enum CartServiceError {
 
     #[error("Cart item not found")]
     ItemNotFound,
+
+    #[error("Cart was modified concurrently; current version is {current}")]
+    VersionMismatch { current: u64 },
+
+    #[error("Invalid cart token")]
+    InvalidCartToken,
+
+    #[error("Product catalog error: {0}")]
+    CatalogError(#[from] CatalogError),
+
+    #[error("Exchange rate error: {0}")]
+    ExchangeRateError(#[from] ExchangeRateError),
+
+    #[error("Invalid line options: {0}")]
+    InvalidLineOptions(#[from] LineOptionsError),
+
+    #[error("Cart limit exceeded: {0}")]
+    LimitExceeded(#[from] LimitViolation),
+
+    #[error("Product {0} does not exist")]
+    ProductNotFound(Uuid),
+
+    #[error("Product {0} is not available")]
+    ProductInactive(Uuid),
+
+    #[error("Not enough stock for product {product_id}: requested {requested}, available {available}")]
+    InsufficientStock {
+        product_id: Uuid,
+        requested: u32,
+        available: u32,
+    },
+
+    #[error("Coupon not found")]
+    CouponNotFound,
+
+    #[error("Coupon rejected: {0}")]
+    CouponRejected(#[from] CouponRejection),
+
+    #[error("Stock for product {product_id} is reserved by other shoppers: requested {requested}, available {available}")]
+    StockReserved {
+        product_id: Uuid,
+        requested: u32,
+        available: u32,
+    },
+
+    #[error("Cart is empty")]
+    EmptyCart,
+
+    #[error("A batch can contain at most {0} operations")]
+    BatchTooLarge(usize),
+
+    #[error("Shipping error: {0}")]
+    ShippingError(#[from] ShippingError),
+
+    #[error("Shipping method {0} is not available for this cart and address")]
+    ShippingUnavailable(String),
+
+    #[error("List not found")]
+    ListNotFound,
+
+    #[error("List entry not found")]
+    ListEntryNotFound,
+
+    #[error("Order not found")]
+    OrderNotFound,
+
+    #[error("Shared cart not found")]
+    SharedCartNotFound,
+
+    #[error("Shared cart link has expired")]
+    SharedCartExpired,
+
+    #[error("Order cannot move from {from} to {to}")]
+    IllegalTransition {
+        from: OrderStatus,
+        to: OrderStatus,
+    },
 }
 
 pub struct CartService {
-    pub db_pool: MySqlPool,
+    pub repository: Arc<dyn CartRepository>,
+    pub cart_token_secret: String, // Secret used to sign guest cart tokens
+    pub jwt_secret: String, // Secret shared with UserService to verify access tokens
+    pub internal_api_key: Option<String>, // Key internal callers send to change order status
+    pub merge_strategy: MergeStrategy, // Default conflict rule when merging guest carts
+    pub catalog: Arc<dyn ProductCatalog>,
+    pub pricing: PricingEngine,
+    pub limits: CartLimits, // Cart-wide limits; products can add their own purchase rules
+    pub stock_ledger: Arc<dyn StockLedger>,
+    pub reservation_ttl: chrono::Duration, // How long checkout holds stock before releasing it
+    pub notifier: Arc<dyn CartNotifier>,
+    pub idempotency_ttl: chrono::Duration, // How long responses to Idempotency-Key requests are kept
+    pub publisher: Option<Arc<dyn EventPublisher>>, // Where the outbox relay delivers cart events; events stay in the outbox without one
+    pub catalog_currency: Currency, // Currency of catalog prices and coupon amounts
+    pub default_currency: Currency, // Currency of carts that have not chosen one
+    pub exchange_rates: Arc<dyn ExchangeRateProvider>,
+    pub shipping: Arc<dyn ShippingCalculator>,
+    pub tax_rules: Arc<TaxRulesStore>,
+    pub default_tax_country: Option<String>, // Used to estimate tax before a destination is known
 }
 
 impl CartService {
+    // Whether UserService revoked an access token before it expired
+    pub async fn is_token_blacklisted(&self, token: &str) -> Result<bool, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+        let blacklisted = tx.is_token_blacklisted(token, Utc::now()).await?;
+        tx.commit().await?;
+
+        Ok(blacklisted)
+    }
+
     // Retrieve a user's cart
     pub async fn get_cart(&self, user_id: Uuid) -> Result<Cart, CartServiceError> {
-        let items = sqlx::query_as!(
-            CartItem,
-            r#"
-            SELECT id, product_id, quantity
-            FROM cart_items
-            WHERE user_id = ?
-            "#,
-            user_id
-        )
-        .fetch_all(&self.db_pool)
-        .await?;
+        let mut tx = self.repository.begin().await?;
+        let items = tx
+            .cart_lines(user_id, false)
+            .await?
+            .into_iter()
+            .map(CartItem::from)
+            .collect();
+        let activity = tx.cart(user_id).await?;
+        tx.commit().await?;
+
+        let currency = activity
+            .as_ref()
+            .and_then(|a| a.currency.as_deref())
+            .and_then(|code| code.parse().ok())
+            .unwrap_or(self.default_currency);
 
-        Ok(Cart { user_id, items })
+        Ok(Cart {
+            user_id,
+            items,
+            version: activity.as_ref().map_or(0, |a| a.version),
+            last_activity_at: activity.map(|a| a.last_activity_at),
+            currency,
+        })
     }
 
-    // Add an item to the cart
+    // Retrieve a user's cart along with the catalog entries for its products
+    async fn load_cart_with_products(
+        &self,
+        user_id: Uuid,
+    ) -> Result<(Cart, HashMap<Uuid, Product>), CartServiceError> {
+        let cart = self.get_cart(user_id).await?;
+        let product_ids: Vec<Uuid> = cart.items.iter().map(|item| item.product_id).collect();
+        let products = self.catalog.get_products(&product_ids).await?;
+
+        Ok((cart, products))
+    }
+
+    // Retrieve a user's cart with prices, discounts, tax and totals resolved from the catalog,
+    // in the requested currency or else the cart's own
+    pub async fn get_priced_cart(
+        &self,
+        user_id: Uuid,
+        query: &PriceCartQuery,
+    ) -> Result<PricedCart, CartServiceError> {
+        let (cart, products) = self.load_cart_with_products(user_id).await?;
+        let coupons = self.get_applied_coupons(user_id).await?;
+        let context = PricingContext {
+            tax: self.tax_context(query.country.as_deref(), query.region.as_deref()),
+            ..PricingContext::new(self.conversion_to(query.currency.unwrap_or(cart.currency)).await?)
+        };
+
+        Ok(self.pricing.price(cart, &products, &coupons, &context, Utc::now()))
+    }
+
+    // Lock the cart's version row and check it against the version the client last saw.
+    // Returns the current version, 0 for a cart that has never been modified.
+    async fn check_version(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CartServiceError> {
+        let current = tx.lock_cart_version(user_id).await?.unwrap_or(0);
+
+        match expected_version {
+            Some(expected) if expected != current => Err(CartServiceError::VersionMismatch { current }),
+            _ => Ok(current),
+        }
+    }
+
+    // Total quantity of a product across the cart's lines, optionally leaving one line out
+    pub(crate) async fn quantity_in_cart(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        product_id: Uuid,
+        excluding_item: Option<Uuid>,
+    ) -> Result<u32, CartServiceError> {
+        let quantity = tx
+            .cart_lines(user_id, false)
+            .await?
+            .iter()
+            .filter(|line| line.product_id == product_id && Some(line.id) != excluding_item)
+            .map(|line| line.quantity)
+            .fold(0, u32::saturating_add);
+
+        Ok(quantity)
+    }
+
+    // Add an item to the cart, merging quantities into an existing line for the same product and options.
+    // Returns the new cart version.
     pub async fn add_item_to_cart(
         &self,
         user_id: Uuid,
         product_id: Uuid,
         quantity: u32,
-    ) -> Result<(), CartServiceError> {
-        let item_id = Uuid::new_v4();
-
-        sqlx::query!(
-            r#"
-            INSERT INTO cart_items (id, user_id, product_id, quantity)
-            VALUES (?, ?, ?, ?)
-            "#,
-            item_id,
-            user_id,
-            product_id,
-            quantity
+        options: &LineOptions,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CartServiceError> {
+        limits::check_added_quantity(product_id, quantity)?;
+        options.validate()?;
+
+        let mut tx = self.repository.begin().await?;
+        self.check_version(&mut *tx, user_id, expected_version).await?;
+        self.check_line_limit(&mut *tx, user_id, product_id, &options.key()).await?;
+
+        // Stock and purchase rules apply per product, so every line of the product counts
+        let in_cart = self.quantity_in_cart(&mut *tx, user_id, product_id, None).await?;
+        self.validate_product(&mut *tx, user_id, product_id, in_cart.saturating_add(quantity))
+            .await?;
+
+        let now = Utc::now();
+        tx.add_to_line(user_id, product_id, options, quantity, now).await?;
+
+        record_event(
+            &mut *tx,
+            CartEvent::ItemAdded {
+                user_id,
+                product_id,
+                quantity,
+            },
         )
-        .execute(&self.db_pool)
         .await?;
 
-        Ok(())
+        let version = tx.touch_cart(user_id, now).await?;
+        tx.commit().await?;
+
+        Ok(version)
     }
 
-    // Remove an item from the cart
-    pub async fn remove_item_from_cart(
+    // Set the absolute quantity of a cart line; a quantity of 0 removes the line
+    pub async fn set_item_quantity(
         &self,
         user_id: Uuid,
         item_id: Uuid,
-    ) -> Result<(), CartServiceError> {
-        let result = sqlx::query!(
-            r#"
-            DELETE FROM cart_items
-            WHERE user_id = ? AND id = ?
-            "#,
-            user_id,
-            item_id
+        quantity: u32,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CartServiceError> {
+        if quantity == 0 {
+            return self.remove_item_from_cart(user_id, item_id, expected_version).await;
+        }
+
+        let mut tx = self.repository.begin().await?;
+        self.check_version(&mut *tx, user_id, expected_version).await?;
+
+        let line = tx
+            .cart_line(user_id, item_id, true)
+            .await?
+            .ok_or(CartServiceError::ItemNotFound)?;
+
+        let other_lines = self
+            .quantity_in_cart(&mut *tx, user_id, line.product_id, Some(item_id))
+            .await?;
+        self.validate_product(&mut *tx, user_id, line.product_id, other_lines.saturating_add(quantity))
+            .await?;
+
+        let now = Utc::now();
+        if !tx.set_line_quantity(user_id, item_id, quantity, now).await? {
+            return Err(CartServiceError::ItemNotFound);
+        }
+
+        record_event(
+            &mut *tx,
+            CartEvent::ItemQuantityChanged {
+                user_id,
+                item_id,
+                product_id: line.product_id,
+                quantity,
+            },
         )
-        .execute(&self.db_pool)
         .await?;
 
-        if result.rows_affected() == 0 {
-            return Err(CartServiceError::ItemNotFound);
+        let version = tx.touch_cart(user_id, now).await?;
+        tx.commit().await?;
+
+        Ok(version)
+    }
+
+    // Check that a product exists, is active, allows the requested quantity under the cart limits
+    // and its purchase rules, and has enough unreserved stock for it
+    pub(crate) async fn validate_product(
+        &self,
+        tx: &mut dyn CartTransaction,
+        user_id: Uuid,
+        product_id: Uuid,
+        requested: u32,
+    ) -> Result<(), CartServiceError> {
+        let product = self
+            .catalog
+            .get_product(product_id)
+            .await?
+            .ok_or(CartServiceError::ProductNotFound(product_id))?;
+
+        if !product.is_active {
+            return Err(CartServiceError::ProductInactive(product_id));
+        }
+
+        self.limits.check_quantity(&product, requested)?;
+        self.check_customer_limit(tx, user_id, &product, requested).await?;
+
+        if requested > product.quantity {
+            return Err(CartServiceError::InsufficientStock {
+                product_id,
+                requested,
+                available: product.quantity,
+            });
+        }
+
+        let reserved = self.reserved_elsewhere(tx, user_id, product_id).await?;
+        let available = product.quantity.saturating_sub(reserved);
+        if requested > available {
+            return Err(CartServiceError::StockReserved {
+                product_id,
+                requested,
+                available,
+            });
         }
 
         Ok(())
     }
 
-    // Clear the user's cart
-    pub async fn clear_cart(&self, user_id: Uuid) -> Result<(), CartServiceError> {
-        sqlx::query!(
-            r#"
-            DELETE FROM cart_items
-            WHERE user_id = ?
-            "#,
-            user_id
+    // Remove an item from the cart
+    pub async fn remove_item_from_cart(
+        &self,
+        user_id: Uuid,
+        item_id: Uuid,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+        self.check_version(&mut *tx, user_id, expected_version).await?;
+
+        let line = tx
+            .cart_line(user_id, item_id, true)
+            .await?
+            .ok_or(CartServiceError::ItemNotFound)?;
+
+        tx.delete_line(item_id).await?;
+
+        record_event(
+            &mut *tx,
+            CartEvent::ItemRemoved {
+                user_id,
+                item_id,
+                product_id: line.product_id,
+            },
         )
-        .execute(&self.db_pool)
         .await?;
 
+        let version = tx.touch_cart(user_id, Utc::now()).await?;
+        tx.commit().await?;
+
+        Ok(version)
+    }
+
+    // Clear the user's cart
+    pub async fn clear_cart(
+        &self,
+        user_id: Uuid,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+        self.check_version(&mut *tx, user_id, expected_version).await?;
+
+        tx.delete_lines(user_id).await?;
+
+        record_event(&mut *tx, CartEvent::CartCleared { user_id }).await?;
+
+        let version = tx.touch_cart(user_id, Utc::now()).await?;
+        tx.commit().await?;
+
+        Ok(version)
+    }
+
+    // Resolve the guest cart for a token, issuing a new cart id and token when none is given.
+    // Guest carts are stored under their cart id in place of a user id.
+    pub fn resolve_guest_cart(
+        &self,
+        token: Option<&str>,
+    ) -> Result<(Uuid, Option<String>), CartServiceError> {
+        match token {
+            Some(token) => cart_token::verify_cart_token(token, &self.cart_token_secret)
+                .map(|cart_id| (cart_id, None))
+                .ok_or(CartServiceError::InvalidCartToken),
+            None => {
+                let cart_id = Uuid::new_v4();
+                let token = cart_token::issue_cart_token(cart_id, &self.cart_token_secret);
+                Ok((cart_id, Some(token)))
+            }
+        }
+    }
+
+    // Merge a guest cart into a user's cart and delete the guest cart
+    pub async fn merge_guest_cart(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        strategy: Option<MergeStrategy>,
+    ) -> Result<(), CartServiceError> {
+        let guest_cart_id = cart_token::verify_cart_token(token, &self.cart_token_secret)
+            .ok_or(CartServiceError::InvalidCartToken)?;
+        let strategy = strategy.unwrap_or(self.merge_strategy);
+
+        let mut tx = self.repository.begin().await?;
+
+        let guest_lines = tx.cart_lines(guest_cart_id, true).await?;
+        let user_lines = tx.cart_lines(user_id, true).await?;
+        let mut merged_products = Vec::new();
+
+        for guest_line in guest_lines {
+            if !merged_products.contains(&guest_line.product_id) {
+                merged_products.push(guest_line.product_id);
+            }
+
+            // Lines only merge when the product and its options match
+            let options_key = guest_line.options.key();
+            let existing = user_lines
+                .iter()
+                .find(|line| line.product_id == guest_line.product_id && line.options.key() == options_key);
+
+            match existing {
+                Some(existing) => {
+                    let quantity = strategy.resolve(
+                        (existing.quantity, existing.updated_at),
+                        (guest_line.quantity, guest_line.updated_at),
+                    );
+
+                    tx.set_line_quantity(
+                        user_id,
+                        existing.id,
+                        quantity,
+                        existing.updated_at.max(guest_line.updated_at),
+                    )
+                    .await?;
+                    tx.delete_line(guest_line.id).await?;
+
+                    record_event(
+                        &mut *tx,
+                        CartEvent::ItemQuantityChanged {
+                            user_id,
+                            item_id: existing.id,
+                            product_id: existing.product_id,
+                            quantity,
+                        },
+                    )
+                    .await?;
+                }
+                None => {
+                    // Move the line over to the user's cart as-is
+                    tx.move_line(guest_line.id, user_id).await?;
+
+                    record_event(
+                        &mut *tx,
+                        CartEvent::ItemAdded {
+                            user_id,
+                            product_id: guest_line.product_id,
+                            quantity: guest_line.quantity,
+                        },
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        // The merged cart has to pass the same limits and stock checks as items added one by
+        // one; if it does not, nothing is merged and the guest cart is kept
+        let merged_lines = tx.cart_lines(user_id, false).await?;
+        if merged_lines.len() > user_lines.len() {
+            self.limits.check_line_count(merged_lines.len())?;
+        }
+        for product_id in merged_products {
+            let quantity = merged_lines
+                .iter()
+                .filter(|line| line.product_id == product_id)
+                .map(|line| line.quantity)
+                .fold(0, u32::saturating_add);
+            self.validate_product(&mut *tx, user_id, product_id, quantity).await?;
+        }
+
+        tx.delete_cart(guest_cart_id).await?;
+        tx.touch_cart(user_id, Utc::now()).await?;
+
+        tx.commit().await?;
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use rust_decimal::Decimal;
+    use testing::{cart_service, product};
+
+    #[test]
+    fn merge_strategies_resolve_quantities() {
+        let older = Utc::now() - Duration::hours(1);
+        let newer = Utc::now();
+
+        assert_eq!(MergeStrategy::Sum.resolve((2, older), (3, newer)), 5);
+        assert_eq!(MergeStrategy::Sum.resolve((u32::MAX, older), (1, newer)), u32::MAX);
+        assert_eq!(MergeStrategy::KeepNewest.resolve((2, older), (3, newer)), 3);
+        assert_eq!(MergeStrategy::KeepNewest.resolve((2, newer), (3, older)), 2);
+        assert_eq!(MergeStrategy::KeepMax.resolve((7, older), (3, newer)), 7);
+        assert_eq!(MergeStrategy::KeepMax.resolve((2, newer), (3, older)), 3);
+    }
+
+    #[test]
+    fn merge_strategy_parses_config_values() {
+        assert_eq!("keep_newest".parse(), Ok(MergeStrategy::KeepNewest));
+        assert_eq!("keep_max".parse(), Ok(MergeStrategy::KeepMax));
+        assert!("newest".parse::<MergeStrategy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn adding_nothing_is_rejected() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+
+        assert!(matches!(
+            service
+                .add_item_to_cart(user_id, product.id, 0, &LineOptions::default(), None)
+                .await,
+            Err(CartServiceError::LimitExceeded(LimitViolation::ZeroQuantity { product_id })) if product_id == product.id
+        ));
+        let cart = service.get_cart(user_id).await.unwrap();
+        assert!(cart.items.is_empty());
+        assert_eq!(cart.version, 0);
+    }
+
+    #[tokio::test]
+    async fn lines_are_kept_apart_by_their_options() {
+        let product = product(Decimal::new(1000, 2), 10);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+        let engraved = |text: &str| LineOptions {
+            engraving: Some(text.to_string()),
+            ..LineOptions::default()
+        };
+
+        for options in [LineOptions::default(), engraved("Ana"), engraved("Ana"), engraved("Bo")] {
+            service
+                .add_item_to_cart(user_id, product.id, 1, &options, None)
+                .await
+                .unwrap();
+        }
+
+        let cart = service.get_cart(user_id).await.unwrap();
+        let lines: Vec<(LineOptions, u32)> =
+            cart.items.iter().map(|item| (item.options.clone(), item.quantity)).collect();
+        assert_eq!(lines, vec![(LineOptions::default(), 1), (engraved("Ana"), 2), (engraved("Bo"), 1)]);
+        assert!(matches!(
+            service
+                .add_item_to_cart(user_id, product.id, 1, &engraved(&"x".repeat(100)), None)
+                .await,
+            Err(CartServiceError::InvalidLineOptions(LineOptionsError::EngravingTooLong))
+        ));
+    }
+}
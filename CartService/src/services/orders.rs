@@ -0,0 +1,516 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use super::currency::Currency;
+use super::line_options::LineOptions;
+use super::outbox::{record_event, CartEvent};
+use super::pricing::PricingContext;
+use super::promotions::AppliedDiscount;
+use super::shipping::{ShippingAddress, ShippingLine};
+use super::tax::TaxBreakdown;
+use super::{Cart, CartItem, CartService, CartServiceError};
+
+/// Lifecycle of an order:
+/// pending_payment -> paid -> fulfilled -> completed,
+/// with cancelled and refunded as terminal branches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    PendingPayment,
+    Paid,
+    Fulfilled,
+    Completed,
+    Cancelled,
+    Refunded,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::PendingPayment => "pending_payment",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Fulfilled => "fulfilled",
+            OrderStatus::Completed => "completed",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+
+    /// Whether the state machine allows moving from this status to `next`
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        use OrderStatus::*;
+
+        matches!(
+            (self, next),
+            (PendingPayment, Paid)
+                | (PendingPayment, Cancelled)
+                | (Paid, Fulfilled)
+                | (Paid, Cancelled)
+                | (Paid, Refunded)
+                | (Fulfilled, Completed)
+                | (Fulfilled, Refunded)
+                | (Completed, Refunded)
+        )
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending_payment" => Ok(OrderStatus::PendingPayment),
+            "paid" => Ok(OrderStatus::Paid),
+            "fulfilled" => Ok(OrderStatus::Fulfilled),
+            "completed" => Ok(OrderStatus::Completed),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "refunded" => Ok(OrderStatus::Refunded),
+            other => Err(format!("Unknown order status: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderLine {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub name: String,
+    pub options: LineOptions,
+    pub quantity: u32,
+    pub unit_price: Decimal,
+    pub line_total: Decimal,
+}
+
+/// A priced snapshot of a cart at checkout time
+#[derive(Debug, Clone, Serialize)]
+pub struct Order {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub status: OrderStatus,
+    pub currency: Currency,
+    pub lines: Vec<OrderLine>,
+    pub shipping: ShippingLine,
+    pub discounts: Vec<AppliedDiscount>,
+    pub subtotal: Decimal,
+    pub discount_total: Decimal,
+    pub tax_total: Decimal,
+    pub tax: Option<TaxBreakdown>,
+    pub shipping_total: Decimal,
+    pub grand_total: Decimal,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckoutRequest {
+    pub shipping_address: ShippingAddress,
+    pub shipping_method: String, // Code of one of the cart's shipping options
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateOrderStatusRequest {
+    pub status: OrderStatus,
+}
+
+impl CartService {
+    // Snapshot the priced cart into an order and empty the cart, in a single transaction
+    pub async fn checkout(&self, user_id: Uuid, request: &CheckoutRequest) -> Result<Order, CartServiceError> {
+        // The catalog, exchange rate and shipping lookups can go over the network, so they are
+        // made before the cart is locked rather than while concurrent edits wait on the lock
+        let cart = self.get_cart(user_id).await?;
+        if cart.items.is_empty() {
+            return Err(CartServiceError::EmptyCart);
+        }
+
+        let mut tx = self.repository.begin().await?;
+        let quoted_coupons = tx.applied_coupons(user_id).await?;
+        tx.commit().await?;
+
+        let product_ids: Vec<Uuid> = cart.items.iter().map(|item| item.product_id).collect();
+        let products = self.catalog.get_products(&product_ids).await?;
+
+        // Products can be taken off sale after they were added
+        if let Some(product) = products.values().find(|product| !product.is_active) {
+            return Err(CartServiceError::ProductInactive(product.id));
+        }
+
+        let conversion = self.conversion_to(cart.currency).await?;
+
+        let shipment = self.shipment_for(&cart, &products, &quoted_coupons);
+        let option = self
+            .shipping
+            .options(&request.shipping_address, &shipment)
+            .await?
+            .into_iter()
+            .find(|option| option.code == request.shipping_method)
+            .ok_or_else(|| CartServiceError::ShippingUnavailable(request.shipping_method.clone()))?;
+
+        let mut tx = self.repository.begin().await?;
+
+        // Lock the cart so concurrent edits wait for the checkout to finish, and check it is
+        // still the cart the lookups were made for
+        let version = self.check_version(&mut *tx, user_id, Some(cart.version)).await?;
+        let items: Vec<CartItem> = tx
+            .cart_lines(user_id, true)
+            .await?
+            .into_iter()
+            .map(CartItem::from)
+            .collect();
+
+        // Purchase rules may have changed since the items were added, and other orders
+        // count towards per-customer caps, so check them again
+        let mut quantities: HashMap<Uuid, u32> = HashMap::new();
+        for item in &items {
+            let quantity = quantities.entry(item.product_id).or_default();
+            *quantity = quantity.saturating_add(item.quantity);
+        }
+        for (product_id, &quantity) in &quantities {
+            if let Some(product) = products.get(product_id) {
+                self.limits.check_quantity(product, quantity)?;
+                self.check_customer_limit(&mut *tx, user_id, product, quantity).await?;
+            }
+        }
+
+        // Coupons do not change the cart version, but they feed into the shipping quote
+        let coupons = tx.applied_coupons(user_id).await?;
+        let mut codes: Vec<&str> = coupons.iter().map(|coupon| coupon.code.as_str()).collect();
+        let mut quoted_codes: Vec<&str> = quoted_coupons.iter().map(|coupon| coupon.code.as_str()).collect();
+        codes.sort_unstable();
+        quoted_codes.sort_unstable();
+        if codes != quoted_codes {
+            return Err(CartServiceError::VersionMismatch { current: version });
+        }
+
+        // Other checkouts may have used up a coupon since it was applied. Lock the coupons,
+        // in a fixed order so checkouts cannot deadlock, and check their limits again
+        for code in codes {
+            tx.lock_coupon(code).await?;
+        }
+        for coupon in &coupons {
+            let redemptions = tx.coupon_redemptions(&coupon.code, user_id).await?;
+            coupon.check_redemptions(&redemptions)?;
+        }
+
+        let cart = Cart { items, ..cart };
+
+        let address = &request.shipping_address;
+        let context = PricingContext {
+            conversion,
+            shipping: Some(option.price),
+            tax: self.tax_context(Some(&address.country), address.region.as_deref()),
+        };
+
+        let now = Utc::now();
+        let priced = self.pricing.price(cart, &products, &coupons, &context, now);
+
+        if let Some(item) = priced.unavailable_items.first() {
+            return Err(CartServiceError::ProductNotFound(item.product_id));
+        }
+
+        let order = Order {
+            id: Uuid::new_v4(),
+            user_id,
+            status: OrderStatus::PendingPayment,
+            currency: priced.currency,
+            lines: priced
+                .lines
+                .iter()
+                .map(|line| OrderLine {
+                    id: Uuid::new_v4(),
+                    product_id: line.product_id,
+                    name: line.name.clone(),
+                    options: line.options.clone(),
+                    quantity: line.quantity,
+                    unit_price: line.unit_price,
+                    line_total: line.line_total,
+                })
+                .collect(),
+            shipping: ShippingLine {
+                method: option.code,
+                name: option.name,
+                address: request.shipping_address.clone(),
+                price: priced.shipping_total,
+            },
+            discounts: priced.discounts,
+            subtotal: priced.subtotal,
+            discount_total: priced.discount_total,
+            tax_total: priced.tax_total,
+            tax: priced.tax,
+            shipping_total: priced.shipping_total,
+            grand_total: priced.grand_total,
+            created_at: now,
+            updated_at: now,
+        };
+
+        tx.insert_order(&order).await?;
+
+        // Hold the stock until the order is paid or the reservation expires
+        self.reserve_order_lines(&mut *tx, user_id, order.id, &order.lines, |product_id| {
+            products.get(&product_id).map_or(0, |product| product.quantity)
+        })
+        .await?;
+
+        // Count the coupons towards their usage limits
+        for discount in &order.discounts {
+            tx.record_redemption(&discount.code, user_id, order.id, now).await?;
+        }
+
+        tx.delete_lines(user_id).await?;
+        tx.clear_coupons(user_id).await?;
+
+        record_event(
+            &mut *tx,
+            CartEvent::CartCheckedOut {
+                user_id,
+                order_id: order.id,
+                currency: order.currency,
+                grand_total: order.grand_total,
+            },
+        )
+        .await?;
+
+        tx.touch_cart(user_id, now).await?;
+
+        tx.commit().await?;
+
+        Ok(order)
+    }
+
+    // Retrieve one of a user's orders with its lines
+    pub async fn get_order(&self, user_id: Uuid, order_id: Uuid) -> Result<Order, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+        let order = tx.order(user_id, order_id).await?;
+        tx.commit().await?;
+
+        order.ok_or(CartServiceError::OrderNotFound)
+    }
+
+    // Move an order to a new status, rejecting transitions the state machine does not allow
+    pub async fn transition_order(
+        &self,
+        user_id: Uuid,
+        order_id: Uuid,
+        next: OrderStatus,
+    ) -> Result<(), CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+
+        let current = tx
+            .lock_order_status(user_id, order_id)
+            .await?
+            .ok_or(CartServiceError::OrderNotFound)?;
+
+        if !current.can_transition_to(next) {
+            return Err(CartServiceError::IllegalTransition {
+                from: current,
+                to: next,
+            });
+        }
+
+        // Stock is decremented over HTTP while the order is locked, so it has to be put back
+        // if the status change is not committed
+        let decremented = match next {
+            OrderStatus::Paid => self.confirm_reservations(&mut *tx, order_id).await?,
+            OrderStatus::Cancelled => {
+                self.release_reservations(&mut *tx, order_id).await?;
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+
+        let result = match tx.set_order_status(order_id, next, Utc::now()).await {
+            Ok(()) => tx.commit().await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            self.reverse_decrements(&decremented).await;
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    use crate::repository::CartRepository;
+    use crate::services::catalog::{CatalogError, InMemoryProductCatalog, Product, ProductCatalog};
+    use crate::services::promotions::{Coupon, CouponRejection, DiscountKind};
+    use crate::services::testing::{cart_service, product, TestServices};
+    use crate::services::PriceCartQuery;
+
+    fn checkout_request() -> CheckoutRequest {
+        CheckoutRequest {
+            shipping_address: ShippingAddress {
+                country: "US".to_string(),
+                region: None,
+                postal_code: None,
+            },
+            shipping_method: "standard".to_string(),
+        }
+    }
+
+    #[test]
+    fn orders_move_forward_or_to_a_terminal_branch() {
+        use OrderStatus::*;
+
+        assert!(PendingPayment.can_transition_to(Paid));
+        assert!(PendingPayment.can_transition_to(Cancelled));
+        assert!(Paid.can_transition_to(Fulfilled));
+        assert!(Fulfilled.can_transition_to(Completed));
+        assert!(Completed.can_transition_to(Refunded));
+
+        assert!(!PendingPayment.can_transition_to(Fulfilled));
+        assert!(!PendingPayment.can_transition_to(Refunded));
+        assert!(!Fulfilled.can_transition_to(Cancelled));
+        assert!(!Paid.can_transition_to(PendingPayment));
+        for next in [PendingPayment, Paid, Fulfilled, Completed, Cancelled, Refunded] {
+            assert!(!Cancelled.can_transition_to(next));
+            assert!(!Refunded.can_transition_to(next));
+        }
+    }
+
+    #[test]
+    fn statuses_round_trip_through_strings() {
+        for status in [
+            OrderStatus::PendingPayment,
+            OrderStatus::Paid,
+            OrderStatus::Fulfilled,
+            OrderStatus::Completed,
+            OrderStatus::Cancelled,
+            OrderStatus::Refunded,
+        ] {
+            assert_eq!(status.to_string().parse(), Ok(status));
+        }
+        assert!("shipped".parse::<OrderStatus>().is_err());
+    }
+
+    #[tokio::test]
+    async fn added_items_are_priced_and_checked_out() {
+        let product = product(Decimal::new(1250, 2), 10);
+        let TestServices { service, repository, catalog, .. } = cart_service([product.clone()]);
+        repository
+            .upsert_coupon(Coupon {
+                code: "TENOFF".to_string(),
+                kind: DiscountKind::PercentageOff { percent: Decimal::new(10, 0) },
+                min_spend: None,
+                category: None,
+                max_uses: Some(1),
+                max_uses_per_user: None,
+                valid_from: None,
+                valid_until: None,
+            })
+            .await;
+        let user_id = Uuid::new_v4();
+
+        service
+            .add_item_to_cart(user_id, product.id, 2, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        service.apply_coupon(user_id, "TENOFF").await.unwrap();
+
+        let priced = service.get_priced_cart(user_id, &PriceCartQuery::default()).await.unwrap();
+        assert_eq!(priced.subtotal, Decimal::new(2500, 2));
+        assert_eq!(priced.discount_total, Decimal::new(250, 2));
+        assert_eq!(priced.tax_total, Decimal::new(225, 2));
+
+        let order = service.checkout(user_id, &checkout_request()).await.unwrap();
+        assert_eq!(order.status, OrderStatus::PendingPayment);
+        assert_eq!(order.lines.len(), 1);
+        assert_eq!(order.shipping_total, Decimal::new(500, 2));
+        assert_eq!(order.grand_total, Decimal::new(2975, 2));
+        assert!(service.get_cart(user_id).await.unwrap().items.is_empty());
+
+        // Stock leaves the catalog only once the order is paid
+        service.transition_order(user_id, order.id, OrderStatus::Paid).await.unwrap();
+        let stocked = catalog.get_product(product.id).await.unwrap().unwrap();
+        assert_eq!(stocked.quantity, 8);
+        assert_eq!(
+            service.get_order(user_id, order.id).await.unwrap().status,
+            OrderStatus::Paid
+        );
+
+        // The coupon's single use went to this order
+        service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.apply_coupon(user_id, "TENOFF").await,
+            Err(CartServiceError::CouponRejected(CouponRejection::UsageLimitReached))
+        ));
+    }
+
+    #[tokio::test]
+    async fn products_taken_off_sale_are_not_checked_out() {
+        let product = product(Decimal::new(1250, 2), 10);
+        let TestServices { service, catalog, .. } = cart_service([product.clone()]);
+        let user_id = Uuid::new_v4();
+        service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+
+        catalog.products.write().unwrap().get_mut(&product.id).unwrap().is_active = false;
+
+        assert!(matches!(
+            service.checkout(user_id, &checkout_request()).await,
+            Err(CartServiceError::ProductInactive(id)) if id == product.id
+        ));
+        assert_eq!(service.get_cart(user_id).await.unwrap().items.len(), 1);
+    }
+
+    // A catalog that changes the cart while checkout is looking products up
+    struct EditingCatalog {
+        inner: Arc<InMemoryProductCatalog>,
+        repository: Arc<dyn CartRepository>,
+        user_id: Uuid,
+    }
+
+    #[async_trait]
+    impl ProductCatalog for EditingCatalog {
+        async fn get_product(&self, product_id: Uuid) -> Result<Option<Product>, CatalogError> {
+            let mut tx = self.repository.begin().await.unwrap();
+            tx.touch_cart(self.user_id, Utc::now()).await.unwrap();
+            tx.commit().await.unwrap();
+
+            self.inner.get_product(product_id).await
+        }
+    }
+
+    #[tokio::test]
+    async fn carts_changed_during_checkout_lookups_are_not_checked_out() {
+        let product = product(Decimal::new(1250, 2), 10);
+        let TestServices { mut service, catalog, .. } = cart_service([product.clone()]);
+        let user_id = Uuid::new_v4();
+        service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        service.catalog = Arc::new(EditingCatalog {
+            inner: catalog,
+            repository: service.repository.clone(),
+            user_id,
+        });
+
+        assert!(matches!(
+            service.checkout(user_id, &checkout_request()).await,
+            Err(CartServiceError::VersionMismatch { current: 2 })
+        ));
+        assert_eq!(service.get_cart(user_id).await.unwrap().items.len(), 1);
+    }
+}
@@ -0,0 +1,332 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use super::currency::Currency;
+use super::{CartService, CartServiceError};
+use crate::repository::CartTransaction;
+
+/// Domain events other teams can subscribe to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CartEvent {
+    ItemAdded {
+        user_id: Uuid,
+        product_id: Uuid,
+        quantity: u32,
+    },
+    ItemRemoved {
+        user_id: Uuid,
+        item_id: Uuid,
+        product_id: Uuid,
+    },
+    ItemQuantityChanged {
+        user_id: Uuid,
+        item_id: Uuid,
+        product_id: Uuid,
+        quantity: u32, // The line's new quantity
+    },
+    CartCleared {
+        user_id: Uuid,
+    },
+    CartCheckedOut {
+        user_id: Uuid,
+        order_id: Uuid,
+        currency: Currency,
+        grand_total: Decimal,
+    },
+}
+
+/// An event as stored in the outbox; `id` stays the same across redeliveries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    pub id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub event: CartEvent,
+}
+
+#[derive(Debug, Error)]
+pub enum PublishError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+}
+
+/// Delivers outbox messages to subscribers. Delivery is at-least-once,
+/// so consumers should deduplicate on the message id.
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), PublishError>;
+}
+
+/// Publisher that keeps messages in memory, used in tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct InMemoryEventPublisher {
+    pub messages: std::sync::Mutex<Vec<OutboxMessage>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl EventPublisher for InMemoryEventPublisher {
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), PublishError> {
+        self.messages.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
+
+/// Publisher that appends one JSON object per line to a file, for local runs
+pub struct NdjsonFilePublisher {
+    path: PathBuf,
+}
+
+impl NdjsonFilePublisher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for NdjsonFilePublisher {
+    async fn publish(&self, message: &OutboxMessage) -> Result<(), PublishError> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+
+        Ok(())
+    }
+}
+
+/// Write an event to the outbox; call this inside the transaction that makes the change
+pub(crate) async fn record_event(tx: &mut dyn CartTransaction, event: CartEvent) -> Result<Uuid, sqlx::Error> {
+    let message = OutboxMessage {
+        id: Uuid::new_v4(),
+        occurred_at: Utc::now(),
+        event,
+    };
+    tx.record_event(&message).await?;
+
+    Ok(message.id)
+}
+
+impl CartService {
+    // Publish a batch of unpublished events in the order they were recorded
+    pub async fn relay_outbox(&self, batch_size: u32) -> Result<usize, CartServiceError> {
+        let publisher = match &self.publisher {
+            Some(publisher) => publisher,
+            None => return Ok(0),
+        };
+
+        let mut tx = self.repository.begin().await?;
+
+        // Locked rows are skipped, so several relays can run side by side without publishing the same batch
+        let messages = tx.unpublished_events(batch_size).await?;
+
+        let mut published = 0;
+        for message in messages {
+            // Stop at the first failure so events keep their order; the rest are retried later
+            if let Err(err) = publisher.publish(&message).await {
+                error!("Failed to publish cart event {}: {}", message.id, err);
+                break;
+            }
+
+            tx.mark_event_published(message.id, Utc::now()).await?;
+
+            published += 1;
+        }
+
+        tx.commit().await?;
+
+        Ok(published)
+    }
+
+    // Publish everything still waiting in the outbox, stopping early if the publisher fails
+    pub async fn flush_outbox(&self) -> Result<usize, CartServiceError> {
+        let mut published = 0;
+        loop {
+            match self.relay_outbox(100).await? {
+                0 => return Ok(published),
+                count => published += count,
+            }
+        }
+    }
+}
+
+/// Periodically publish pending outbox events until shutdown begins
+pub fn spawn_outbox_relay(
+    cart_service: Arc<CartService>,
+    interval: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.changed() => break,
+            }
+            match cart_service.relay_outbox(100).await {
+                Ok(0) => {}
+                Ok(count) => info!("Published {} cart events", count),
+                Err(err) => error!("Failed to relay cart events: {:?}", err),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::services::line_options::LineOptions;
+    use crate::services::testing::{cart_service, product};
+
+    // Fails the first `failures` publishes, then delivers to `inner`
+    struct FlakyPublisher {
+        failures: Mutex<u32>,
+        inner: InMemoryEventPublisher,
+    }
+
+    #[async_trait]
+    impl EventPublisher for FlakyPublisher {
+        async fn publish(&self, message: &OutboxMessage) -> Result<(), PublishError> {
+            {
+                let mut failures = self.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return Err(std::io::Error::other("subscriber unavailable").into());
+                }
+            }
+
+            self.inner.publish(message).await
+        }
+    }
+
+    async fn unpublished(service: &CartService) -> Vec<OutboxMessage> {
+        let mut tx = service.repository.begin().await.unwrap();
+        let messages = tx.unpublished_events(100).await.unwrap();
+        tx.commit().await.unwrap();
+        messages
+    }
+
+    #[tokio::test]
+    async fn events_are_recorded_with_the_change_that_caused_them() {
+        let product = product(Decimal::new(1000, 2), 2);
+        let service = cart_service([product.clone()]).service;
+        let user_id = Uuid::new_v4();
+
+        service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        // Rejected changes roll back with their events
+        service
+            .add_item_to_cart(user_id, product.id, 5, &LineOptions::default(), None)
+            .await
+            .unwrap_err();
+
+        let messages = unpublished(&service).await;
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(
+            messages[0].event,
+            CartEvent::ItemAdded { user_id: id, quantity: 1, .. } if id == user_id
+        ));
+    }
+
+    #[tokio::test]
+    async fn relayed_events_are_published_in_order_and_only_once() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let mut service = cart_service([product.clone()]).service;
+        let publisher = Arc::new(InMemoryEventPublisher::default());
+        service.publisher = Some(publisher.clone());
+        let user_id = Uuid::new_v4();
+
+        service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        service.clear_cart(user_id, None).await.unwrap();
+        let recorded: Vec<Uuid> = unpublished(&service).await.iter().map(|message| message.id).collect();
+
+        assert_eq!(service.relay_outbox(100).await.unwrap(), 2);
+        assert_eq!(service.relay_outbox(100).await.unwrap(), 0);
+
+        let messages = publisher.messages.lock().unwrap();
+        assert_eq!(messages.iter().map(|message| message.id).collect::<Vec<_>>(), recorded);
+        assert!(matches!(messages[1].event, CartEvent::CartCleared { .. }));
+    }
+
+    #[tokio::test]
+    async fn events_that_fail_to_publish_are_retried() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let mut service = cart_service([product.clone()]).service;
+        let publisher = Arc::new(FlakyPublisher {
+            failures: Mutex::new(1),
+            inner: InMemoryEventPublisher::default(),
+        });
+        service.publisher = Some(publisher.clone());
+        let user_id = Uuid::new_v4();
+        service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        let recorded = unpublished(&service).await;
+
+        assert_eq!(service.relay_outbox(100).await.unwrap(), 0);
+        assert_eq!(unpublished(&service).await.len(), 1);
+
+        assert_eq!(service.flush_outbox().await.unwrap(), 1);
+        assert!(unpublished(&service).await.is_empty());
+        let delivered = publisher.inner.messages.lock().unwrap();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].id, recorded[0].id);
+    }
+
+    #[tokio::test]
+    async fn events_stay_in_the_outbox_without_a_publisher() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let mut service = cart_service([product.clone()]).service;
+        service.publisher = None;
+        service
+            .add_item_to_cart(Uuid::new_v4(), product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+
+        assert_eq!(service.flush_outbox().await.unwrap(), 0);
+        assert_eq!(unpublished(&service).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_relay_stops_when_shutdown_begins() {
+        let service = Arc::new(cart_service([]).service);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let relay = spawn_outbox_relay(service, Duration::from_secs(3600), shutdown_rx);
+
+        shutdown_tx.send(true).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), relay)
+            .await
+            .expect("relay did not stop")
+            .unwrap();
+    }
+}
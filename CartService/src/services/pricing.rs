@@ -0,0 +1,283 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::catalog::Product;
+use super::currency::{Currency, CurrencyConversion};
+use super::promotions::{apply_coupons, AppliedDiscount, Coupon};
+use super::tax::{TaxBreakdown, TaxContext, TaxMode};
+use super::line_options::LineOptions;
+use super::{Cart, CartItem};
+
+/// A cart line with its price resolved from the catalog and converted to the cart currency
+#[derive(Debug, Clone, Serialize)]
+pub struct PricedCartLine {
+    pub id: Uuid,
+    pub product_id: Uuid,
+    pub name: String,
+    pub options: LineOptions,
+    pub quantity: u32,
+    pub unit_price: Decimal,
+    pub line_total: Decimal,
+}
+
+/// A cart with all totals computed server-side
+#[derive(Debug, Clone, Serialize)]
+pub struct PricedCart {
+    pub user_id: Uuid,
+    pub version: u64,
+    pub last_activity_at: Option<DateTime<Utc>>,
+    pub currency: Currency, // Currency of every amount below
+    pub lines: Vec<PricedCartLine>,
+    pub unavailable_items: Vec<CartItem>, // Lines whose product is no longer in the catalog
+    pub subtotal: Decimal,
+    pub discounts: Vec<AppliedDiscount>, // Breakdown of discount_total by coupon
+    pub discount_total: Decimal,
+    pub tax_total: Decimal, // Included in the line totals when the tax mode is inclusive
+    pub tax: Option<TaxBreakdown>, // Missing when there are no tax rules for the destination
+    pub shipping_total: Decimal,
+    pub grand_total: Decimal,
+}
+
+impl PricedCart {
+    /// Subtotal after line discounts, excluding shipping and tax
+    pub fn merchandise_total(&self) -> Decimal {
+        let line_discounts: Decimal = self
+            .discounts
+            .iter()
+            .filter(|d| !d.free_shipping)
+            .map(|d| d.amount)
+            .sum();
+
+        self.subtotal - line_discounts
+    }
+}
+
+/// What a cart is priced for, beyond its contents
+#[derive(Debug, Clone)]
+pub struct PricingContext {
+    pub conversion: CurrencyConversion,
+    pub shipping: Option<Decimal>, // Charge for the chosen shipping option, in the catalog currency
+    pub tax: Option<TaxContext>,   // Tax rules for the destination; none prices without tax
+}
+
+impl PricingContext {
+    pub fn new(conversion: CurrencyConversion) -> Self {
+        Self {
+            conversion,
+            shipping: None,
+            tax: None,
+        }
+    }
+}
+
+/// Computes cart totals using exact decimal arithmetic
+#[derive(Debug, Clone)]
+pub struct PricingEngine {
+    pub flat_shipping: Decimal, // Charged on every non-empty cart without a chosen shipping option, in the catalog currency
+}
+
+impl Default for PricingEngine {
+    fn default() -> Self {
+        Self {
+            flat_shipping: Decimal::ZERO,
+        }
+    }
+}
+
+impl PricingEngine {
+    /// Price a cart against the given products, keyed by product id, applying coupons in order.
+    /// Catalog prices and coupon amounts are converted first, so every total is in the target currency.
+    /// Without a chosen shipping option the flat rate is used as an estimate.
+    pub fn price(
+        &self,
+        cart: Cart,
+        products: &HashMap<Uuid, Product>,
+        coupons: &[Coupon],
+        context: &PricingContext,
+        now: DateTime<Utc>,
+    ) -> PricedCart {
+        let conversion = &context.conversion;
+        let currency = conversion.currency;
+        let coupons: Vec<Coupon> = coupons.iter().map(|coupon| coupon.converted(conversion)).collect();
+        let mut lines = Vec::new();
+        let mut unavailable_items = Vec::new();
+
+        for item in cart.items {
+            match products.get(&item.product_id) {
+                Some(product) => {
+                    let unit_price = conversion.convert(product.price);
+                    let line_total = unit_price * Decimal::from(item.quantity);
+                    lines.push(PricedCartLine {
+                        id: item.id,
+                        product_id: item.product_id,
+                        name: product.name.clone(),
+                        options: item.options,
+                        quantity: item.quantity,
+                        unit_price,
+                        line_total,
+                    });
+                }
+                None => unavailable_items.push(item),
+            }
+        }
+
+        let subtotal: Decimal = lines.iter().map(|line| line.line_total).sum();
+        let shipping_total = if lines.is_empty() {
+            Decimal::ZERO
+        } else {
+            conversion.convert(context.shipping.unwrap_or(self.flat_shipping))
+        };
+
+        let discounts = apply_coupons(&coupons, &lines, products, subtotal, shipping_total, currency, now);
+        let discount_total: Decimal = discounts.iter().map(|d| d.amount).sum();
+        let line_discounts: Decimal = discounts
+            .iter()
+            .filter(|d| !d.free_shipping)
+            .map(|d| d.amount)
+            .sum();
+        // Waived shipping is not taxed
+        let taxable_shipping = if discounts.iter().any(|d| d.free_shipping) {
+            Decimal::ZERO
+        } else {
+            shipping_total
+        };
+
+        let tax = context
+            .tax
+            .as_ref()
+            .map(|tax| tax.compute(&lines, products, line_discounts, taxable_shipping, currency));
+        let tax_total = tax.as_ref().map_or(Decimal::ZERO, |tax| tax.total);
+        let added_tax = match &tax {
+            Some(tax) if tax.mode == TaxMode::Inclusive => Decimal::ZERO,
+            _ => tax_total,
+        };
+        let grand_total = subtotal - discount_total + added_tax + shipping_total;
+
+        PricedCart {
+            user_id: cart.user_id,
+            version: cart.version,
+            last_activity_at: cart.last_activity_at,
+            currency,
+            lines,
+            unavailable_items,
+            subtotal,
+            discounts,
+            discount_total,
+            tax_total,
+            tax,
+            shipping_total,
+            grand_total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::promotions::DiscountKind;
+
+    fn product(price: Decimal) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "Widget".to_string(),
+            category: "tools".to_string(),
+            price,
+            quantity: 100,
+            is_active: true,
+            weight_grams: 0,
+            purchase_rules: Default::default(),
+        }
+    }
+
+    fn cart(items: &[(Uuid, u32)]) -> Cart {
+        Cart {
+            user_id: Uuid::new_v4(),
+            items: items
+                .iter()
+                .map(|&(product_id, quantity)| CartItem {
+                    id: Uuid::new_v4(),
+                    product_id,
+                    quantity,
+                    options: LineOptions::default(),
+                })
+                .collect(),
+            version: 1,
+            last_activity_at: None,
+            currency: Currency::Usd,
+        }
+    }
+
+    #[test]
+    fn totals_use_exact_decimal_arithmetic() {
+        let a = product(Decimal::new(10, 2));
+        let b = product(Decimal::new(20, 2));
+        let products = HashMap::from([(a.id, a.clone()), (b.id, b.clone())]);
+        let engine = PricingEngine { flat_shipping: Decimal::new(499, 2) };
+        let context = PricingContext::new(CurrencyConversion::identity(Currency::Usd));
+
+        let priced = engine.price(cart(&[(a.id, 3), (b.id, 1)]), &products, &[], &context, Utc::now());
+
+        assert_eq!(priced.subtotal, Decimal::new(50, 2));
+        assert_eq!(priced.shipping_total, Decimal::new(499, 2));
+        assert_eq!(priced.grand_total, Decimal::new(549, 2));
+    }
+
+    #[test]
+    fn missing_products_are_reported_and_not_priced() {
+        let a = product(Decimal::new(1000, 2));
+        let products = HashMap::from([(a.id, a.clone())]);
+        let context = PricingContext::new(CurrencyConversion::identity(Currency::Usd));
+
+        let priced = PricingEngine::default().price(
+            cart(&[(a.id, 1), (Uuid::new_v4(), 2)]),
+            &products,
+            &[],
+            &context,
+            Utc::now(),
+        );
+
+        assert_eq!(priced.lines.len(), 1);
+        assert_eq!(priced.unavailable_items.len(), 1);
+        assert_eq!(priced.subtotal, Decimal::new(1000, 2));
+    }
+
+    #[test]
+    fn empty_carts_are_not_charged_shipping() {
+        let engine = PricingEngine { flat_shipping: Decimal::new(499, 2) };
+        let context = PricingContext::new(CurrencyConversion::identity(Currency::Usd));
+
+        let priced = engine.price(cart(&[]), &HashMap::new(), &[], &context, Utc::now());
+
+        assert_eq!(priced.grand_total, Decimal::ZERO);
+    }
+
+    #[test]
+    fn prices_and_coupon_amounts_are_converted() {
+        let a = product(Decimal::new(1000, 2));
+        let products = HashMap::from([(a.id, a.clone())]);
+        let coupon = Coupon {
+            code: "FIVE".to_string(),
+            kind: DiscountKind::FixedAmountOff { amount: Decimal::new(500, 2) },
+            min_spend: None,
+            category: None,
+            max_uses: None,
+            max_uses_per_user: None,
+            valid_from: None,
+            valid_until: None,
+        };
+        let context = PricingContext::new(CurrencyConversion {
+            currency: Currency::Jpy,
+            rate: Decimal::new(15712, 2),
+        });
+
+        let priced = PricingEngine::default().price(cart(&[(a.id, 2)]), &products, &[coupon], &context, Utc::now());
+
+        assert_eq!(priced.currency, Currency::Jpy);
+        assert_eq!(priced.lines[0].unit_price, Decimal::new(1571, 0));
+        assert_eq!(priced.discount_total, Decimal::new(786, 0));
+        assert_eq!(priced.grand_total, Decimal::new(2356, 0));
+    }
+}
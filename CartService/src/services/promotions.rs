@@ -0,0 +1,437 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::repository::Redemptions;
+use super::catalog::Product;
+use super::currency::{Currency, CurrencyConversion};
+use super::pricing::{PricedCartLine, PricingContext};
+use super::{CartService, CartServiceError};
+
+/// What a coupon takes off the cart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DiscountKind {
+    /// Percentage off the eligible lines, e.g. 15 for 15%
+    PercentageOff { percent: Decimal },
+    /// Fixed amount off the eligible lines, capped at their total
+    FixedAmountOff { amount: Decimal },
+    /// For every `buy_quantity` units of an eligible line, `free_quantity` more units are free
+    BuyXGetY {
+        buy_quantity: u32,
+        free_quantity: u32,
+        product_id: Option<Uuid>,
+    },
+    /// Waives the shipping charge
+    FreeShipping,
+}
+
+/// A coupon definition as stored in the `coupons` table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coupon {
+    pub code: String,
+    pub kind: DiscountKind,
+    pub min_spend: Option<Decimal>,       // Minimum cart subtotal
+    pub category: Option<String>,         // Restrict the discount to products in this category
+    pub max_uses: Option<u32>,            // Overall redemption limit
+    pub max_uses_per_user: Option<u32>,   // Redemption limit per user
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+/// One discount applied to a priced cart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedDiscount {
+    pub code: String,
+    pub amount: Decimal,
+    pub free_shipping: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CouponRejection {
+    #[error("Coupon is not valid yet")]
+    NotYetValid,
+
+    #[error("Coupon has expired")]
+    Expired,
+
+    #[error("Cart subtotal is below the minimum spend of {0}")]
+    MinimumSpendNotMet(Decimal),
+
+    #[error("Coupon has reached its usage limit")]
+    UsageLimitReached,
+
+    #[error("Coupon has reached its usage limit for this user")]
+    UserUsageLimitReached,
+
+    #[error("Coupon does not apply to any item in the cart")]
+    NotApplicable,
+
+    #[error("Coupon is already applied to this cart")]
+    AlreadyApplied,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyCouponRequest {
+    pub code: String,
+}
+
+impl Coupon {
+    /// Check the validity window and minimum spend against the current cart
+    pub fn check_eligibility(
+        &self,
+        subtotal: Decimal,
+        now: DateTime<Utc>,
+    ) -> Result<(), CouponRejection> {
+        if matches!(self.valid_from, Some(from) if now < from) {
+            return Err(CouponRejection::NotYetValid);
+        }
+        if matches!(self.valid_until, Some(until) if now >= until) {
+            return Err(CouponRejection::Expired);
+        }
+        if let Some(min_spend) = self.min_spend {
+            if subtotal < min_spend {
+                return Err(CouponRejection::MinimumSpendNotMet(min_spend));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the overall and per-user usage limits against the coupon's redemptions so far
+    pub fn check_redemptions(&self, redemptions: &Redemptions) -> Result<(), CouponRejection> {
+        if matches!(self.max_uses, Some(max) if redemptions.total >= u64::from(max)) {
+            return Err(CouponRejection::UsageLimitReached);
+        }
+        if matches!(self.max_uses_per_user, Some(max) if redemptions.by_user >= u64::from(max)) {
+            return Err(CouponRejection::UserUsageLimitReached);
+        }
+
+        Ok(())
+    }
+
+    /// The coupon with its monetary amounts converted out of the catalog currency
+    pub fn converted(&self, conversion: &CurrencyConversion) -> Coupon {
+        let kind = match &self.kind {
+            DiscountKind::FixedAmountOff { amount } => DiscountKind::FixedAmountOff {
+                amount: conversion.convert(*amount),
+            },
+            other => other.clone(),
+        };
+
+        Coupon {
+            kind,
+            min_spend: self.min_spend.map(|min_spend| conversion.convert(min_spend)),
+            ..self.clone()
+        }
+    }
+
+    fn is_eligible_line(&self, line: &PricedCartLine, products: &HashMap<Uuid, Product>) -> bool {
+        match &self.category {
+            Some(category) => products
+                .get(&line.product_id)
+                .is_some_and(|product| &product.category == category),
+            None => true,
+        }
+    }
+
+    /// Compute the discount this coupon gives on the given lines, before any stacking cap
+    pub fn discount(
+        &self,
+        lines: &[PricedCartLine],
+        products: &HashMap<Uuid, Product>,
+        shipping_total: Decimal,
+        currency: Currency,
+    ) -> Result<AppliedDiscount, CouponRejection> {
+        let eligible: Vec<&PricedCartLine> = lines
+            .iter()
+            .filter(|line| self.is_eligible_line(line, products))
+            .collect();
+
+        if eligible.is_empty() {
+            return Err(CouponRejection::NotApplicable);
+        }
+
+        let eligible_total: Decimal = eligible.iter().map(|line| line.line_total).sum();
+
+        let (amount, free_shipping) = match &self.kind {
+            DiscountKind::PercentageOff { percent } => {
+                (currency.round(eligible_total * percent / Decimal::ONE_HUNDRED), false)
+            }
+            DiscountKind::FixedAmountOff { amount } => ((*amount).min(eligible_total), false),
+            DiscountKind::BuyXGetY {
+                buy_quantity,
+                free_quantity,
+                product_id,
+            } => {
+                let group_size = buy_quantity + free_quantity;
+                let amount: Decimal = eligible
+                    .iter()
+                    .filter(|line| product_id.is_none_or(|id| id == line.product_id))
+                    .filter(|_| group_size > 0)
+                    .map(|line| {
+                        let free_units = (line.quantity / group_size) * free_quantity;
+                        line.unit_price * Decimal::from(free_units)
+                    })
+                    .sum();
+
+                if amount.is_zero() {
+                    return Err(CouponRejection::NotApplicable);
+                }
+                (amount, false)
+            }
+            DiscountKind::FreeShipping => (shipping_total, true),
+        };
+
+        Ok(AppliedDiscount {
+            code: self.code.clone(),
+            amount,
+            free_shipping,
+        })
+    }
+}
+
+/// Apply coupons in order, skipping the ones the cart no longer qualifies for.
+/// Line discounts are capped so that the merchandise total never goes below zero.
+pub fn apply_coupons(
+    coupons: &[Coupon],
+    lines: &[PricedCartLine],
+    products: &HashMap<Uuid, Product>,
+    subtotal: Decimal,
+    shipping_total: Decimal,
+    currency: Currency,
+    now: DateTime<Utc>,
+) -> Vec<AppliedDiscount> {
+    let mut remaining = subtotal;
+    let mut shipping_waived = false;
+    let mut applied = Vec::new();
+
+    for coupon in coupons {
+        if coupon.check_eligibility(subtotal, now).is_err() {
+            continue;
+        }
+
+        if let Ok(mut discount) = coupon.discount(lines, products, shipping_total, currency) {
+            if discount.free_shipping {
+                // Shipping can only be waived once
+                if shipping_waived {
+                    continue;
+                }
+                shipping_waived = true;
+            } else {
+                discount.amount = discount.amount.min(remaining);
+                remaining -= discount.amount;
+            }
+            applied.push(discount);
+        }
+    }
+
+    applied
+}
+
+impl CartService {
+    // Load the coupons applied to a cart, in the order they were applied
+    pub async fn get_applied_coupons(&self, user_id: Uuid) -> Result<Vec<Coupon>, CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+        let coupons = tx.applied_coupons(user_id).await?;
+        tx.commit().await?;
+
+        Ok(coupons)
+    }
+
+    // Apply a coupon code to a user's cart after checking its rules against the current cart
+    pub async fn apply_coupon(&self, user_id: Uuid, code: &str) -> Result<(), CartServiceError> {
+        // Price the cart without coupons, in the catalog currency coupons are defined in,
+        // to check that this one would apply
+        let now = Utc::now();
+        let (cart, products) = self.load_cart_with_products(user_id).await?;
+        let context = PricingContext::new(CurrencyConversion::identity(self.catalog_currency));
+        let priced = self.pricing.price(cart, &products, &[], &context, now);
+
+        let mut tx = self.repository.begin().await?;
+
+        let coupon = tx.coupon(code).await?.ok_or(CartServiceError::CouponNotFound)?;
+
+        let applied = tx.applied_coupons(user_id).await?;
+        if applied.iter().any(|c| c.code == coupon.code) {
+            return Err(CouponRejection::AlreadyApplied.into());
+        }
+
+        let redemptions = tx.coupon_redemptions(&coupon.code, user_id).await?;
+        coupon.check_redemptions(&redemptions)?;
+
+        coupon.check_eligibility(priced.subtotal, now)?;
+        coupon.discount(&priced.lines, &products, priced.shipping_total, priced.currency)?;
+
+        tx.apply_coupon(user_id, &coupon.code, Utc::now()).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Remove a coupon code from a user's cart
+    pub async fn remove_coupon(&self, user_id: Uuid, code: &str) -> Result<(), CartServiceError> {
+        let mut tx = self.repository.begin().await?;
+
+        if !tx.remove_coupon(user_id, code).await? {
+            return Err(CartServiceError::CouponNotFound);
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn coupon(code: &str, kind: DiscountKind) -> Coupon {
+        Coupon {
+            code: code.to_string(),
+            kind,
+            min_spend: None,
+            category: None,
+            max_uses: None,
+            max_uses_per_user: None,
+            valid_from: None,
+            valid_until: None,
+        }
+    }
+
+    fn product(category: &str, price: Decimal) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "Widget".to_string(),
+            category: category.to_string(),
+            price,
+            quantity: 100,
+            is_active: true,
+            weight_grams: 0,
+            purchase_rules: Default::default(),
+        }
+    }
+
+    fn line(product: &Product, quantity: u32) -> PricedCartLine {
+        PricedCartLine {
+            id: Uuid::new_v4(),
+            product_id: product.id,
+            name: product.name.clone(),
+            options: Default::default(),
+            quantity,
+            unit_price: product.price,
+            line_total: product.price * Decimal::from(quantity),
+        }
+    }
+
+    fn apply(
+        coupons: &[Coupon],
+        lines: &[PricedCartLine],
+        products: &[&Product],
+        shipping_total: Decimal,
+    ) -> Vec<AppliedDiscount> {
+        let products = products.iter().map(|p| (p.id, (*p).clone())).collect();
+        let subtotal = lines.iter().map(|line| line.line_total).sum();
+        apply_coupons(coupons, lines, &products, subtotal, shipping_total, Currency::Usd, Utc::now())
+    }
+
+    #[test]
+    fn percentage_off_is_limited_to_the_coupon_category() {
+        let book = product("books", Decimal::new(2000, 2));
+        let toy = product("toys", Decimal::new(1000, 2));
+        let lines = [line(&book, 1), line(&toy, 1)];
+        let books_only = Coupon {
+            category: Some("books".to_string()),
+            ..coupon("BOOKS15", DiscountKind::PercentageOff { percent: Decimal::new(15, 0) })
+        };
+
+        let applied = apply(&[books_only], &lines, &[&book, &toy], Decimal::ZERO);
+
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].amount, Decimal::new(300, 2));
+    }
+
+    #[test]
+    fn line_discounts_never_exceed_the_subtotal() {
+        let toy = product("toys", Decimal::new(1000, 2));
+        let lines = [line(&toy, 2)];
+        let coupons = [
+            coupon("TEN", DiscountKind::FixedAmountOff { amount: Decimal::new(1500, 2) }),
+            coupon("TWENTY", DiscountKind::FixedAmountOff { amount: Decimal::new(2000, 2) }),
+        ];
+
+        let applied = apply(&coupons, &lines, &[&toy], Decimal::ZERO);
+
+        let amounts: Vec<Decimal> = applied.iter().map(|d| d.amount).collect();
+        assert_eq!(amounts, vec![Decimal::new(1500, 2), Decimal::new(500, 2)]);
+    }
+
+    #[test]
+    fn buy_x_get_y_discounts_whole_groups() {
+        let toy = product("toys", Decimal::new(400, 2));
+        let lines = [line(&toy, 7)];
+        let three_for_two = coupon(
+            "3FOR2",
+            DiscountKind::BuyXGetY {
+                buy_quantity: 2,
+                free_quantity: 1,
+                product_id: None,
+            },
+        );
+
+        let applied = apply(&[three_for_two], &lines, &[&toy], Decimal::ZERO);
+
+        assert_eq!(applied[0].amount, Decimal::new(800, 2));
+    }
+
+    #[test]
+    fn shipping_is_waived_once_and_ineligible_coupons_are_skipped() {
+        let toy = product("toys", Decimal::new(1000, 2));
+        let lines = [line(&toy, 1)];
+        let expired = Coupon {
+            valid_until: Some(Utc::now() - Duration::days(1)),
+            ..coupon("OLD", DiscountKind::PercentageOff { percent: Decimal::new(50, 0) })
+        };
+        let big_spender = Coupon {
+            min_spend: Some(Decimal::new(5000, 2)),
+            ..coupon("BIG", DiscountKind::FixedAmountOff { amount: Decimal::new(500, 2) })
+        };
+        let coupons = [
+            expired,
+            big_spender,
+            coupon("SHIP1", DiscountKind::FreeShipping),
+            coupon("SHIP2", DiscountKind::FreeShipping),
+        ];
+
+        let applied = apply(&coupons, &lines, &[&toy], Decimal::new(499, 2));
+
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].code, "SHIP1");
+        assert!(applied[0].free_shipping);
+        assert_eq!(applied[0].amount, Decimal::new(499, 2));
+    }
+
+    #[test]
+    fn redemption_limits_are_checked_overall_and_per_user() {
+        let limited = Coupon {
+            max_uses: Some(10),
+            max_uses_per_user: Some(1),
+            ..coupon("ONCE", DiscountKind::FreeShipping)
+        };
+
+        assert_eq!(limited.check_redemptions(&Redemptions { total: 9, by_user: 0 }), Ok(()));
+        assert_eq!(
+            limited.check_redemptions(&Redemptions { total: 10, by_user: 0 }),
+            Err(CouponRejection::UsageLimitReached)
+        );
+        assert_eq!(
+            limited.check_redemptions(&Redemptions { total: 3, by_user: 1 }),
+            Err(CouponRejection::UserUsageLimitReached)
+        );
+    }
+}
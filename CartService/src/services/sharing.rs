@@ -0,0 +1,345 @@
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use super::limits::check_added_quantity;
+use super::line_options::LineOptions;
+use super::outbox::{record_event, CartEvent};
+use super::currency::Currency;
+use super::pricing::{PricedCartLine, PricingContext};
+use super::{Cart, CartItem, CartService, CartServiceError};
+use crate::repository::SharedCartRecord;
+use crate::utils::cart_token;
+
+/// Longest a share link can stay valid
+pub const MAX_SHARE_LIFETIME_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// One line of a shared cart snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedCartItem {
+    pub product_id: Uuid,
+    pub quantity: u32,
+    #[serde(default)]
+    pub options: LineOptions,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ShareCartRequest {
+    pub expires_in_secs: Option<u64>, // The link never expires when omitted; capped at a year
+}
+
+/// A link to a frozen copy of a cart
+#[derive(Debug, Serialize)]
+pub struct SharedCartLink {
+    pub token: String,
+    pub path: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A shared snapshot as shown to whoever holds the link, priced at current catalog prices
+#[derive(Debug, Serialize)]
+pub struct SharedCartView {
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub cart: SharedCartContents,
+}
+
+/// The priced lines of a snapshot; unlike a `PricedCart` it says nothing about whose cart it was
+#[derive(Debug, Serialize)]
+pub struct SharedCartContents {
+    pub currency: Currency,
+    pub lines: Vec<PricedCartLine>, // Ids are stable for the snapshot but are not cart line ids
+    pub unavailable_items: Vec<CartItem>, // Lines whose product is no longer in the catalog
+    pub subtotal: Decimal,
+}
+
+impl CartService {
+    // Freeze the current contents of a cart into a snapshot and issue a link to it
+    pub async fn share_cart(
+        &self,
+        user_id: Uuid,
+        request: &ShareCartRequest,
+    ) -> Result<SharedCartLink, CartServiceError> {
+        let cart = self.get_cart(user_id).await?;
+        if cart.items.is_empty() {
+            return Err(CartServiceError::EmptyCart);
+        }
+
+        let items: Vec<SharedCartItem> = cart
+            .items
+            .iter()
+            .map(|item| SharedCartItem {
+                product_id: item.product_id,
+                quantity: item.quantity,
+                options: item.options.clone(),
+            })
+            .collect();
+
+        let snapshot_id = Uuid::new_v4();
+        let now = Utc::now();
+        let expires_at = request
+            .expires_in_secs
+            .map(|secs| now + Duration::seconds(secs.min(MAX_SHARE_LIFETIME_SECS) as i64));
+
+        let mut tx = self.repository.begin().await?;
+        tx.insert_shared_cart(&SharedCartRecord {
+            id: snapshot_id,
+            user_id,
+            items,
+            currency: cart.currency,
+            created_at: now,
+            expires_at,
+        })
+        .await?;
+        tx.commit().await?;
+
+        let token = cart_token::issue_share_token(snapshot_id, &self.cart_token_secret);
+
+        Ok(SharedCartLink {
+            path: format!("/shared-carts/{}", token),
+            token,
+            expires_at,
+        })
+    }
+
+    // Look up a snapshot by its link token, rejecting forged and expired links
+    async fn find_shared_cart(&self, token: &str) -> Result<SharedCartRecord, CartServiceError> {
+        let snapshot_id = cart_token::verify_share_token(token, &self.cart_token_secret)
+            .ok_or(CartServiceError::SharedCartNotFound)?;
+
+        let mut tx = self.repository.begin().await?;
+        let snapshot = tx
+            .shared_cart(snapshot_id)
+            .await?
+            .ok_or(CartServiceError::SharedCartNotFound)?;
+        tx.commit().await?;
+
+        if matches!(snapshot.expires_at, Some(expires_at) if expires_at <= Utc::now()) {
+            return Err(CartServiceError::SharedCartExpired);
+        }
+
+        Ok(snapshot)
+    }
+
+    // Show a shared snapshot; no coupons or tax, since those depend on whose cart it ends up in
+    pub async fn get_shared_cart(&self, token: &str) -> Result<SharedCartView, CartServiceError> {
+        let snapshot = self.find_shared_cart(token).await?;
+
+        let product_ids: Vec<Uuid> = snapshot.items.iter().map(|item| item.product_id).collect();
+        let products = self.catalog.get_products(&product_ids).await?;
+
+        // Line ids are derived from the snapshot so they stay the same between views
+        let cart = Cart {
+            user_id: Uuid::nil(),
+            items: snapshot
+                .items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| CartItem {
+                    id: Uuid::new_v5(&snapshot.id, &(index as u64).to_be_bytes()),
+                    product_id: item.product_id,
+                    quantity: item.quantity,
+                    options: item.options.clone(),
+                })
+                .collect(),
+            version: 0,
+            last_activity_at: None,
+            currency: snapshot.currency,
+        };
+        let context = PricingContext::new(self.conversion_to(snapshot.currency).await?);
+
+        let priced = self.pricing.price(cart, &products, &[], &context, Utc::now());
+
+        Ok(SharedCartView {
+            created_at: snapshot.created_at,
+            expires_at: snapshot.expires_at,
+            cart: SharedCartContents {
+                currency: priced.currency,
+                lines: priced.lines,
+                unavailable_items: priced.unavailable_items,
+                subtotal: priced.subtotal,
+            },
+        })
+    }
+
+    // Add the lines of a shared snapshot to a user's cart in one transaction.
+    // Returns the new cart version.
+    pub async fn import_shared_cart(
+        &self,
+        user_id: Uuid,
+        token: &str,
+        expected_version: Option<u64>,
+    ) -> Result<u64, CartServiceError> {
+        let snapshot = self.find_shared_cart(token).await?;
+
+        let mut tx = self.repository.begin().await?;
+        self.check_version(&mut *tx, user_id, expected_version).await?;
+
+        // Snapshot lines matching an existing line are added to it rather than creating a new one
+        let mut line_keys: HashSet<(Uuid, String)> = tx
+            .cart_lines(user_id, false)
+            .await?
+            .into_iter()
+            .map(|line| (line.product_id, line.options.key()))
+            .collect();
+        line_keys.extend(snapshot.items.iter().map(|item| (item.product_id, item.options.key())));
+        self.limits.check_line_count(line_keys.len())?;
+
+        // Stock and purchase rules are checked per product, counting every snapshot line of it
+        let mut requested: HashMap<Uuid, u32> = HashMap::new();
+        for item in &snapshot.items {
+            check_added_quantity(item.product_id, item.quantity)?;
+            let quantity = requested.entry(item.product_id).or_default();
+            *quantity = quantity.saturating_add(item.quantity);
+        }
+        for (&product_id, &quantity) in &requested {
+            let in_cart = self.quantity_in_cart(&mut *tx, user_id, product_id, None).await?;
+            self.validate_product(&mut *tx, user_id, product_id, in_cart.saturating_add(quantity))
+                .await?;
+        }
+
+        let now = Utc::now();
+        for item in &snapshot.items {
+            tx.add_to_line(user_id, item.product_id, &item.options, item.quantity, now)
+                .await?;
+
+            record_event(
+                &mut *tx,
+                CartEvent::ItemAdded {
+                    user_id,
+                    product_id: item.product_id,
+                    quantity: item.quantity,
+                },
+            )
+            .await?;
+        }
+
+        let version = tx.touch_cart(user_id, now).await?;
+        tx.commit().await?;
+
+        Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::limits::LimitViolation;
+    use crate::services::testing::{cart_service, product};
+
+    // Share a cart holding two of the product; returns the share token
+    async fn share(service: &CartService, product_id: Uuid, request: &ShareCartRequest) -> String {
+        let owner = Uuid::new_v4();
+        service
+            .add_item_to_cart(owner, product_id, 2, &LineOptions::default(), None)
+            .await
+            .unwrap();
+        service.share_cart(owner, request).await.unwrap().token
+    }
+
+    #[tokio::test]
+    async fn shared_carts_do_not_reveal_their_owner_and_keep_their_line_ids() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let token = share(&service, product.id, &ShareCartRequest::default()).await;
+
+        let first = service.get_shared_cart(&token).await.unwrap();
+        let second = service.get_shared_cart(&token).await.unwrap();
+
+        assert_eq!(first.cart.lines.len(), 1);
+        assert_eq!(first.cart.lines[0].quantity, 2);
+        assert_eq!(first.cart.subtotal, Decimal::new(2000, 2));
+        assert_eq!(first.cart.lines[0].id, second.cart.lines[0].id);
+        let json = serde_json::to_value(&first).unwrap();
+        assert!(json["cart"].get("user_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn forged_share_tokens_are_not_found() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let token = share(&service, product.id, &ShareCartRequest::default()).await;
+        let snapshot_id = cart_token::verify_share_token(&token, &service.cart_token_secret).unwrap();
+
+        let forged = [
+            cart_token::issue_share_token(snapshot_id, "some-other-secret-that-is-long-enough"),
+            cart_token::issue_share_token(Uuid::new_v4(), &service.cart_token_secret),
+            // A guest cart token is signed with the same secret but for a different purpose
+            cart_token::issue_cart_token(snapshot_id, &service.cart_token_secret),
+            format!("{}x", token),
+        ];
+        for token in forged {
+            assert!(matches!(
+                service.get_shared_cart(&token).await,
+                Err(CartServiceError::SharedCartNotFound)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn expired_share_links_are_gone() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let token = share(&service, product.id, &ShareCartRequest { expires_in_secs: Some(0) }).await;
+
+        assert!(matches!(
+            service.get_shared_cart(&token).await,
+            Err(CartServiceError::SharedCartExpired)
+        ));
+        assert!(matches!(
+            service.import_shared_cart(Uuid::new_v4(), &token, None).await,
+            Err(CartServiceError::SharedCartExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn imported_lines_are_added_to_the_cart() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let token = share(&service, product.id, &ShareCartRequest::default()).await;
+        let user_id = Uuid::new_v4();
+        service
+            .add_item_to_cart(user_id, product.id, 1, &LineOptions::default(), None)
+            .await
+            .unwrap();
+
+        service.import_shared_cart(user_id, &token, None).await.unwrap();
+
+        let cart = service.get_cart(user_id).await.unwrap();
+        assert_eq!(cart.items.len(), 1);
+        assert_eq!(cart.items[0].quantity, 3);
+    }
+
+    #[tokio::test]
+    async fn snapshots_with_empty_lines_are_not_imported() {
+        let product = product(Decimal::new(1000, 2), 5);
+        let service = cart_service([product.clone()]).service;
+        let snapshot_id = Uuid::new_v4();
+        let mut tx = service.repository.begin().await.unwrap();
+        tx.insert_shared_cart(&SharedCartRecord {
+            id: snapshot_id,
+            user_id: Uuid::new_v4(),
+            items: vec![SharedCartItem {
+                product_id: product.id,
+                quantity: 0,
+                options: LineOptions::default(),
+            }],
+            currency: Currency::Usd,
+            created_at: Utc::now(),
+            expires_at: None,
+        })
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        let token = cart_token::issue_share_token(snapshot_id, &service.cart_token_secret);
+        let user_id = Uuid::new_v4();
+
+        assert!(matches!(
+            service.import_shared_cart(user_id, &token, None).await,
+            Err(CartServiceError::LimitExceeded(LimitViolation::ZeroQuantity { .. }))
+        ));
+        assert!(service.get_cart(user_id).await.unwrap().items.is_empty());
+    }
+}
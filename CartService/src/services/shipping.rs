@@ -0,0 +1,326 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use thiserror::Error;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use super::catalog::Product;
+use super::currency::{Currency, CurrencyConversion};
+use super::pricing::PricingContext;
+use super::promotions::Coupon;
+use super::{Cart, CartService, CartServiceError};
+
+/// Where an order is shipped to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShippingAddress {
+    pub country: String, // ISO 3166-1 alpha-2 code, e.g. "DE"
+    pub region: Option<String>,
+    pub postal_code: Option<String>,
+}
+
+/// What is being shipped, in the catalog currency
+#[derive(Debug, Clone, Copy)]
+pub struct Shipment {
+    pub weight_grams: u32,
+    pub order_value: Decimal, // Merchandise total after line discounts
+}
+
+/// A way to ship the cart and what it costs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShippingOption {
+    pub code: String,
+    pub name: String,
+    pub price: Decimal,
+    pub min_days: u32,
+    pub max_days: u32,
+}
+
+/// The shipping option chosen at checkout, priced in the order currency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShippingLine {
+    pub method: String,
+    pub name: String,
+    pub address: ShippingAddress,
+    pub price: Decimal,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShippingOptionsQuery {
+    #[serde(flatten)]
+    pub address: ShippingAddress,
+    pub currency: Option<Currency>,
+}
+
+#[derive(Debug, Error)]
+pub enum ShippingError {
+    #[error("Failed to read shipping table: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid shipping table: {0}")]
+    InvalidTable(#[from] serde_json::Error),
+}
+
+/// Quotes the shipping options available for a shipment
+#[async_trait]
+pub trait ShippingCalculator: Send + Sync {
+    /// Options for shipping to `address`, cheapest first; empty if the destination is not served
+    async fn options(
+        &self,
+        address: &ShippingAddress,
+        shipment: &Shipment,
+    ) -> Result<Vec<ShippingOption>, ShippingError>;
+}
+
+/// Price for shipments up to a weight; a band without a limit covers any weight
+#[derive(Debug, Clone, Deserialize)]
+pub struct WeightBand {
+    pub max_weight_grams: Option<u32>,
+    pub price: Decimal,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShippingMethod {
+    pub code: String,
+    pub name: String,
+    pub min_days: u32,
+    pub max_days: u32,
+    pub bands: Vec<WeightBand>,
+    pub free_over: Option<Decimal>, // Orders worth at least this ship for free
+}
+
+/// A group of countries sharing the same shipping methods; `"*"` matches any country
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShippingZone {
+    pub name: String,
+    pub countries: Vec<String>,
+    pub methods: Vec<ShippingMethod>,
+}
+
+/// Shipping rates as loaded from a JSON file, with amounts in the catalog currency
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShippingTable {
+    pub version: Option<String>,
+    pub zones: Vec<ShippingZone>,
+}
+
+/// Calculator that looks rates up in a zone/weight table
+pub struct TableShippingCalculator {
+    table: ShippingTable,
+}
+
+impl TableShippingCalculator {
+    pub fn new(table: ShippingTable) -> Self {
+        Self { table }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ShippingError> {
+        let contents = std::fs::read_to_string(path)?;
+        let table: ShippingTable = serde_json::from_str(&contents)?;
+        if let Some(version) = &table.version {
+            info!("Loaded shipping rates version {}", version);
+        }
+
+        Ok(Self::new(table))
+    }
+
+    // The first zone listing the country wins, so specific zones go before catch-alls
+    fn zone_for(&self, country: &str) -> Option<&ShippingZone> {
+        self.table.zones.iter().find(|zone| {
+            zone.countries
+                .iter()
+                .any(|c| c == "*" || c.eq_ignore_ascii_case(country))
+        })
+    }
+}
+
+#[async_trait]
+impl ShippingCalculator for TableShippingCalculator {
+    async fn options(
+        &self,
+        address: &ShippingAddress,
+        shipment: &Shipment,
+    ) -> Result<Vec<ShippingOption>, ShippingError> {
+        let zone = match self.zone_for(&address.country) {
+            Some(zone) => zone,
+            None => return Ok(Vec::new()),
+        };
+        debug!("Quoting shipping to {} from zone {}", address.country, zone.name);
+
+        let mut options: Vec<ShippingOption> = zone
+            .methods
+            .iter()
+            .filter_map(|method| {
+                // Methods without a band for this weight cannot carry the shipment
+                let band = method.bands.iter().find(|band| {
+                    band.max_weight_grams
+                        .is_none_or(|max| shipment.weight_grams <= max)
+                })?;
+                let free = matches!(method.free_over, Some(threshold) if shipment.order_value >= threshold);
+
+                Some(ShippingOption {
+                    code: method.code.clone(),
+                    name: method.name.clone(),
+                    price: if free { Decimal::ZERO } else { band.price },
+                    min_days: method.min_days,
+                    max_days: method.max_days,
+                })
+            })
+            .collect();
+
+        options.sort_by_key(|option| option.price);
+        Ok(options)
+    }
+}
+
+impl CartService {
+    // Weight and value of a cart, the inputs for quoting shipping
+    pub(crate) fn shipment_for(
+        &self,
+        cart: &Cart,
+        products: &HashMap<Uuid, Product>,
+        coupons: &[Coupon],
+    ) -> Shipment {
+        let weight_grams = cart
+            .items
+            .iter()
+            .filter_map(|item| {
+                let product = products.get(&item.product_id)?;
+                Some(product.weight_grams.saturating_mul(item.quantity))
+            })
+            .fold(0u32, u32::saturating_add);
+
+        // Free-shipping thresholds are defined in the catalog currency
+        let context = PricingContext::new(CurrencyConversion::identity(self.catalog_currency));
+        let priced = self
+            .pricing
+            .price(cart.clone(), products, coupons, &context, Utc::now());
+
+        Shipment {
+            weight_grams,
+            order_value: priced.merchandise_total(),
+        }
+    }
+
+    // List the ways the cart can be shipped to an address, priced in the requested currency
+    // or else the cart's own
+    pub async fn get_shipping_options(
+        &self,
+        user_id: Uuid,
+        address: &ShippingAddress,
+        currency: Option<Currency>,
+    ) -> Result<Vec<ShippingOption>, CartServiceError> {
+        let (cart, products) = self.load_cart_with_products(user_id).await?;
+        if cart.items.is_empty() {
+            return Err(CartServiceError::EmptyCart);
+        }
+
+        let coupons = self.get_applied_coupons(user_id).await?;
+        let shipment = self.shipment_for(&cart, &products, &coupons);
+        let conversion = self.conversion_to(currency.unwrap_or(cart.currency)).await?;
+
+        Ok(self
+            .shipping
+            .options(address, &shipment)
+            .await?
+            .into_iter()
+            .map(|option| ShippingOption {
+                price: conversion.convert(option.price),
+                ..option
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calculator() -> TableShippingCalculator {
+        let band = |max_weight_grams, price| WeightBand {
+            max_weight_grams,
+            price: Decimal::new(price, 2),
+        };
+        let method = |code: &str, bands, free_over| ShippingMethod {
+            code: code.to_string(),
+            name: code.to_string(),
+            min_days: 1,
+            max_days: 5,
+            bands,
+            free_over,
+        };
+
+        TableShippingCalculator::new(ShippingTable {
+            version: None,
+            zones: vec![
+                ShippingZone {
+                    name: "Germany".to_string(),
+                    countries: vec!["DE".to_string()],
+                    methods: vec![
+                        method("express", vec![band(Some(1000), 1499)], None),
+                        method(
+                            "standard",
+                            vec![band(Some(1000), 599), band(None, 999)],
+                            Some(Decimal::new(5000, 2)),
+                        ),
+                    ],
+                },
+                ShippingZone {
+                    name: "Rest of world".to_string(),
+                    countries: vec!["*".to_string()],
+                    methods: vec![method("international", vec![band(Some(2000), 2999)], None)],
+                },
+            ],
+        })
+    }
+
+    fn address(country: &str) -> ShippingAddress {
+        ShippingAddress {
+            country: country.to_string(),
+            region: None,
+            postal_code: None,
+        }
+    }
+
+    fn shipment(weight_grams: u32, order_value: i64) -> Shipment {
+        Shipment {
+            weight_grams,
+            order_value: Decimal::new(order_value, 2),
+        }
+    }
+
+    fn quote(country: &str, shipment: Shipment) -> Vec<(String, Decimal)> {
+        let options = tokio_test::block_on(calculator().options(&address(country), &shipment)).unwrap();
+        options.into_iter().map(|option| (option.code, option.price)).collect()
+    }
+
+    #[test]
+    fn options_are_priced_by_weight_band_cheapest_first() {
+        assert_eq!(
+            quote("de", shipment(800, 1000)),
+            vec![
+                ("standard".to_string(), Decimal::new(599, 2)),
+                ("express".to_string(), Decimal::new(1499, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn methods_without_a_band_for_the_weight_are_dropped() {
+        assert_eq!(quote("DE", shipment(3000, 1000)), vec![("standard".to_string(), Decimal::new(999, 2))]);
+        assert!(quote("US", shipment(3000, 1000)).is_empty());
+    }
+
+    #[test]
+    fn orders_over_the_threshold_ship_free() {
+        assert_eq!(quote("DE", shipment(800, 5000))[0], ("standard".to_string(), Decimal::ZERO));
+    }
+
+    #[test]
+    fn catch_all_zone_covers_other_countries() {
+        assert_eq!(quote("US", shipment(800, 1000)), vec![("international".to_string(), Decimal::new(2999, 2))]);
+    }
+}
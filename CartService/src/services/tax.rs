@@ -0,0 +1,430 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use super::catalog::Product;
+use super::currency::Currency;
+use super::pricing::PricedCartLine;
+use super::CartService;
+
+/// Tax category used for products and shipping without a more specific one
+pub const STANDARD_TAX_CATEGORY: &str = "standard";
+
+/// Whether catalog prices already include tax
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaxMode {
+    /// Prices include tax, which is shown as a share of the total (EU VAT)
+    Inclusive,
+    /// Tax is added on top of the prices (US sales tax)
+    Exclusive,
+}
+
+/// Tax rules for a country, or for one region of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxJurisdiction {
+    pub country: String,
+    pub region: Option<String>,
+    pub mode: TaxMode,
+    pub rates: HashMap<String, Decimal>, // Rate by tax category, e.g. "standard": "0.19"
+    #[serde(default)]
+    pub exempt_categories: Vec<String>,  // Tax categories that are not taxed here
+    #[serde(default)]
+    pub tax_shipping: bool,              // Whether shipping is taxed at the standard rate
+}
+
+/// A versioned set of tax rules, as loaded from the tax data file
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TaxRules {
+    pub version: String,
+    #[serde(default)]
+    pub categories: HashMap<String, String>, // Catalog category -> tax category
+    pub jurisdictions: Vec<TaxJurisdiction>,
+}
+
+impl TaxRules {
+    /// Rules for a destination; a region-specific entry wins over the country-wide one
+    pub fn jurisdiction(&self, country: &str, region: Option<&str>) -> Option<&TaxJurisdiction> {
+        let matches_country = |j: &&TaxJurisdiction| j.country.eq_ignore_ascii_case(country);
+
+        region
+            .and_then(|region| {
+                self.jurisdictions.iter().filter(matches_country).find(|j| {
+                    j.region
+                        .as_deref()
+                        .is_some_and(|r| r.eq_ignore_ascii_case(region))
+                })
+            })
+            .or_else(|| {
+                self.jurisdictions
+                    .iter()
+                    .filter(matches_country)
+                    .find(|j| j.region.is_none())
+            })
+    }
+}
+
+/// Where tax is computed for, resolved against the current rules before pricing
+#[derive(Debug, Clone)]
+pub struct TaxContext {
+    pub version: String,
+    pub jurisdiction: TaxJurisdiction,
+    pub categories: HashMap<String, String>,
+}
+
+impl TaxContext {
+    pub fn resolve(rules: &TaxRules, country: &str, region: Option<&str>) -> Option<Self> {
+        Some(Self {
+            version: rules.version.clone(),
+            jurisdiction: rules.jurisdiction(country, region)?.clone(),
+            categories: rules.categories.clone(),
+        })
+    }
+
+    fn rate_for(&self, tax_category: &str) -> Decimal {
+        let jurisdiction = &self.jurisdiction;
+        if jurisdiction.exempt_categories.iter().any(|c| c == tax_category) {
+            return Decimal::ZERO;
+        }
+
+        jurisdiction
+            .rates
+            .get(tax_category)
+            .or_else(|| jurisdiction.rates.get(STANDARD_TAX_CATEGORY))
+            .copied()
+            .unwrap_or(Decimal::ZERO)
+    }
+
+    // Tax contained in or added to an amount, depending on the mode
+    fn tax_on(&self, amount: Decimal, rate: Decimal, currency: Currency) -> Decimal {
+        match self.jurisdiction.mode {
+            TaxMode::Exclusive => currency.round(amount * rate),
+            TaxMode::Inclusive => currency.round(amount * rate / (Decimal::ONE + rate)),
+        }
+    }
+
+    /// Compute tax per line. Line discounts are spread over the lines in proportion to their
+    /// totals so each line is taxed on what the shopper actually pays for it.
+    pub fn compute(
+        &self,
+        lines: &[PricedCartLine],
+        products: &HashMap<Uuid, Product>,
+        line_discounts: Decimal,
+        shipping: Decimal,
+        currency: Currency,
+    ) -> TaxBreakdown {
+        let subtotal: Decimal = lines.iter().map(|line| line.line_total).sum();
+        let mut undistributed = line_discounts;
+        let mut tax_lines = Vec::with_capacity(lines.len());
+
+        for (index, line) in lines.iter().enumerate() {
+            let discount = if index + 1 == lines.len() || subtotal.is_zero() {
+                undistributed
+            } else {
+                currency.round(line_discounts * line.line_total / subtotal)
+            };
+            undistributed -= discount;
+
+            let tax_category = products
+                .get(&line.product_id)
+                .and_then(|product| self.categories.get(&product.category))
+                .map(String::as_str)
+                .unwrap_or(STANDARD_TAX_CATEGORY);
+            let rate = self.rate_for(tax_category);
+            let taxable_amount = line.line_total - discount;
+
+            tax_lines.push(TaxLine {
+                line_id: line.id,
+                tax_category: tax_category.to_string(),
+                rate,
+                taxable_amount,
+                tax_amount: self.tax_on(taxable_amount, rate, currency),
+            });
+        }
+
+        let shipping_tax = if self.jurisdiction.tax_shipping {
+            self.tax_on(shipping, self.rate_for(STANDARD_TAX_CATEGORY), currency)
+        } else {
+            Decimal::ZERO
+        };
+
+        let mut by_rate: Vec<TaxRateTotal> = Vec::new();
+        for line in &tax_lines {
+            match by_rate.iter_mut().find(|total| total.rate == line.rate) {
+                Some(total) => {
+                    total.taxable_amount += line.taxable_amount;
+                    total.tax_amount += line.tax_amount;
+                }
+                None => by_rate.push(TaxRateTotal {
+                    rate: line.rate,
+                    taxable_amount: line.taxable_amount,
+                    tax_amount: line.tax_amount,
+                }),
+            }
+        }
+
+        let total = tax_lines.iter().map(|line| line.tax_amount).sum::<Decimal>() + shipping_tax;
+
+        TaxBreakdown {
+            rules_version: self.version.clone(),
+            country: self.jurisdiction.country.clone(),
+            region: self.jurisdiction.region.clone(),
+            mode: self.jurisdiction.mode,
+            lines: tax_lines,
+            shipping_tax,
+            by_rate,
+            total,
+        }
+    }
+}
+
+/// Tax on one cart line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxLine {
+    pub line_id: Uuid,
+    pub tax_category: String,
+    pub rate: Decimal,
+    pub taxable_amount: Decimal, // Line total after its share of line discounts
+    pub tax_amount: Decimal,
+}
+
+/// Taxable amount and tax summed over the lines sharing a rate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxRateTotal {
+    pub rate: Decimal,
+    pub taxable_amount: Decimal,
+    pub tax_amount: Decimal,
+}
+
+/// How the tax on a cart or order was computed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxBreakdown {
+    pub rules_version: String,
+    pub country: String,
+    pub region: Option<String>,
+    pub mode: TaxMode, // With inclusive tax the total is already part of the prices
+    pub lines: Vec<TaxLine>,
+    pub shipping_tax: Decimal,
+    pub by_rate: Vec<TaxRateTotal>,
+    pub total: Decimal,
+}
+
+#[derive(Debug, Error)]
+pub enum TaxRulesError {
+    #[error("Failed to read tax rules: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid tax rules: {0}")]
+    InvalidRules(#[from] serde_json::Error),
+}
+
+/// Holds the current tax rules and reloads them when the data file changes
+pub struct TaxRulesStore {
+    path: Option<PathBuf>,
+    current: RwLock<(Option<DateTime<Utc>>, Arc<TaxRules>)>,
+}
+
+impl TaxRulesStore {
+    /// A store with fixed rules, used in tests
+    #[cfg(test)]
+    pub fn new(rules: TaxRules) -> Self {
+        Self {
+            path: None,
+            current: RwLock::new((None, Arc::new(rules))),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, TaxRulesError> {
+        let store = Self {
+            path: Some(path.as_ref().to_path_buf()),
+            current: RwLock::new((None, Arc::new(TaxRules::default()))),
+        };
+        store.reload()?;
+
+        Ok(store)
+    }
+
+    /// The rules in effect right now
+    pub fn current(&self) -> Arc<TaxRules> {
+        self.current.read().unwrap().1.clone()
+    }
+
+    /// Re-read the data file if it changed since it was last loaded.
+    /// Returns the new version, or `None` if nothing changed.
+    pub fn reload(&self) -> Result<Option<String>, TaxRulesError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let modified: DateTime<Utc> = std::fs::metadata(path)?.modified()?.into();
+        if self.current.read().unwrap().0 == Some(modified) {
+            return Ok(None);
+        }
+
+        let rules: TaxRules = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let version = rules.version.clone();
+        *self.current.write().unwrap() = (Some(modified), Arc::new(rules));
+
+        Ok(Some(version))
+    }
+}
+
+impl CartService {
+    // Resolve the tax rules for a destination, falling back to the default tax country
+    pub(crate) fn tax_context(&self, country: Option<&str>, region: Option<&str>) -> Option<TaxContext> {
+        let country = country.or(self.default_tax_country.as_deref())?;
+
+        TaxContext::resolve(&self.tax_rules.current(), country, region)
+    }
+}
+
+/// Periodically pick up changes to the tax data file until shutdown begins; a bad file keeps the previous rules
+pub fn spawn_tax_rules_reloader(
+    store: Arc<TaxRulesStore>,
+    interval: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = shutdown.changed() => break,
+            }
+            match store.reload() {
+                Ok(None) => {}
+                Ok(Some(version)) => info!("Loaded tax rules version {}", version),
+                Err(err) => error!("Failed to reload tax rules: {}", err),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(category: &str) -> Product {
+        Product {
+            id: Uuid::new_v4(),
+            name: "Widget".to_string(),
+            category: category.to_string(),
+            price: Decimal::ZERO,
+            quantity: 100,
+            is_active: true,
+            weight_grams: 0,
+            purchase_rules: Default::default(),
+        }
+    }
+
+    fn line(product: &Product, line_total: Decimal) -> PricedCartLine {
+        PricedCartLine {
+            id: Uuid::new_v4(),
+            product_id: product.id,
+            name: product.name.clone(),
+            options: Default::default(),
+            quantity: 1,
+            unit_price: line_total,
+            line_total,
+        }
+    }
+
+    fn context(mode: TaxMode, tax_shipping: bool) -> TaxContext {
+        TaxContext {
+            version: "test".to_string(),
+            jurisdiction: TaxJurisdiction {
+                country: "DE".to_string(),
+                region: None,
+                mode,
+                rates: HashMap::from([
+                    (STANDARD_TAX_CATEGORY.to_string(), Decimal::new(19, 2)),
+                    ("reduced".to_string(), Decimal::new(7, 2)),
+                ]),
+                exempt_categories: vec!["exempt".to_string()],
+                tax_shipping,
+            },
+            categories: HashMap::from([
+                ("books".to_string(), "reduced".to_string()),
+                ("gift_cards".to_string(), "exempt".to_string()),
+            ]),
+        }
+    }
+
+    #[test]
+    fn line_discounts_are_spread_in_proportion_to_line_totals() {
+        let (a, b, c) = (product("tools"), product("tools"), product("tools"));
+        let lines = [
+            line(&a, Decimal::new(1000, 2)),
+            line(&b, Decimal::new(1000, 2)),
+            line(&c, Decimal::new(1000, 2)),
+        ];
+        let products = HashMap::from([(a.id, a), (b.id, b), (c.id, c)]);
+
+        let tax = context(TaxMode::Exclusive, false).compute(&lines, &products, Decimal::new(1000, 2), Decimal::ZERO, Currency::Usd);
+
+        let taxable: Vec<Decimal> = tax.lines.iter().map(|line| line.taxable_amount).collect();
+        assert_eq!(taxable, vec![Decimal::new(667, 2), Decimal::new(667, 2), Decimal::new(666, 2)]);
+        assert_eq!(taxable.iter().sum::<Decimal>(), Decimal::new(2000, 2));
+    }
+
+    #[test]
+    fn exclusive_tax_is_added_on_top_per_line() {
+        let (tool, book, card) = (product("tools"), product("books"), product("gift_cards"));
+        let lines = [
+            line(&tool, Decimal::new(1999, 2)),
+            line(&book, Decimal::new(1250, 2)),
+            line(&card, Decimal::new(2500, 2)),
+        ];
+        let products = HashMap::from([(tool.id, tool), (book.id, book), (card.id, card)]);
+
+        let tax = context(TaxMode::Exclusive, true).compute(&lines, &products, Decimal::ZERO, Decimal::new(500, 2), Currency::Usd);
+
+        let amounts: Vec<Decimal> = tax.lines.iter().map(|line| line.tax_amount).collect();
+        assert_eq!(amounts, vec![Decimal::new(380, 2), Decimal::new(88, 2), Decimal::ZERO]);
+        assert_eq!(tax.shipping_tax, Decimal::new(95, 2));
+        assert_eq!(tax.total, Decimal::new(563, 2));
+        assert_eq!(tax.by_rate.len(), 3);
+    }
+
+    #[test]
+    fn inclusive_tax_is_the_share_contained_in_the_price() {
+        let tool = product("tools");
+        let lines = [line(&tool, Decimal::new(1190, 2)), line(&tool, Decimal::new(1000, 2))];
+        let products = HashMap::from([(tool.id, tool)]);
+
+        let tax = context(TaxMode::Inclusive, false).compute(&lines, &products, Decimal::ZERO, Decimal::new(500, 2), Currency::Usd);
+
+        let amounts: Vec<Decimal> = tax.lines.iter().map(|line| line.tax_amount).collect();
+        assert_eq!(amounts, vec![Decimal::new(190, 2), Decimal::new(160, 2)]);
+        assert_eq!(tax.shipping_tax, Decimal::ZERO);
+        assert_eq!(tax.by_rate[0].tax_amount, Decimal::new(350, 2));
+    }
+
+    #[test]
+    fn region_rules_win_over_country_rules() {
+        let country = context(TaxMode::Exclusive, false).jurisdiction;
+        let region = TaxJurisdiction {
+            region: Some("BY".to_string()),
+            ..country.clone()
+        };
+        let rules = TaxRules {
+            version: "test".to_string(),
+            categories: HashMap::new(),
+            jurisdictions: vec![country, region],
+        };
+
+        assert_eq!(rules.jurisdiction("de", Some("by")).unwrap().region.as_deref(), Some("BY"));
+        assert_eq!(rules.jurisdiction("DE", Some("BE")).unwrap().region, None);
+        assert!(rules.jurisdiction("FR", None).is_none());
+    }
+}
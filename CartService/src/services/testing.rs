@@ -0,0 +1,281 @@
+//! Fixtures shared by the service tests: a `CartService` wired to in-memory backends, and a
+//! stand-in for the ProductService routes CartService calls.
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router, Server};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use super::abandonment::InMemoryNotifier;
+use super::catalog::{InMemoryProductCatalog, Product};
+use super::currency::{Currency, RateTable, StaticExchangeRates};
+use super::limits::CartLimits;
+use super::outbox::InMemoryEventPublisher;
+use super::pricing::PricingEngine;
+use super::shipping::{ShippingMethod, ShippingTable, ShippingZone, TableShippingCalculator, WeightBand};
+use super::tax::{TaxJurisdiction, TaxMode, TaxRules, TaxRulesStore, STANDARD_TAX_CATEGORY};
+use super::{CartService, MergeStrategy};
+use crate::db::Database;
+use crate::handlers::health::HealthCheck;
+use crate::repository::InMemoryCartRepository;
+use crate::routes::create_router;
+use crate::utils::jwt::Claims;
+
+/// An active product with no purchase rules
+pub fn product(price: Decimal, quantity: u32) -> Product {
+    Product {
+        id: Uuid::new_v4(),
+        name: "Widget".to_string(),
+        category: "tools".to_string(),
+        price,
+        quantity,
+        is_active: true,
+        weight_grams: 200,
+        purchase_rules: Default::default(),
+    }
+}
+
+/// Secret the test services share with the (absent) UserService for access tokens
+pub const JWT_SECRET: &str = "jwt-secret-that-is-long-enough-to-pass";
+
+/// An access token for `user_id`, as UserService would issue it, valid for an hour
+pub fn access_token(user_id: Uuid) -> String {
+    let claims = Claims {
+        sub: user_id.to_string(),
+        exp: (chrono::Utc::now() + chrono::Duration::hours(1)).timestamp() as usize,
+    };
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(JWT_SECRET.as_ref()),
+    )
+    .unwrap()
+}
+
+/// A `CartService` on in-memory backends, with handles to inspect them
+pub struct TestServices {
+    pub service: CartService,
+    pub repository: Arc<InMemoryCartRepository>,
+    pub catalog: Arc<InMemoryProductCatalog>,
+}
+
+/// Prices in USD, ships anywhere for a flat 5.00 as `standard`, and taxes US orders at 10%
+pub fn cart_service(products: impl IntoIterator<Item = Product>) -> TestServices {
+    let repository = Arc::new(InMemoryCartRepository::new());
+    let catalog = Arc::new(InMemoryProductCatalog::new(products));
+
+    let shipping = ShippingTable {
+        version: None,
+        zones: vec![ShippingZone {
+            name: "Everywhere".to_string(),
+            countries: vec!["*".to_string()],
+            methods: vec![ShippingMethod {
+                code: "standard".to_string(),
+                name: "Standard".to_string(),
+                min_days: 3,
+                max_days: 5,
+                bands: vec![WeightBand {
+                    max_weight_grams: None,
+                    price: Decimal::new(500, 2),
+                }],
+                free_over: None,
+            }],
+        }],
+    };
+    let tax_rules = TaxRules {
+        version: "test".to_string(),
+        categories: HashMap::new(),
+        jurisdictions: vec![TaxJurisdiction {
+            country: "US".to_string(),
+            region: None,
+            mode: TaxMode::Exclusive,
+            rates: HashMap::from([(STANDARD_TAX_CATEGORY.to_string(), Decimal::new(10, 2))]),
+            exempt_categories: Vec::new(),
+            tax_shipping: false,
+        }],
+    };
+
+    let service = CartService {
+        repository: repository.clone(),
+        cart_token_secret: "cart-token-secret-that-is-long-enough".to_string(),
+        jwt_secret: JWT_SECRET.to_string(),
+        internal_api_key: None,
+        merge_strategy: MergeStrategy::Sum,
+        catalog: catalog.clone(),
+        pricing: PricingEngine::default(),
+        limits: CartLimits::default(),
+        stock_ledger: catalog.clone(),
+        reservation_ttl: chrono::Duration::minutes(15),
+        notifier: Arc::new(InMemoryNotifier::default()),
+        idempotency_ttl: chrono::Duration::hours(24),
+        publisher: Some(Arc::new(InMemoryEventPublisher::default())),
+        catalog_currency: Currency::Usd,
+        default_currency: Currency::Usd,
+        exchange_rates: Arc::new(StaticExchangeRates::new(RateTable {
+            version: None,
+            base: Currency::Usd,
+            rates: HashMap::new(),
+        })),
+        shipping: Arc::new(TableShippingCalculator::new(shipping)),
+        tax_rules: Arc::new(TaxRulesStore::new(tax_rules)),
+        default_tax_country: Some("US".to_string()),
+    };
+
+    TestServices {
+        service,
+        repository,
+        catalog,
+    }
+}
+
+/// Serve the full router over the test service, as `main` does. Returns the base URL.
+pub async fn spawn_app(services: TestServices) -> String {
+    let health = Arc::new(HealthCheck {
+        database: Arc::new(Database::Memory((*services.repository).clone())),
+        http_client: reqwest::Client::new(),
+        endpoints: Vec::new(),
+        timeout: std::time::Duration::from_secs(1),
+        stopping: watch::channel(false).1,
+    });
+    let app = create_router(Arc::new(services.service), health);
+
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(app.into_make_service());
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    url
+}
+
+/// API key the ProductService stand-in accepts
+pub const PRODUCT_SERVICE_API_KEY: &str = "test-api-key";
+
+/// Products the ProductService stand-in serves, with the stock decrements applied by key
+#[derive(Default)]
+pub struct ProductServiceState {
+    pub products: Mutex<HashMap<Uuid, Product>>,
+    pub decrements: Mutex<HashMap<String, (Uuid, u32)>>,
+}
+
+/// Serve the `/api/products` routes CartService calls, as ProductService does: products are
+/// addressed by their `uuid`, other ids are not found, and requests need an API key.
+/// Returns the base URL to give `HttpProductCatalog`.
+pub async fn spawn_product_service(products: impl IntoIterator<Item = Product>) -> (String, Arc<ProductServiceState>) {
+    let state = Arc::new(ProductServiceState::default());
+    state.products.lock().unwrap().extend(products.into_iter().map(|p| (p.id, p)));
+
+    let app = Router::new()
+        .route("/api/products/:id/details", get(product_details))
+        .route("/api/products/:id/stock-decrements", post(decrement_stock))
+        .route("/api/products/:id/stock-decrements/:key", delete(reverse_decrement))
+        .with_state(state.clone());
+
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(app.into_make_service());
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    (url, state)
+}
+
+fn authorized(headers: &HeaderMap) -> Result<(), StatusCode> {
+    match headers.get("x-api-key") {
+        Some(key) if key == PRODUCT_SERVICE_API_KEY => Ok(()),
+        Some(_) => Err(StatusCode::FORBIDDEN),
+        None => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+// ProductService only matches hyphenated UUIDs
+fn parse_uuid(id: &str) -> Option<Uuid> {
+    Uuid::try_parse(id).ok().filter(|uuid| uuid.hyphenated().to_string() == id.to_ascii_lowercase())
+}
+
+async fn product_details(
+    State(state): State<Arc<ProductServiceState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(status) = authorized(&headers) {
+        return status.into_response();
+    }
+    let products = state.products.lock().unwrap();
+    let product = match parse_uuid(&id).and_then(|uuid| products.get(&uuid)) {
+        Some(product) => product,
+        None => return (StatusCode::NOT_FOUND, Json(json!({ "message": "Product not found" }))).into_response(),
+    };
+
+    // The document as Mongoose serializes it
+    Json(json!({
+        "_id": "65a1f0c2e4b0a1b2c3d4e5f6",
+        "uuid": product.id,
+        "name": product.name,
+        "description": "A product",
+        "price": product.price.to_string().parse::<f64>().unwrap(),
+        "category": product.category,
+        "quantity": product.quantity,
+        "createdAt": "2024-06-01T00:00:00.000Z",
+        "updatedAt": "2024-06-01T00:00:00.000Z",
+        "__v": 0,
+    }))
+    .into_response()
+}
+
+#[derive(Deserialize)]
+struct StockDecrement {
+    quantity: u32,
+    key: String,
+}
+
+async fn decrement_stock(
+    State(state): State<Arc<ProductServiceState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<StockDecrement>,
+) -> Response {
+    if let Err(status) = authorized(&headers) {
+        return status.into_response();
+    }
+    let mut products = state.products.lock().unwrap();
+    let product = match parse_uuid(&id).and_then(|uuid| products.get_mut(&uuid)) {
+        Some(product) => product,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    if let Entry::Vacant(entry) = state.decrements.lock().unwrap().entry(body.key) {
+        if product.quantity < body.quantity {
+            return (StatusCode::CONFLICT, Json(json!({ "quantity": product.quantity }))).into_response();
+        }
+        product.quantity -= body.quantity;
+        entry.insert((product.id, body.quantity));
+    }
+
+    Json(json!({ "quantity": product.quantity })).into_response()
+}
+
+async fn reverse_decrement(
+    State(state): State<Arc<ProductServiceState>>,
+    Path((id, key)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(status) = authorized(&headers) {
+        return status.into_response();
+    }
+    if parse_uuid(&id).is_some() {
+        if let Some((product_id, quantity)) = state.decrements.lock().unwrap().remove(&key) {
+            if let Some(product) = state.products.lock().unwrap().get_mut(&product_id) {
+                product.quantity += quantity;
+            }
+        }
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -0,0 +1,64 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Header used to send and receive the guest cart token
+pub const CART_TOKEN_HEADER: &str = "x-cart-token";
+
+/// Issues an opaque token for a guest cart, in the form `<cart id>.<signature>`
+pub fn issue_cart_token(cart_id: Uuid, secret: &str) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(cart_id.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(sign(payload.as_bytes(), secret));
+
+    format!("{}.{}", payload, signature)
+}
+
+/// Verifies a guest cart token and returns the cart id it was issued for
+pub fn verify_cart_token(token: &str, secret: &str) -> Option<Uuid> {
+    let (payload, signature) = token.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature).ok()?;
+
+    let bytes = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    Uuid::from_slice(&bytes).ok()
+}
+
+/// Issues an unguessable token for a shared cart snapshot. Share tokens are signed under a
+/// different context than guest cart tokens, so one can never be used as the other.
+pub fn issue_share_token(snapshot_id: Uuid, secret: &str) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(snapshot_id.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(sign(share_context(&payload).as_bytes(), secret));
+
+    format!("{}.{}", payload, signature)
+}
+
+/// Verifies a share token and returns the snapshot id it was issued for
+pub fn verify_share_token(token: &str, secret: &str) -> Option<Uuid> {
+    let (payload, signature) = token.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(share_context(payload).as_bytes());
+    mac.verify_slice(&signature).ok()?;
+
+    let bytes = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    Uuid::from_slice(&bytes).ok()
+}
+
+// Base64 payloads never contain '.', so this cannot collide with a guest cart payload
+fn share_context(payload: &str) -> String {
+    format!("share.{}", payload)
+}
+
+fn sign(payload: &[u8], secret: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(payload);
+    mac.finalize().into_bytes().to_vec()
+}
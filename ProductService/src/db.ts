@@ -1,10 +1,15 @@
 import mongoose from "mongoose";
+import { assignMissingUuids } from "./product/product.service";
 
 const connectDB = async () => {
   try {
     const uri = process.env.MONGO_URI || "mongodb://localhost:27017/products";
     await mongoose.connect(uri);
     console.log("MongoDB connected!");
+    const assigned = await assignMissingUuids();
+    if (assigned > 0) {
+      console.log(`Assigned UUIDs to ${assigned} products`);
+    }
   } catch (error) {
     console.error("Error connecting to the database: " + error);
     process.exit(1);
@@ -1,13 +1,16 @@
 import mongoose, { Schema, Document } from "mongoose";
+import { randomUUID } from "crypto";
 import Joi from "joi";
 
 export interface IProduct extends Document {
+  uuid: string;
   name: string;
   description: string;
   notes?: string;
   price: number;
   category: string;
   quantity: number;
+  stockDecrements?: { key: string; quantity: number }[];
   createdAt?: Date;
   updatedAt?: Date;
 }
@@ -15,6 +18,14 @@ export interface IProduct extends Document {
 // Create the Product schema
 const ProductSchema: Schema = new Schema(
   {
+    // Id other services, such as CartService, refer to the product by
+    uuid: {
+      type: String,
+      required: true,
+      unique: true,
+      immutable: true,
+      default: () => randomUUID(),
+    },
     name: {
       type: String,
       required: [true, "Product name is required"],
@@ -44,6 +55,12 @@ const ProductSchema: Schema = new Schema(
       required: [true, "Quantity is required"],
       min: [0, "Quantity must be a positive number"],
     },
+    // Recent keyed stock decrements, so a retried decrement is applied only once
+    stockDecrements: {
+      type: [{ _id: false, key: String, quantity: Number }],
+      default: [],
+      select: false,
+    },
   },
   {
     timestamps: true, // Automatically add createdAt and updatedAt fields
@@ -0,0 +1,115 @@
+import test, { after, before, mock } from "node:test";
+import assert from "node:assert/strict";
+import { randomUUID } from "crypto";
+import { AddressInfo } from "net";
+import { Server } from "http";
+import express from "express";
+import Product from "./product.model";
+import router from "./product.routes";
+
+// Contract tests for the routes CartService calls, with the ids and payloads it sends.
+// The model is stubbed, so no database is needed.
+
+const API_KEY = "test-api-key";
+let server: Server;
+let baseUrl: string;
+
+before(async () => {
+  process.env.VALID_API_KEYS = API_KEY;
+  const app = express();
+  app.use(express.json());
+  app.use("/api/products", router);
+  server = app.listen(0);
+  await new Promise((resolve) => server.once("listening", resolve));
+  baseUrl = `http://127.0.0.1:${(server.address() as AddressInfo).port}/api/products`;
+});
+
+after(() => {
+  server.close();
+});
+
+const request = (path: string, init: RequestInit = {}) =>
+  fetch(`${baseUrl}${path}`, {
+    ...init,
+    headers: { "x-api-key": API_KEY, "content-type": "application/json" },
+  });
+
+test("details are looked up by the UUID CartService sends", async () => {
+  const uuid = randomUUID();
+  const findOne = mock.method(Product, "findOne", async (filter: any) =>
+    filter.uuid === uuid
+      ? { uuid, name: "Widget", category: "tools", price: 12.5, quantity: 3 }
+      : null
+  );
+
+  const response = await request(`/${uuid}/details`);
+
+  assert.equal(response.status, 200);
+  assert.deepEqual(findOne.mock.calls[0].arguments[0], { uuid });
+  // The fields CartService's ProductResponse requires
+  const body = await response.json();
+  assert.equal(body.name, "Widget");
+  assert.equal(body.category, "tools");
+  assert.equal(body.price, 12.5);
+  assert.equal(body.quantity, 3);
+  findOne.mock.restore();
+});
+
+test("details for an unknown UUID are not found", async () => {
+  const findOne = mock.method(Product, "findOne", async () => null);
+
+  const response = await request(`/${randomUUID()}/details`);
+
+  assert.equal(response.status, 404);
+  findOne.mock.restore();
+});
+
+test("ids that are not UUIDs are not found without a lookup", async () => {
+  const findOne = mock.method(Product, "findOne", async () => {
+    throw new Error("should not be called");
+  });
+
+  const response = await request("/65a1f0c2e4b0a1b2c3d4e5f6/details");
+
+  assert.equal(response.status, 404);
+  assert.equal(findOne.mock.callCount(), 0);
+  findOne.mock.restore();
+});
+
+test("stock is decremented by UUID with the caller's key", async () => {
+  const uuid = randomUUID();
+  const key = randomUUID();
+  const findOneAndUpdate = mock.method(Product, "findOneAndUpdate", async (filter: any) =>
+    filter.uuid === uuid ? { uuid, quantity: 7 } : null
+  );
+
+  const response = await request(`/${uuid}/stock-decrements`, {
+    method: "POST",
+    body: JSON.stringify({ quantity: 2, key }),
+  });
+
+  assert.equal(response.status, 200);
+  assert.deepEqual(await response.json(), { quantity: 7 });
+  const [filter, update] = findOneAndUpdate.mock.calls[0].arguments as any[];
+  assert.equal(filter.uuid, uuid);
+  assert.deepEqual(update.$inc, { quantity: -2 });
+  assert.deepEqual(update.$push.stockDecrements.$each, [{ key, quantity: 2 }]);
+  findOneAndUpdate.mock.restore();
+});
+
+test("a decrement that cannot be covered is a conflict", async () => {
+  const uuid = randomUUID();
+  const findOneAndUpdate = mock.method(Product, "findOneAndUpdate", async () => null);
+  const findOne = mock.method(Product, "findOne", () => ({
+    select: async () => ({ uuid, quantity: 1, stockDecrements: [] }),
+  }));
+
+  const response = await request(`/${uuid}/stock-decrements`, {
+    method: "POST",
+    body: JSON.stringify({ quantity: 2, key: randomUUID() }),
+  });
+
+  assert.equal(response.status, 409);
+  findOneAndUpdate.mock.restore();
+  findOne.mock.restore();
+});
@@ -1,14 +1,16 @@
 import { Router, Request, Response, NextFunction } from "express";
 import authorize from "./product.middleware"; // Authorization middleware
 import validateRequest from "./product.validateRequest"; // Validation middleware
-import productSchema from "./product.validation"; // Joi validation schema
+import productSchema, { stockDecrementSchema } from "./product.validation"; // Joi validation schemas
 import Product, { IProduct } from "./product.model";
 import {
   createProduct,
   getAllProducts,
-  getProductById,
+  getProductByUuid,
   updateProduct,
   deleteProduct,
+  decrementStock,
+  reverseStockDecrement,
 } from "./product.service";
 
 // Helper to wrap async handlers
@@ -20,6 +22,9 @@ const asyncHandler =
 
 const router = Router();
 
+// Products are addressed by UUID in the routes other services call
+const UUID_PATTERN = /^[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$/i;
+
 // Apply authorization middleware globally to the routes
 router.use((req: Request, res: Response, next: NextFunction) => {
   authorize(req, res, next);
@@ -86,6 +91,57 @@ router.get(
   })
 );
 
+// Get the product document by UUID, for services such as CartService
+router.get(
+  "/:id/details",
+  asyncHandler(async (req: Request, res: Response) => {
+    // Ids that are not UUIDs cannot match a product
+    if (!UUID_PATTERN.test(req.params.id)) {
+      res.status(404).json({ message: "Product not found" });
+      return;
+    }
+    const product = await getProductByUuid(req.params.id);
+    if (!product) {
+      res.status(404).json({ message: "Product not found" });
+      return;
+    }
+    res.json(product);
+  })
+);
+
+// Take stock out of a product, by UUID; retries with the same key are applied once
+router.post(
+  "/:id/stock-decrements",
+  validateRequest(stockDecrementSchema),
+  asyncHandler(async (req: Request, res: Response) => {
+    if (!UUID_PATTERN.test(req.params.id)) {
+      res.status(404).json({ message: "Product not found" });
+      return;
+    }
+    const result = await decrementStock(req.params.id, req.body.quantity, req.body.key);
+    if (result.status === "not_found") {
+      res.status(404).json({ message: "Product not found" });
+      return;
+    }
+    if (result.status === "insufficient_stock") {
+      res.status(409).json({ message: "Not enough stock", quantity: result.product.quantity });
+      return;
+    }
+    res.json({ quantity: result.product.quantity });
+  })
+);
+
+// Undo the stock decrement made with a key; undoing an unknown key does nothing
+router.delete(
+  "/:id/stock-decrements/:key",
+  asyncHandler(async (req: Request, res: Response) => {
+    if (UUID_PATTERN.test(req.params.id)) {
+      await reverseStockDecrement(req.params.id, req.params.key);
+    }
+    res.status(204).end();
+  })
+);
+
 // Get a product by ID
 router.get(
   "/:id",
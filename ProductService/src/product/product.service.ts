@@ -1,3 +1,4 @@
+import { randomUUID } from "crypto";
 import Product, { IProduct } from "./product.model";
 
 // Create a new product
@@ -16,6 +17,24 @@ export const getProductById = async (id: string) => {
   return await Product.findById(id);
 };
 
+// Get a single product by the UUID other services know it by
+export const getProductByUuid = async (uuid: string) => {
+  return await Product.findOne({ uuid });
+};
+
+// Give products created before they had a UUID one, so other services can refer to them
+export const assignMissingUuids = async () => {
+  // Lean documents, because hydrating would fill in a default UUID without saving it
+  const products = await Product.find({ uuid: { $exists: false } }).select("_id").lean();
+  for (const product of products) {
+    await Product.updateOne(
+      { _id: product._id, uuid: { $exists: false } },
+      { $set: { uuid: randomUUID() } }
+    );
+  }
+  return products.length;
+};
+
 // Update a product by ID
 export const updateProduct = async (id: string, data: Partial<IProduct>) => {
   return await Product.findByIdAndUpdate(id, data, { new: true });
@@ -25,3 +44,57 @@ export const updateProduct = async (id: string, data: Partial<IProduct>) => {
 export const deleteProduct = async (id: string) => {
   return await Product.findByIdAndDelete(id);
 };
+
+// How many decrement keys each product remembers for retries
+const MAX_STOCK_DECREMENT_KEYS = 1000;
+
+// Take stock out in a single atomic update, once per key. Products are addressed by UUID.
+export const decrementStock = async (
+  uuid: string,
+  quantity: number,
+  key: string
+) => {
+  const updated = await Product.findOneAndUpdate(
+    { uuid, quantity: { $gte: quantity }, "stockDecrements.key": { $ne: key } },
+    {
+      $inc: { quantity: -quantity },
+      $push: {
+        stockDecrements: {
+          $each: [{ key, quantity }],
+          $slice: -MAX_STOCK_DECREMENT_KEYS,
+        },
+      },
+    },
+    { new: true }
+  );
+  if (updated) {
+    return { status: "applied" as const, product: updated };
+  }
+
+  // Nothing matched: the product is missing, the key was already used, or stock ran out
+  const product = await Product.findOne({ uuid }).select("+stockDecrements");
+  if (!product) {
+    return { status: "not_found" as const };
+  }
+  if (product.stockDecrements?.some((decrement) => decrement.key === key)) {
+    return { status: "applied" as const, product };
+  }
+  return { status: "insufficient_stock" as const, product };
+};
+
+// Put back the stock taken by the decrement with this key, if it was applied
+export const reverseStockDecrement = async (uuid: string, key: string) => {
+  const product = await Product.findOne({ uuid, "stockDecrements.key": key }).select(
+    "+stockDecrements"
+  );
+  const decrement = product?.stockDecrements?.find((d) => d.key === key);
+  if (!decrement) {
+    return;
+  }
+
+  // Only the update that removes the key puts the stock back
+  await Product.updateOne(
+    { uuid, "stockDecrements.key": key },
+    { $inc: { quantity: decrement.quantity }, $pull: { stockDecrements: { key } } }
+  );
+};
@@ -12,4 +12,10 @@ const productSchema = Joi.object({
   notes: Joi.string().optional(),
 });
 
+// Joi schema for a keyed stock decrement
+export const stockDecrementSchema = Joi.object({
+  quantity: Joi.number().integer().min(1).required(),
+  key: Joi.string().required(),
+});
+
 export default productSchema;
@@ -0,0 +1,17 @@
+CREATE TABLE users (
+    id BINARY(16) PRIMARY KEY,
+    first_name VARCHAR(100) NOT NULL,
+    last_name VARCHAR(100) NOT NULL,
+    email VARCHAR(255) NOT NULL,
+    password_hash VARCHAR(255) NOT NULL,
+    phone_number VARCHAR(32) NULL,
+    secondary_email VARCHAR(255) NULL,
+    mailing_address TEXT NULL,
+    secondary_address TEXT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    role VARCHAR(32) NOT NULL DEFAULT 'customer',
+    created_at DATETIME(6) NOT NULL,
+    updated_at DATETIME(6) NOT NULL,
+    UNIQUE KEY users_email_key (email),
+    INDEX users_role_idx (role)
+) ENGINE = InnoDB;
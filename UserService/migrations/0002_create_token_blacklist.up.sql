@@ -0,0 +1,11 @@
+-- invalidate_token only supplies the token and its expiry, so id and created_at have defaults
+CREATE TABLE token_blacklist (
+    id BINARY(16) NOT NULL DEFAULT (UUID_TO_BIN(UUID())) PRIMARY KEY,
+    token VARCHAR(512) NOT NULL,
+    user_id BINARY(16) NULL,
+    expires_at DATETIME(6) NOT NULL,
+    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+    INDEX token_blacklist_token_idx (token, expires_at),
+    INDEX token_blacklist_expiry_idx (expires_at),
+    CONSTRAINT token_blacklist_user_fk FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) ENGINE = InnoDB;
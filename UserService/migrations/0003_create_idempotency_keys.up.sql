@@ -0,0 +1,10 @@
+CREATE TABLE idempotency_keys (
+    key_hash CHAR(64) PRIMARY KEY,
+    fingerprint CHAR(64) NOT NULL,
+    created_at DATETIME(6) NOT NULL,
+    expires_at DATETIME(6) NOT NULL,
+    response_status SMALLINT UNSIGNED NULL,
+    response_headers JSON NULL,
+    response_body LONGBLOB NULL,
+    INDEX idempotency_keys_expiry_idx (expires_at)
+) ENGINE = InnoDB;
@@ -0,0 +1,192 @@
+use sqlx::migrate::{AppliedMigration, Migrate, MigrateError, Migrator};
+use sqlx::MySqlPool;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Schema migrations, embedded in the binary at build time.
+/// CartService shares this database and its `_sqlx_migrations` table, numbering its own migrations
+/// from 1001; UserService keeps to versions below 1000 and ignores the ones CartService applied.
+pub static MIGRATOR: Migrator = Migrator {
+    ignore_missing: true,
+    ..sqlx::migrate!("./migrations")
+};
+
+/// Versions UserService numbers its migrations in; the rest of the table belongs to CartService
+const VERSIONS: std::ops::Range<i64> = 1..1000;
+
+/// Subcommands of `UserService migrate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrateCommand {
+    Up,     // Apply every pending migration
+    Down,   // Revert the most recently applied migration
+    Status, // List migrations and whether they have been applied
+}
+
+impl FromStr for MigrateCommand {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "up" => Ok(MigrateCommand::Up),
+            "down" => Ok(MigrateCommand::Down),
+            "status" => Ok(MigrateCommand::Status),
+            other => Err(format!("Unknown migrate command: {} (expected up, down or status)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    pub checksum_matches: bool, // False when an applied migration was edited afterwards
+}
+
+impl fmt::Display for MigrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match (self.applied, self.checksum_matches) {
+            (false, _) => "pending",
+            (true, true) => "applied",
+            (true, false) => "modified",
+        };
+        write!(f, "{:>4} {:<8} {}", self.version, state, self.description)
+    }
+}
+
+/// Reasons the schema check can fail, including the ways the schema can drift from the embedded migrations
+#[derive(Debug)]
+pub enum SchemaError {
+    Migrate(MigrateError),
+    Pending(Vec<i64>), // Migrations the database has not applied yet
+    Modified(i64),     // Applied migration whose SQL has changed since
+    Unknown(i64),      // Applied UserService migration this binary does not know about
+    Dirty(i64),        // Migration that failed partway through
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Migrate(err) => write!(f, "Failed to read migrations: {}", err),
+            SchemaError::Pending(versions) => write!(f, "Schema is behind, pending migrations: {:?}", versions),
+            SchemaError::Modified(version) => write!(f, "Migration {} was changed after it was applied", version),
+            SchemaError::Unknown(version) => write!(f, "Migration {} is applied but not known to this binary", version),
+            SchemaError::Dirty(version) => write!(f, "Migration {} failed partway and left the schema dirty", version),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<MigrateError> for SchemaError {
+    fn from(err: MigrateError) -> Self {
+        SchemaError::Migrate(err)
+    }
+}
+
+/// Apply every pending migration.
+pub async fn migrate_up(pool: &MySqlPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Revert the most recently applied migration, returning its version.
+pub async fn migrate_down(pool: &MySqlPool) -> Result<Option<i64>, MigrateError> {
+    let mut applied: Vec<i64> = applied_migrations(pool)
+        .await?
+        .0
+        .into_iter()
+        .map(|migration| migration.version)
+        .filter(|version| VERSIONS.contains(version))
+        .collect();
+    applied.sort_unstable();
+
+    let Some(latest) = applied.pop() else {
+        return Ok(None);
+    };
+    // Undo reverts everything above the target, so aim for the previous applied version
+    let target = applied.last().copied().unwrap_or(0);
+
+    MIGRATOR.undo(pool, target).await?;
+
+    Ok(Some(latest))
+}
+
+/// List the embedded migrations and whether each one has been applied.
+pub async fn migration_status(pool: &MySqlPool) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let (applied, _) = applied_migrations(pool).await?;
+    let applied: HashMap<i64, AppliedMigration> =
+        applied.into_iter().map(|migration| (migration.version, migration)).collect();
+
+    let statuses = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| {
+            let record = applied.get(&migration.version);
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                applied: record.is_some(),
+                checksum_matches: record.map_or(true, |record| record.checksum == migration.checksum),
+            }
+        })
+        .collect();
+
+    Ok(statuses)
+}
+
+/// Compare the database against the embedded migrations, reporting the first drift found.
+pub async fn check_schema(pool: &MySqlPool) -> Result<(), SchemaError> {
+    // Only UserService's own migrations are checked; CartService records its own in the same table
+    let (applied, dirty) = applied_migrations(pool).await?;
+    if let Some(version) = dirty.filter(|version| VERSIONS.contains(version)) {
+        return Err(SchemaError::Dirty(version));
+    }
+
+    // A newer UserService may have applied migrations this one does not know about
+    let unknown = applied
+        .iter()
+        .filter(|migration| VERSIONS.contains(&migration.version))
+        .find(|migration| MIGRATOR.iter().all(|known| known.version != migration.version));
+    if let Some(migration) = unknown {
+        return Err(SchemaError::Unknown(migration.version));
+    }
+
+    let statuses = migration_status(pool).await?;
+
+    if let Some(status) = statuses.iter().find(|status| status.applied && !status.checksum_matches) {
+        return Err(SchemaError::Modified(status.version));
+    }
+
+    let pending: Vec<i64> = statuses
+        .iter()
+        .filter(|status| !status.applied)
+        .map(|status| status.version)
+        .collect();
+    if !pending.is_empty() {
+        return Err(SchemaError::Pending(pending));
+    }
+
+    Ok(())
+}
+
+/// Read the applied migrations and any dirty version, creating the bookkeeping table if needed.
+async fn applied_migrations(pool: &MySqlPool) -> Result<(Vec<AppliedMigration>, Option<i64>), MigrateError> {
+    let mut conn = pool.acquire().await?;
+
+    conn.ensure_migrations_table().await?;
+    let dirty = conn.dirty_version().await?;
+    let applied = conn.list_applied_migrations().await?;
+
+    Ok((applied, dirty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_stay_clear_of_cart_service() {
+        assert!(MIGRATOR.iter().all(|migration| VERSIONS.contains(&migration.version)));
+    }
+}
@@ -1,10 +1,17 @@
 use sqlx::{MySql, Pool};
 use sqlx::mysql::MySqlPoolOptions;
+use crate::config::Config;
 
-pub async fn establish_connection(db_url: &str) -> Pool<MySql> {
+pub mod migrations;
+pub mod models;
+pub mod token_blacklist;
+
+/// Connect with the pool size and timeouts from the configuration
+pub async fn establish_connection(config: &Config) -> Result<Pool<MySql>, sqlx::Error> {
     MySqlPoolOptions::new()
-        .max_connections(5)
-        .connect(db_url)
+        .max_connections(config.db_max_connections)
+        .min_connections(config.db_min_connections)
+        .acquire_timeout(config.db_acquire_timeout())
+        .connect(&config.db_url)
         .await
-        .expect("Failed to connect to database")
 }
@@ -1,12 +1,31 @@
-use actix_web::{web, HttpResponse, Result};
-use crate::services::user_service::UserService;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use crate::services::UserService;
 use crate::utils::validation;
-use crate::models::{LoginRequest, LoginResponse, RegisterUserRequest};
+use crate::models::{EditUserRequest, LoginRequest, LoginResponse, RegisterUserRequest};
 use log::{error, info};
-use chrono::Utc;
-use sqlx::MySqlPool;
+use uuid::Uuid;
+
+// The JWT from the `Authorization: Bearer` header
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("Authorization")?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+// The user a request's token was issued to, or a 401 response when the token is missing or invalid
+async fn authenticated_user(req: &HttpRequest, user_service: &UserService) -> std::result::Result<Uuid, HttpResponse> {
+    let token = bearer_token(req).ok_or_else(|| HttpResponse::Unauthorized().json("Unauthorized"))?;
+
+    user_service.verify_token(token).await.map_err(|err| {
+        info!("Authentication failed: {}", err);
+        HttpResponse::Unauthorized().json("Unauthorized")
+    })
+}
 
 pub async fn login_user(
+    req: HttpRequest,
     user_service: web::Data<UserService>,
     credentials: web::Json<LoginRequest>,
 ) -> Result<HttpResponse> {
@@ -15,7 +34,16 @@ pub async fn login_user(
     }
 
     match user_service.authenticate_user(&credentials.email, &credentials.password).await {
-        Ok(token) => Ok(HttpResponse::Ok().json(LoginResponse { token })),
+        Ok(token) => {
+            // Merge the shopper's guest cart, if any, into their user cart
+            if let Some(cart_token) = req.headers().get("X-Cart-Token").and_then(|v| v.to_str().ok()) {
+                if let Err(err) = user_service.merge_guest_cart(&token, cart_token).await {
+                    error!("Guest cart merge failed: {}", err);
+                }
+            }
+
+            Ok(HttpResponse::Ok().json(LoginResponse { token }))
+        }
         Err(err) => {
             error!("Login failed: {}", err);
             Ok(HttpResponse::Unauthorized().json("Invalid email or password"))
@@ -55,10 +83,15 @@ pub async fn register_user(
 }
 
 pub async fn logout_user(
+    req: HttpRequest,
     user_service: web::Data<UserService>,
-    token: web::Header<String>, // JWT Token from the Authorization header
 ) -> Result<HttpResponse> {
-    match user_service.invalidate_token(&token.into_inner()).await {
+    if let Err(response) = authenticated_user(&req, &user_service).await {
+        return Ok(response);
+    }
+    let token = bearer_token(&req).unwrap_or_default();
+
+    match user_service.invalidate_token(token).await {
         Ok(_) => {
             info!("Token invalidated successfully");
             Ok(HttpResponse::Ok().json("Logout successful"))
@@ -71,14 +104,106 @@ pub async fn logout_user(
 }
 
 pub async fn authenticate_user(
+    req: HttpRequest,
     user_service: web::Data<UserService>,
-    token: web::Header<String>, // JWT Token from the Authorization header
 ) -> Result<HttpResponse> {
-    match user_service.verify_token(&token.into_inner()).await {
+    match authenticated_user(&req, &user_service).await {
         Ok(user_id) => Ok(HttpResponse::Ok().json(format!("Authenticated user: {}", user_id))),
+        Err(response) => Ok(response),
+    }
+}
+
+pub async fn deactivate_user(
+    req: HttpRequest,
+    user_service: web::Data<UserService>,
+) -> Result<HttpResponse> {
+    let user_id = match authenticated_user(&req, &user_service).await {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    match user_service.deactivate_user(user_id).await {
+        Ok(true) => Ok(HttpResponse::Ok().json("User deactivated successfully")),
+        Ok(false) => Ok(HttpResponse::NotFound().json("User not found")),
+        Err(err) => {
+            error!("Deactivation failed: {}", err);
+            Ok(HttpResponse::InternalServerError().json("Failed to deactivate user"))
+        }
+    }
+}
+
+pub async fn edit_user(
+    req: HttpRequest,
+    user_service: web::Data<UserService>,
+    changes: web::Json<EditUserRequest>,
+) -> Result<HttpResponse> {
+    let user_id = match authenticated_user(&req, &user_service).await {
+        Ok(user_id) => user_id,
+        Err(response) => return Ok(response),
+    };
+
+    match user_service
+        .update_user(
+            user_id,
+            changes.first_name.as_deref(),
+            changes.last_name.as_deref(),
+            changes.phone_number.as_deref(),
+            changes.mailing_address.as_deref(),
+        )
+        .await
+    {
+        Ok(Some(user)) => Ok(HttpResponse::Ok().json(user)),
+        Ok(None) => Ok(HttpResponse::NotFound().json("User not found")),
         Err(err) => {
-            info!("Authentication failed: {}", err);
-            Ok(HttpResponse::Unauthorized().json("Unauthorized"))
+            error!("User update failed: {}", err);
+            Ok(HttpResponse::InternalServerError().json("Failed to update user"))
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use serde_json::json;
+
+    use crate::services::testing::{database_pool, spawn_cart_service, user_service};
+
+    #[actix_web::test]
+    #[ignore = "needs a MySQL database in DATABASE_URL"]
+    async fn logging_in_merges_the_guest_cart() {
+        let (url, merges) = spawn_cart_service().await;
+        let service = web::Data::new(user_service(database_pool().await, Some(url)));
+        let email = format!("{}@example.com", Uuid::new_v4().simple());
+        service
+            .create_user("Guest", "Shopper", &email, "correct horse", None, None, None, None)
+            .await
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(service.clone())
+                .configure(crate::routes::user_routes),
+        )
+        .await;
+
+        let request = test::TestRequest::post()
+            .uri("/login")
+            .insert_header(("X-Cart-Token", "guest-cart-token"))
+            .set_json(json!({ "email": email, "password": "correct horse" }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let login: LoginResponse = test::read_body_json(response).await;
+        let user_id = service.verify_token(&login.token).await.unwrap();
+        assert_eq!(
+            *merges.lock().unwrap(),
+            vec![(
+                format!("/cart/{}/merge", user_id),
+                format!("Bearer {}", login.token),
+                json!({ "cart_token": "guest-cart-token" }),
+            )]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::MySqlPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::db::migrations;
+
+/// Dependencies checked by the readiness probe
+pub struct HealthCheck {
+    pub db_pool: MySqlPool,
+    pub timeout: Duration, // Applies to each check separately
+    pub stopping: AtomicBool, // Set on shutdown, before the server stops accepting connections
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CheckStatus {
+    Ok,
+    Failing,
+}
+
+#[derive(Debug, Serialize)]
+struct CheckResult {
+    status: CheckStatus,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    status: &'static str,
+    checks: BTreeMap<&'static str, CheckResult>,
+}
+
+/// Liveness: the process is up and serving requests
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness: the database answers, the schema matches the embedded migrations and shutdown
+/// has not begun. Responds with 503 and the failing checks otherwise.
+pub async fn readyz(health: web::Data<HealthCheck>) -> impl Responder {
+    if health.stopping.load(Ordering::Relaxed) {
+        let body = Readiness {
+            status: "shutting_down",
+            checks: BTreeMap::new(),
+        };
+        return HttpResponse::ServiceUnavailable().json(body);
+    }
+
+    let database = check(health.timeout, async {
+        sqlx::query("SELECT 1")
+            .execute(&health.db_pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    });
+    let migrations = check(health.timeout, async {
+        migrations::check_schema(&health.db_pool).await.map_err(|err| err.to_string())
+    });
+    let (database, migrations) = tokio::join!(database, migrations);
+
+    let mut checks = BTreeMap::new();
+    checks.insert("database", database);
+    checks.insert("migrations", migrations);
+
+    let ready = checks.values().all(|check| !matches!(check.status, CheckStatus::Failing));
+    if ready {
+        HttpResponse::Ok().json(Readiness { status: "ready", checks })
+    } else {
+        HttpResponse::ServiceUnavailable().json(Readiness { status: "not_ready", checks })
+    }
+}
+
+/// Run one check, failing it if it does not finish within `timeout`
+async fn check<F>(timeout: Duration, check: F) -> CheckResult
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Timed out after {}ms", timeout.as_millis())),
+    };
+
+    CheckResult {
+        status: if result.is_ok() { CheckStatus::Ok } else { CheckStatus::Failing },
+        latency_ms: started.elapsed().as_millis(),
+        error: result.err(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+
+    use crate::services::testing::unconnected_pool;
+
+    fn probes(stopping: bool) -> web::Data<HealthCheck> {
+        web::Data::new(HealthCheck {
+            db_pool: unconnected_pool(),
+            timeout: Duration::from_millis(500),
+            stopping: AtomicBool::new(stopping),
+        })
+    }
+
+    async fn probe(health: web::Data<HealthCheck>, path: &str) -> (StatusCode, serde_json::Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(health)
+                .route("/healthz", web::get().to(healthz))
+                .route("/readyz", web::get().to(readyz)),
+        )
+        .await;
+        let response = test::call_service(&app, test::TestRequest::get().uri(path).to_request()).await;
+        (response.status(), test::read_body_json(response).await)
+    }
+
+    #[actix_web::test]
+    async fn an_unreachable_database_fails_readiness_but_not_liveness() {
+        let (status, body) = probe(probes(false), "/readyz").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["checks"]["database"]["status"], "failing");
+        assert!(body["checks"]["database"]["error"].is_string());
+        assert_eq!(body["checks"]["migrations"]["status"], "failing");
+        assert_eq!(probe(probes(false), "/healthz").await.0, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn readiness_fails_once_shutdown_begins() {
+        let (status, body) = probe(probes(true), "/readyz").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "shutting_down");
+        assert!(body["checks"].as_object().unwrap().is_empty());
+    }
+}
@@ -1,34 +1,196 @@
-use actix_web::{web, App, HttpServer, HttpResponse};
-use sqlx::MySqlPool;
-use dotenvy::dotenv;
+use actix_web::{web, App, HttpServer};
 use std::env;
-use crate::services::user_service::UserService;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::config::{Args, Config, LogFormat};
+use crate::services::UserService;
 use uuid::Uuid; // Add import for Uuid
 use actix_web::error::{ErrorNotFound, ErrorInternalServerError};
+use crate::db::migrations::{self, MigrateCommand};
+use crate::health::{healthz, readyz, HealthCheck};
 
+mod config;
 mod db;
+mod handlers;
+mod health;
+mod middleware;
+mod models;
+mod routes;
 mod services;
+mod utils;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let args = Args::parse(env::args().skip(1)).map_err(invalid_input)?;
+    let config = config::get_config(&args).map_err(invalid_input)?;
+
+    // `UserService --print-config` shows the effective configuration and exits
+    if args.print_config {
+        print!("{}", config.redacted());
+        return Ok(());
+    }
+
+    init_logging(&config);
 
     // Create a database connection pool
-    let db_pool = MySqlPool::connect(&database_url)
-        .await
-        .expect("Failed to connect to the database");
+    let db_pool = db::establish_connection(&config).await.map_err(other_error)?;
+
+    // `UserService migrate up|down|status` manages the schema and exits
+    if args.command.first().map(String::as_str) == Some("migrate") {
+        let command = args
+            .command
+            .get(1)
+            .ok_or_else(|| invalid_input("Usage: UserService migrate <up|down|status>"))?
+            .parse::<MigrateCommand>()
+            .map_err(invalid_input)?;
+
+        match command {
+            MigrateCommand::Up => {
+                migrations::migrate_up(&db_pool).await.map_err(other_error)?;
+                println!("Schema is up to date");
+            }
+            MigrateCommand::Down => match migrations::migrate_down(&db_pool).await.map_err(other_error)? {
+                Some(version) => println!("Reverted migration {}", version),
+                None => println!("No migrations to revert"),
+            },
+            MigrateCommand::Status => {
+                for status in migrations::migration_status(&db_pool).await.map_err(other_error)? {
+                    println!("{}", status);
+                }
+            }
+        }
 
-    let user_service = web::Data::new(UserService { db_pool });
+        return Ok(());
+    }
 
-    HttpServer::new(move || {
+    if let Some(command) = args.command.first() {
+        return Err(invalid_input(format!("Unknown command: {}", command)));
+    }
+
+    // Refuse to serve when the schema has drifted from the embedded migrations
+    if config.schema_check {
+        migrations::check_schema(&db_pool).await.map_err(other_error)?;
+    }
+
+    let http_client = reqwest::Client::builder()
+        .timeout(config.upstream_timeout())
+        .build()
+        .map_err(other_error)?;
+
+    let user_service = web::Data::new(UserService {
+        db_pool: db_pool.clone(),
+        jwt_secret: config.jwt_secret.clone(),
+        jwt_expiry_secs: config.jwt_expiry_secs,
+        cart_service_url: config.cart_service_url.clone(),
+        http_client,
+        idempotency_ttl: chrono::Duration::seconds(config.idempotency_ttl_secs as i64),
+    });
+
+    let health = web::Data::new(HealthCheck {
+        db_pool: db_pool.clone(),
+        timeout: config.readiness_timeout(),
+        stopping: AtomicBool::new(false),
+    });
+    let readiness = health.clone();
+
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(user_service.clone()) // Inject service
+            .app_data(health.clone())
+            .route("/healthz", web::get().to(healthz)) // Liveness
+            .route("/readyz", web::get().to(readyz)) // Readiness, with a breakdown per dependency
             .route("/user/{id}", web::get().to(get_user)) // Correct route handler
+            .configure(routes::user_routes) // Login, registration and account routes
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
+    .client_request_timeout(config.request_timeout())
+    .shutdown_timeout(config.shutdown_timeout_secs)
+    // Actix force-stops on SIGINT, so both signals are handled below
+    .disable_signals();
+
+    if let Some(workers) = config.workers {
+        server = server.workers(workers);
+    }
+
+    log::info!("Server running on http://{}", config.server_address);
+    let server = server.bind(config.server_address)?.run();
+
+    // Fail readiness first so load balancers stop sending traffic, then after the pre-stop delay
+    // stop accepting connections and give in-flight requests until the shutdown timeout to finish
+    let handle = server.handle();
+    let pre_stop_delay = config.pre_stop_delay();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+        log::info!("Shutdown signal received, failing readiness for {}s", pre_stop_delay.as_secs());
+        readiness.stopping.store(true, Ordering::Relaxed);
+        tokio::time::sleep(pre_stop_delay).await;
+        log::info!("Draining in-flight requests");
+        handle.stop(true).await;
+    });
+
+    server.await?;
+
+    db_pool.close().await;
+    log::info!("Shutdown complete");
+
+    Ok(())
+}
+
+/// Resolve on the first SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            log::error!("Failed to listen for SIGINT: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                log::error!("Failed to listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Install the global logger in the configured format
+fn init_logging(config: &Config) {
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(&config.log_level);
+
+    if config.log_format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = serde_json::json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", line)
+        });
+    }
+
+    builder.init();
+}
+
+fn invalid_input<E: ToString>(err: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, err.to_string())
+}
+
+fn other_error<E: ToString>(err: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, err.to_string())
 }
 
 async fn get_user(
@@ -41,7 +203,7 @@ async fn get_user(
         Ok(Some(user)) => Ok(web::Json(user)),
         Ok(None) => Err(ErrorNotFound("User not found")), // Correct error handling
         Err(err) => {
-            eprintln!("Error fetching user: {:?}", err); // Log the error
+            log::error!("Error fetching user: {:?}", err); // Log the error
             Err(ErrorInternalServerError("Database error"))
         }
     }
@@ -0,0 +1,311 @@
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, Error, HttpResponse};
+use chrono::Utc;
+use futures::future::{ok, Ready};
+use futures::Future;
+use sha2::{Digest, Sha256};
+use sqlx::types::Json;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use tracing::error;
+use crate::services::UserService;
+
+/// Header clients use to make a mutating request safe to retry
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Replays the stored response for a repeated `Idempotency-Key`, and rejects a key
+/// reused with a different request body with 422.
+pub struct IdempotencyMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for IdempotencyMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = IdempotencyMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(IdempotencyMiddlewareService {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct IdempotencyMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+enum Claim {
+    Acquired,
+    Replay(HttpResponse),
+    Mismatch,
+    InFlight,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotencyMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, ctx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let is_mutating = matches!(
+                *req.method(),
+                Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+            );
+            let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+                Some(key) if is_mutating => key.to_str().unwrap_or_default().to_string(),
+                _ => return Ok(service.call(req).await?.map_into_boxed_body()),
+            };
+            let user_service = match req.app_data::<web::Data<UserService>>() {
+                Some(user_service) => user_service.clone(),
+                None => return Ok(service.call(req).await?.map_into_boxed_body()),
+            };
+
+            // Buffer the body so it can be fingerprinted, then hand it back to the handler
+            let body = req.extract::<web::Bytes>().await?;
+            req.set_payload(Payload::from(body.clone()));
+
+            // Keys are scoped to the caller and the endpoint so they cannot collide across users
+            let authorization = req
+                .headers()
+                .get("Authorization")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let key_hash = sha256_hex(&[
+                key.as_bytes(),
+                req.method().as_str().as_bytes(),
+                req.path().as_bytes(),
+                authorization.as_bytes(),
+            ]);
+            let fingerprint = sha256_hex(&[&body]);
+
+            match claim_key(&user_service, &key_hash, &fingerprint).await {
+                Ok(Claim::Acquired) => {}
+                Ok(Claim::Replay(response)) => return Ok(req.into_response(response)),
+                Ok(Claim::Mismatch) => {
+                    return Ok(req.into_response(
+                        HttpResponse::UnprocessableEntity()
+                            .json("Idempotency-Key was already used with a different request"),
+                    ))
+                }
+                Ok(Claim::InFlight) => {
+                    return Ok(req.into_response(
+                        HttpResponse::Conflict()
+                            .json("A request with this Idempotency-Key is still being processed"),
+                    ))
+                }
+                Err(err) => {
+                    error!("Idempotency lookup failed: {:?}", err);
+                    return Ok(req.into_response(
+                        HttpResponse::InternalServerError().json("Internal server error"),
+                    ));
+                }
+            }
+
+            let response = service.call(req).await?;
+            let (req, response) = response.into_parts();
+            let (response, body) = response.into_parts();
+            let body = body::to_bytes(body)
+                .await
+                .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to read response body"))?;
+
+            let headers: Vec<(String, String)> = response
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                .collect();
+            if let Err(err) =
+                store_response(&user_service, &key_hash, response.status(), headers, &body).await
+            {
+                error!("Failed to store idempotent response: {:?}", err);
+            }
+
+            Ok(ServiceResponse::new(req, response.set_body(body).map_into_boxed_body()))
+        })
+    }
+}
+
+fn sha256_hex(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Reserve the key for this request, or find the earlier request that used it.
+async fn claim_key(
+    user_service: &UserService,
+    key_hash: &str,
+    fingerprint: &str,
+) -> Result<Claim, sqlx::Error> {
+    let now = Utc::now();
+
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE expires_at <= ?
+        "#,
+        now
+    )
+    .execute(&user_service.db_pool)
+    .await?;
+
+    let inserted = sqlx::query!(
+        r#"
+        INSERT IGNORE INTO idempotency_keys (key_hash, fingerprint, created_at, expires_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+        key_hash,
+        fingerprint,
+        now,
+        now + user_service.idempotency_ttl
+    )
+    .execute(&user_service.db_pool)
+    .await?
+    .rows_affected();
+
+    if inserted == 1 {
+        return Ok(Claim::Acquired);
+    }
+
+    let stored = sqlx::query!(
+        r#"
+        SELECT fingerprint, response_status, response_headers as "response_headers: Json<Vec<(String, String)>>", response_body
+        FROM idempotency_keys
+        WHERE key_hash = ?
+        "#,
+        key_hash
+    )
+    .fetch_one(&user_service.db_pool)
+    .await?;
+
+    if stored.fingerprint != fingerprint {
+        return Ok(Claim::Mismatch);
+    }
+
+    let (status, headers, body) = match (stored.response_status, stored.response_headers, stored.response_body) {
+        (Some(status), Some(headers), Some(body)) => (status, headers.0, body),
+        _ => return Ok(Claim::InFlight),
+    };
+
+    let mut response = HttpResponse::build(StatusCode::from_u16(status).unwrap_or(StatusCode::OK));
+    for header in headers {
+        response.append_header(header);
+    }
+    response.insert_header(("Idempotent-Replayed", "true"));
+
+    Ok(Claim::Replay(response.body(body)))
+}
+
+/// Save the response so retries can replay it; server errors release the key instead.
+async fn store_response(
+    user_service: &UserService,
+    key_hash: &str,
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    body: &web::Bytes,
+) -> Result<(), sqlx::Error> {
+    if status.is_server_error() {
+        sqlx::query!(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE key_hash = ?
+            "#,
+            key_hash
+        )
+        .execute(&user_service.db_pool)
+        .await?;
+
+        return Ok(());
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE idempotency_keys
+        SET response_status = ?, response_headers = ?, response_body = ?
+        WHERE key_hash = ?
+        "#,
+        status.as_u16(),
+        Json(headers),
+        body.as_ref(),
+        key_hash
+    )
+    .execute(&user_service.db_pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use crate::services::testing::{database_pool, user_service};
+
+    #[actix_web::test]
+    #[ignore = "needs a MySQL database in DATABASE_URL"]
+    async fn repeated_keys_replay_the_first_response() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let user_service = web::Data::new(user_service(database_pool().await, None));
+        let app = test::init_service(
+            App::new().app_data(user_service).service(
+                web::resource("/register")
+                    .wrap(IdempotencyMiddleware)
+                    .route(web::post().to(move |_body: web::Bytes| {
+                        let call = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                        async move { HttpResponse::Created().json(call) }
+                    })),
+            ),
+        )
+        .await;
+        let key = uuid::Uuid::new_v4().to_string();
+        let request = |body: &'static str| {
+            test::TestRequest::post()
+                .uri("/register")
+                .insert_header((IDEMPOTENCY_KEY_HEADER, key.clone()))
+                .set_payload(body)
+                .to_request()
+        };
+
+        let first = test::call_service(&app, request(r#"{"email":"a@example.com"}"#)).await;
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert!(first.headers().get("Idempotent-Replayed").is_none());
+        let first_body = test::read_body(first).await;
+
+        let replay = test::call_service(&app, request(r#"{"email":"a@example.com"}"#)).await;
+        assert_eq!(replay.status(), StatusCode::CREATED);
+        assert_eq!(replay.headers().get("Idempotent-Replayed").unwrap(), "true");
+        assert_eq!(test::read_body(replay).await, first_body);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let reused = test::call_service(&app, request(r#"{"email":"b@example.com"}"#)).await;
+        assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,8 +1,12 @@
+pub mod idempotency;
+
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
 use actix_web::Error;
+use chrono::Utc;
 use futures::future::{ok, Ready};
 use futures::Future;
 use std::pin::Pin;
+use std::rc::Rc;
 use std::task::{Context, Poll};
 use tracing::info;
 
@@ -10,7 +14,7 @@ pub struct LoggerMiddleware;
 
 impl<S, B> Transform<S, ServiceRequest> for LoggerMiddleware
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -18,19 +22,22 @@ where
     type Error = Error;
     type Transform = LoggerMiddlewareService<S>;
     type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
-    fn new_transform(&self, service: S) -> Self::Transform {
-        LoggerMiddlewareService { service }
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(LoggerMiddlewareService {
+            service: Rc::new(service),
+        })
     }
 }
 
 pub struct LoggerMiddlewareService<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for LoggerMiddlewareService<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub token: String, // JWT to send as `Authorization: Bearer <token>`
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterUserRequest {
+    pub first_name: String,
+    pub last_name: String,
+    pub email: String,
+    pub password: String,
+    pub phone_number: Option<String>,
+    pub secondary_email: Option<String>,
+    pub mailing_address: Option<String>,
+    pub secondary_address: Option<String>,
+}
+
+/// Fields to change on the signed-in user; fields that are not set keep their value
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditUserRequest {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub phone_number: Option<String>,
+    pub mailing_address: Option<String>,
+}
@@ -3,11 +3,17 @@ use uuid::Uuid;
 use chrono::Utc;
 use crate::db::models::User;
 use crate::utils::{password, validation, jwt};
-use actix_web::web;
+
+#[cfg(test)]
+pub mod testing;
 
 pub struct UserService {
     pub db_pool: MySqlPool,
     pub jwt_secret: String, // Secret key for JWT generation
+    pub jwt_expiry_secs: u64, // Lifetime of issued tokens
+    pub cart_service_url: Option<String>, // Base URL of CartService, used to merge guest carts on login
+    pub http_client: reqwest::Client,
+    pub idempotency_ttl: chrono::Duration, // How long responses to Idempotency-Key requests are kept
 }
 
 impl UserService {
@@ -107,7 +113,7 @@ impl UserService {
         }
 
         // Generate a JWT token
-        let token = jwt::generate_token(&user.id.to_string(), &self.jwt_secret, 3600)
+        let token = jwt::generate_token(&user.id.to_string(), &self.jwt_secret, self.jwt_expiry_secs)
             .map_err(|e| format!("Failed to generate token: {:?}", e))?;
 
         Ok(token)
@@ -241,4 +247,65 @@ impl UserService {
 
         Ok(user_id)
     }
+
+    /// Merge a guest cart into the cart of the user who owns the given JWT token.
+    pub async fn merge_guest_cart(&self, token: &str, cart_token: &str) -> Result<(), String> {
+        let cart_service_url = match &self.cart_service_url {
+            Some(url) => url,
+            None => return Ok(()), // Guest cart merging is disabled
+        };
+
+        let claims = jwt::validate_token(token, &self.jwt_secret)
+            .map_err(|e| format!("Failed to validate token: {:?}", e))?;
+
+        let response = self
+            .http_client
+            .post(format!("{}/cart/{}/merge", cart_service_url.trim_end_matches('/'), claims.sub))
+            .bearer_auth(token)
+            .json(&serde_json::json!({ "cart_token": cart_token }))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach cart service: {:?}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Cart service rejected merge: {}", response.status()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::services::testing::{spawn_cart_service, unconnected_pool, user_service};
+
+    #[actix_web::test]
+    async fn guest_carts_are_merged_into_the_token_owners_cart() {
+        let (url, merges) = spawn_cart_service().await;
+        let service = user_service(unconnected_pool(), Some(url));
+        let user_id = Uuid::new_v4();
+        let token = jwt::generate_token(&user_id.to_string(), &service.jwt_secret, 60).unwrap();
+
+        service.merge_guest_cart(&token, "guest-cart-token").await.unwrap();
+
+        assert_eq!(
+            *merges.lock().unwrap(),
+            vec![(
+                format!("/cart/{}/merge", user_id),
+                format!("Bearer {}", token),
+                json!({ "cart_token": "guest-cart-token" }),
+            )]
+        );
+    }
+
+    #[actix_web::test]
+    async fn merging_is_skipped_without_a_cart_service() {
+        let service = user_service(unconnected_pool(), None);
+        let token = jwt::generate_token(&Uuid::new_v4().to_string(), &service.jwt_secret, 60).unwrap();
+
+        assert!(service.merge_guest_cart(&token, "guest-cart-token").await.is_ok());
+    }
 }
@@ -0,0 +1,70 @@
+//! Fixtures shared by the UserService tests: a `UserService` over a given pool, and a
+//! stand-in for the CartService merge route.
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use sqlx::MySqlPool;
+use std::sync::{Arc, Mutex};
+
+use super::UserService;
+use crate::db::migrations;
+
+/// Merge requests the stub CartService received: path, `Authorization` header and body
+pub type MergeRequests = Arc<Mutex<Vec<(String, String, serde_json::Value)>>>;
+
+pub fn user_service(db_pool: MySqlPool, cart_service_url: Option<String>) -> UserService {
+    UserService {
+        db_pool,
+        jwt_secret: "test-secret".to_string(),
+        jwt_expiry_secs: 3600,
+        cart_service_url,
+        http_client: reqwest::Client::new(),
+        idempotency_ttl: chrono::Duration::minutes(5),
+    }
+}
+
+/// A pool that never connects, for tests that do not reach the database
+pub fn unconnected_pool() -> MySqlPool {
+    MySqlPool::connect_lazy("mysql://localhost/unused").unwrap()
+}
+
+/// The database in `DATABASE_URL`, migrated to the latest schema
+pub async fn database_pool() -> MySqlPool {
+    let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+    let db_pool = MySqlPool::connect(&db_url).await.unwrap();
+    migrations::migrate_up(&db_pool).await.unwrap();
+    db_pool
+}
+
+/// Serve `POST /cart/{user_id}/merge` like CartService, recording each request; returns the base URL
+pub async fn spawn_cart_service() -> (String, MergeRequests) {
+    let merges = MergeRequests::default();
+    let recorded = merges.clone();
+
+    let server = HttpServer::new(move || {
+        let recorded = recorded.clone();
+        App::new().route(
+            "/cart/{user_id}/merge",
+            web::post().to(move |req: HttpRequest, body: web::Json<serde_json::Value>| {
+                let authorization = req
+                    .headers()
+                    .get("Authorization")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((req.path().to_string(), authorization, body.into_inner()));
+                async { HttpResponse::Ok().json("Guest cart merged") }
+            }),
+        )
+    })
+    .workers(1)
+    .disable_signals()
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+
+    let url = format!("http://{}", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+    (url, merges)
+}